@@ -0,0 +1,514 @@
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use crate::fsutil::{self, ReadError};
+use crate::sync_backend::{self, SyncBackend};
+use crate::{book_id, icloud};
+
+static ANNOTATIONS_LOCK: Mutex<()> = Mutex::new(());
+static ID_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnnotationKind {
+    Highlight,
+    Underline,
+    Note,
+    FreeText,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Annotation {
+    #[serde(default)]
+    pub id: String,
+    pub hash: String,
+    pub kind: AnnotationKind,
+    pub page: u32,
+    /// Regions in PDF user space, eight numbers per quad in PDF `QuadPoints` order
+    /// (x1 y1 x2 y2 x3 y3 x4 y4). Notes and free text use a single quad.
+    #[serde(default)]
+    pub quads: Vec<[f64; 8]>,
+    /// Text under the selection, empty for sticky notes and free text.
+    #[serde(default)]
+    pub text: String,
+    /// Note body for sticky notes, free text, or a comment attached to a highlight.
+    #[serde(default)]
+    pub note: String,
+    #[serde(default = "default_color")]
+    pub color: String,
+    #[serde(default)]
+    pub created_at: String,
+    #[serde(default)]
+    pub updated_at: String,
+    /// Tombstone so deletions survive a merge with a device that still has the annotation.
+    #[serde(default)]
+    pub deleted: bool,
+}
+
+fn default_color() -> String {
+    "#ffd400".to_string()
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+struct AnnotationFile {
+    hash: String,
+    annotations: Vec<Annotation>,
+}
+
+fn now() -> String {
+    chrono::Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string()
+}
+
+fn new_id() -> String {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(ID_COUNTER.fetch_add(1, Ordering::Relaxed));
+    format!(
+        "{:012x}-{:016x}",
+        chrono::Utc::now().timestamp_millis(),
+        hasher.finish()
+    )
+}
+
+fn local_annotations_file(hash: &str) -> PathBuf {
+    icloud::get_local_annotations_dir().join(format!("{}.json", hash))
+}
+
+fn central_annotations_path(hash: &str) -> String {
+    format!("Annotations/{}.json", hash)
+}
+
+fn read_annotations_file(path: &Path) -> Result<Option<AnnotationFile>, String> {
+    fsutil::read_with_fallback(path, |data| serde_json::from_str::<AnnotationFile>(data)).map_err(|e| match e {
+        ReadError::Io(e) => format!("read_annotations_failed|detail={}", e),
+        ReadError::Parse(e) => format!("parse_annotations_failed|detail={}", e),
+    })
+}
+
+fn write_annotations_file(path: &Path, file: &AnnotationFile) -> Result<(), String> {
+    let data = serde_json::to_string_pretty(file)
+        .map_err(|e| format!("serialize_annotations_failed|detail={}", e))?;
+    fsutil::write_atomic(path, data.as_bytes(), true)
+        .map_err(|e| format!("write_annotations_failed|detail={}", e))
+}
+
+fn load_local(hash: &str) -> Result<AnnotationFile, String> {
+    Ok(read_annotations_file(&local_annotations_file(hash))?.unwrap_or_else(|| AnnotationFile {
+        hash: hash.to_string(),
+        annotations: Vec::new(),
+    }))
+}
+
+/// Whether `a` replaces `b` (same id) in a merge: the later `updated_at` wins, then a
+/// tombstone. Remaining ties are broken on the whole annotation, so every device settles on
+/// the same winner whatever field was edited.
+fn supersedes(a: &Annotation, b: &Annotation) -> bool {
+    let content = |x: &Annotation| serde_json::to_string(x).unwrap_or_default();
+    (&a.updated_at, a.deleted)
+        .cmp(&(&b.updated_at, b.deleted))
+        .then_with(|| content(a).cmp(&content(b)))
+        .is_gt()
+}
+
+/// Union of both sets by id; for the same id the later `updated_at` wins, so edits and
+/// tombstones from either side are kept.
+fn merge(local: &[Annotation], central: &[Annotation]) -> Vec<Annotation> {
+    let mut by_id: HashMap<&str, &Annotation> = HashMap::new();
+    for a in local.iter().chain(central.iter()) {
+        match by_id.get(a.id.as_str()) {
+            Some(existing) if !supersedes(a, existing) => {}
+            _ => {
+                by_id.insert(a.id.as_str(), a);
+            }
+        }
+    }
+    let mut merged: Vec<Annotation> = by_id.into_values().cloned().collect();
+    sort_annotations(&mut merged);
+    merged
+}
+
+fn sort_annotations(annotations: &mut [Annotation]) {
+    annotations.sort_by(|a, b| {
+        a.page
+            .cmp(&b.page)
+            .then_with(|| a.created_at.cmp(&b.created_at))
+            .then_with(|| a.id.cmp(&b.id))
+    });
+}
+
+fn visible(file: AnnotationFile) -> Vec<Annotation> {
+    file.annotations.into_iter().filter(|a| !a.deleted).collect()
+}
+
+/// Central sidecar stored under `id`, with every annotation attributed to `hash`.
+fn read_central(backend: &dyn SyncBackend, id: &str, hash: &str) -> Result<Option<AnnotationFile>, String> {
+    let file: Option<AnnotationFile> = sync_backend::read_json(backend, &central_annotations_path(id))
+        .map_err(|e| format!("read_annotations_failed|detail={}", e))?;
+    Ok(file.map(|f| with_hash(f, hash)))
+}
+
+fn with_hash(file: AnnotationFile, hash: &str) -> AnnotationFile {
+    AnnotationFile {
+        hash: hash.to_string(),
+        annotations: file
+            .annotations
+            .into_iter()
+            .map(|a| Annotation {
+                hash: hash.to_string(),
+                ..a
+            })
+            .collect(),
+    }
+}
+
+/// Merge local and central sidecars. Returns Some(annotations) if central brought changes.
+/// Edits older devices make under the book's short ID are merged in, and the central copy is
+/// mirrored there while such devices are around (`book_id::mirror_short_ids`).
+fn sync_inner(hash: &str) -> Result<Option<Vec<Annotation>>, String> {
+    if book_id::is_provisional(hash) {
+        return Ok(None);
+    }
+    let Some(backend) = sync_backend::current() else {
+        return Ok(None);
+    };
+    let backend = backend.as_ref();
+    let alias = book_id::short_alias(hash);
+
+    let local = read_annotations_file(&local_annotations_file(hash))?;
+    let central = read_central(backend, hash, hash)?;
+    let aliased = match alias {
+        Some(short) => read_central(backend, short, hash)?,
+        None => None,
+    };
+    if local.is_none() && central.is_none() && aliased.is_none() {
+        return Ok(None);
+    }
+
+    let merged = AnnotationFile {
+        hash: hash.to_string(),
+        annotations: [&local, &central, &aliased]
+            .into_iter()
+            .flatten()
+            .fold(Vec::new(), |acc, f| merge(&acc, &f.annotations)),
+    };
+    if central.as_ref().map(|c| &c.annotations) != Some(&merged.annotations) {
+        sync_backend::write_json(backend, &central_annotations_path(hash), &merged)?;
+    }
+    if let Some(short) = alias.filter(|_| book_id::mirror_short_ids()) {
+        if aliased.as_ref().map(|c| &c.annotations) != Some(&merged.annotations) {
+            sync_backend::write_json(backend, &central_annotations_path(short), &with_hash(merged.clone(), short))?;
+        }
+    }
+
+    let local_annotations = local.map(|l| l.annotations);
+    if local_annotations.as_ref() == Some(&merged.annotations) {
+        return Ok(None);
+    }
+    if let Some(l) = &local_annotations {
+        log::info!(
+            "sync annotations: hash={}, merged {} local + central -> {}",
+            &hash[..8.min(hash.len())],
+            l.len(),
+            merged.annotations.len()
+        );
+    }
+    write_annotations_file(&local_annotations_file(hash), &merged)?;
+    Ok(Some(visible(merged)))
+}
+
+/// Sync with central. Returns Some(annotations) if the visible set changed.
+pub fn sync(hash: &str) -> Result<Option<Vec<Annotation>>, String> {
+    let hash = &book_id::current(hash);
+    let _guard = ANNOTATIONS_LOCK.lock().unwrap();
+    sync_inner(hash)
+}
+
+/// Load annotations for a book (sync first), without tombstones.
+pub fn list(hash: &str) -> Result<Vec<Annotation>, String> {
+    let hash = &book_id::current(hash);
+    let _guard = ANNOTATIONS_LOCK.lock().unwrap();
+    if let Err(e) = sync_inner(hash) {
+        log::warn!("list annotations: sync failed for {}: {}", hash, e);
+    }
+    Ok(visible(load_local(hash)?))
+}
+
+fn validate(annotation: &Annotation) -> Result<(), String> {
+    if annotation.hash.is_empty() {
+        return Err("annotation_hash_missing".to_string());
+    }
+    if annotation.page == 0 {
+        return Err("annotation_invalid_page".to_string());
+    }
+    if annotation.quads.is_empty() {
+        return Err("annotation_region_missing".to_string());
+    }
+    Ok(())
+}
+
+/// Create a new annotation. The id and timestamps are assigned here.
+pub fn add(annotation: &Annotation) -> Result<Annotation, String> {
+    let annotation = &Annotation {
+        hash: book_id::current(&annotation.hash),
+        ..annotation.clone()
+    };
+    validate(annotation)?;
+    let _guard = ANNOTATIONS_LOCK.lock().unwrap();
+    let mut file = load_local(&annotation.hash)?;
+    let mut created = annotation.clone();
+    created.id = new_id();
+    created.created_at = now();
+    created.updated_at = created.created_at.clone();
+    created.deleted = false;
+    file.annotations.push(created.clone());
+    sort_annotations(&mut file.annotations);
+    write_annotations_file(&local_annotations_file(&annotation.hash), &file)?;
+    let _ = sync_inner(&annotation.hash);
+    Ok(created)
+}
+
+/// Replace an existing annotation's content, keeping its id and creation time.
+pub fn update(annotation: &Annotation) -> Result<Annotation, String> {
+    let annotation = &Annotation {
+        hash: book_id::current(&annotation.hash),
+        ..annotation.clone()
+    };
+    validate(annotation)?;
+    let _guard = ANNOTATIONS_LOCK.lock().unwrap();
+    let mut file = load_local(&annotation.hash)?;
+    let existing = file
+        .annotations
+        .iter_mut()
+        .find(|a| a.id == annotation.id && !a.deleted)
+        .ok_or_else(|| format!("annotation_not_found|id={}", annotation.id))?;
+    let created_at = existing.created_at.clone();
+    *existing = annotation.clone();
+    existing.created_at = created_at;
+    existing.updated_at = now();
+    existing.deleted = false;
+    let updated = existing.clone();
+    sort_annotations(&mut file.annotations);
+    write_annotations_file(&local_annotations_file(&annotation.hash), &file)?;
+    let _ = sync_inner(&annotation.hash);
+    Ok(updated)
+}
+
+/// Mark an annotation as deleted. The tombstone is kept so the deletion syncs.
+pub fn remove(hash: &str, id: &str) -> Result<(), String> {
+    let hash = &book_id::current(hash);
+    let _guard = ANNOTATIONS_LOCK.lock().unwrap();
+    let mut file = load_local(hash)?;
+    let existing = file
+        .annotations
+        .iter_mut()
+        .find(|a| a.id == id && !a.deleted)
+        .ok_or_else(|| format!("annotation_not_found|id={}", id))?;
+    existing.deleted = true;
+    existing.updated_at = now();
+    write_annotations_file(&local_annotations_file(hash), &file)?;
+    let _ = sync_inner(hash);
+    Ok(())
+}
+
+/// Whether this device has an annotation sidecar for a hash.
+pub fn has_local(hash: &str) -> bool {
+    local_annotations_file(hash).exists()
+}
+
+/// Move this device's annotations from an old edition of a book to a new one, mapping pages.
+/// Ids are kept, so devices migrating the same book independently converge on merge.
+/// Returns how many visible annotations were moved.
+pub fn migrate(old: &str, new: &str, map_page: &dyn Fn(u32) -> u32) -> Result<usize, String> {
+    let _guard = ANNOTATIONS_LOCK.lock().unwrap();
+    // Pull in what other devices added to the old edition first
+    if let Err(e) = sync_inner(old) {
+        log::warn!("migrate annotations: sync failed for {}: {}", old, e);
+    }
+    let old_file = local_annotations_file(old);
+    let Some(previous) = read_annotations_file(&old_file)? else {
+        return Ok(0);
+    };
+    let moved: Vec<Annotation> = previous
+        .annotations
+        .into_iter()
+        .map(|a| Annotation {
+            hash: new.to_string(),
+            page: map_page(a.page),
+            ..a
+        })
+        .collect();
+    let count = moved.iter().filter(|a| !a.deleted).count();
+
+    let mut current = load_local(new)?;
+    current.annotations = merge(&current.annotations, &moved);
+    write_annotations_file(&local_annotations_file(new), &current)?;
+    for path in [fsutil::backup_path(&old_file), old_file] {
+        let _ = std::fs::remove_file(&path);
+    }
+    if let Err(e) = sync_inner(new) {
+        log::warn!("migrate annotations: sync failed for {}: {}", new, e);
+    }
+    Ok(count)
+}
+
+/// Delete both local and central annotation sidecars for a hash.
+pub fn delete(hash: &str) {
+    delete_local(hash);
+    if let Some(backend) = sync_backend::current() {
+        delete_central(backend.as_ref(), hash);
+    }
+}
+
+/// Delete this device's sidecar (and its backup) for a hash.
+pub fn delete_local(hash: &str) {
+    let _guard = ANNOTATIONS_LOCK.lock().unwrap();
+    let local = local_annotations_file(hash);
+    for path in [fsutil::backup_path(&local), local] {
+        if path.exists() {
+            let _ = std::fs::remove_file(&path);
+        }
+    }
+}
+
+/// Delete the central sidecar for a hash, including the copy under its short ID.
+pub fn delete_central(backend: &dyn SyncBackend, hash: &str) {
+    let _guard = ANNOTATIONS_LOCK.lock().unwrap();
+    for id in std::iter::once(hash).chain(book_id::short_alias(hash)) {
+        if let Err(e) = backend.remove(&central_annotations_path(id)) {
+            log::warn!("delete annotations: failed to remove central for {}: {}", id, e);
+        }
+    }
+}
+
+/// Hashes with a sidecar on this device.
+pub fn local_hashes() -> Vec<String> {
+    std::fs::read_dir(icloud::get_local_annotations_dir())
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter_map(|e| e.file_name().to_str()?.strip_suffix(".json").map(str::to_string))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Hashes with a central sidecar.
+pub fn central_hashes(backend: &dyn SyncBackend) -> Result<Vec<String>, String> {
+    Ok(backend
+        .list("Annotations")?
+        .iter()
+        .filter_map(|name| name.strip_suffix(".json").map(str::to_string))
+        .collect())
+}
+
+/// Most recent annotation change for a hash, local or (if given) central. Tombstones count:
+/// deleting an annotation is activity too.
+pub fn last_changed(hash: &str, backend: Option<&dyn SyncBackend>) -> Option<chrono::DateTime<chrono::Utc>> {
+    let _guard = ANNOTATIONS_LOCK.lock().unwrap();
+    let mut files: Vec<AnnotationFile> = read_annotations_file(&local_annotations_file(hash))
+        .ok()
+        .flatten()
+        .into_iter()
+        .collect();
+    if let Some(backend) = backend {
+        files.extend(read_central(backend, hash, hash).ok().flatten());
+    }
+    files
+        .iter()
+        .flat_map(|f| &f.annotations)
+        .flat_map(|a| [&a.updated_at, &a.created_at])
+        .filter_map(|t| chrono::DateTime::parse_from_rfc3339(t).ok())
+        .map(|t| t.with_timezone(&chrono::Utc))
+        .max()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn annotation(id: &str, page: u32, updated_at: &str) -> Annotation {
+        Annotation {
+            id: id.to_string(),
+            hash: "a".repeat(64),
+            kind: AnnotationKind::Highlight,
+            page,
+            quads: vec![[0.0; 8]],
+            text: String::new(),
+            note: String::new(),
+            color: default_color(),
+            created_at: "2026-01-01T00:00:00.000Z".to_string(),
+            updated_at: updated_at.to_string(),
+            deleted: false,
+        }
+    }
+
+    fn ids(annotations: &[Annotation]) -> Vec<&str> {
+        annotations.iter().map(|a| a.id.as_str()).collect()
+    }
+
+    #[test]
+    fn merge_keeps_both_sides() {
+        let local = [annotation("b", 2, "2026-01-01T00:00:00.000Z")];
+        let central = [annotation("a", 1, "2026-01-01T00:00:00.000Z")];
+        assert_eq!(ids(&merge(&local, &central)), ["a", "b"]);
+    }
+
+    #[test]
+    fn merge_later_edit_wins() {
+        let mut older = annotation("a", 1, "2026-01-01T00:00:00.000Z");
+        older.note = "old".to_string();
+        let mut newer = annotation("a", 1, "2026-01-02T00:00:00.000Z");
+        newer.note = "new".to_string();
+        for merged in [merge(&[older.clone()], &[newer.clone()]), merge(&[newer], &[older])] {
+            assert_eq!(merged.len(), 1);
+            assert_eq!(merged[0].note, "new");
+        }
+
+        // Two devices recolor it in the same millisecond: both keep the same one
+        let mut red = annotation("a", 1, "2026-01-03T00:00:00.000Z");
+        red.color = "#ff0000".to_string();
+        let mut blue = red.clone();
+        blue.color = "#0000ff".to_string();
+        let on_red = merge(std::slice::from_ref(&red), std::slice::from_ref(&blue));
+        let on_blue = merge(std::slice::from_ref(&blue), std::slice::from_ref(&red));
+        assert_eq!(on_red, on_blue);
+        assert_eq!(on_red.len(), 1);
+
+        // Likewise for a moved region
+        let mut moved = red.clone();
+        moved.quads = vec![[1.0; 8]];
+        assert_eq!(
+            merge(std::slice::from_ref(&red), std::slice::from_ref(&moved)),
+            merge(std::slice::from_ref(&moved), std::slice::from_ref(&red))
+        );
+    }
+
+    #[test]
+    fn merge_keeps_later_tombstones() {
+        let live = annotation("a", 1, "2026-01-01T00:00:00.000Z");
+        let mut deleted = annotation("a", 1, "2026-01-02T00:00:00.000Z");
+        deleted.deleted = true;
+        let merged = merge(std::slice::from_ref(&live), std::slice::from_ref(&deleted));
+        assert!(merged[0].deleted);
+        assert!(visible(AnnotationFile { hash: live.hash.clone(), annotations: merged }).is_empty());
+
+        // Edited again after the deletion elsewhere
+        let mut revived = live;
+        revived.updated_at = "2026-01-03T00:00:00.000Z".to_string();
+        assert!(!merge(&[deleted], &[revived])[0].deleted);
+    }
+
+    #[test]
+    fn merge_ties_settle_the_same_way_on_every_device() {
+        let mut kept = annotation("a", 1, "2026-01-01T00:00:00.000Z");
+        kept.deleted = true;
+        let mut other = annotation("a", 1, "2026-01-01T00:00:00.000Z");
+        other.note = "edited".to_string();
+        assert_eq!(merge(&[kept.clone()], &[other.clone()]), merge(&[other], &[kept.clone()]));
+        assert_eq!(merge(&[kept.clone()], &[]), [kept]);
+    }
+}
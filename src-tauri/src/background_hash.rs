@@ -0,0 +1,71 @@
+use std::collections::{HashSet, VecDeque};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tauri::Emitter;
+
+use crate::{book_id, pdf_info};
+
+struct Job {
+    path: PathBuf,
+    /// Sampled ID the book is shown under until its full hash is known.
+    provisional: String,
+}
+
+#[derive(Default)]
+struct Queue {
+    jobs: VecDeque<Job>,
+    queued: HashSet<PathBuf>,
+}
+
+fn queue() -> &'static (Mutex<Queue>, Condvar) {
+    static QUEUE: OnceLock<(Mutex<Queue>, Condvar)> = OnceLock::new();
+    QUEUE.get_or_init(|| (Mutex::new(Queue::default()), Condvar::new()))
+}
+
+/// Ask for the full hash of a file that was given a provisional ID. Requests for a file
+/// already waiting are ignored.
+pub fn enqueue(path: PathBuf, provisional: String) {
+    let (lock, ready) = queue();
+    let mut q = lock.lock().unwrap();
+    if q.queued.insert(path.clone()) {
+        log::info!("background hash: queued {}", path.display());
+        q.jobs.push_back(Job { path, provisional });
+        ready.notify_one();
+    }
+}
+
+/// Hash queued files one at a time until `stop` is set. When a full hash is known, anything
+/// saved under the provisional ID moves to it and the library is told to rescan.
+pub fn run(app_handle: tauri::AppHandle, stop: Arc<AtomicBool>) {
+    let (lock, ready) = queue();
+    while !stop.load(Ordering::Relaxed) {
+        let job = {
+            let q = lock.lock().unwrap();
+            let (mut q, _) = ready.wait_timeout(q, Duration::from_secs(1)).unwrap();
+            match q.jobs.pop_front() {
+                Some(job) => job,
+                None => continue,
+            }
+        };
+
+        let start = Instant::now();
+        let result = pdf_info::confirm_hash(&job.path);
+        lock.lock().unwrap().queued.remove(&job.path);
+        match result {
+            Ok(full) => {
+                log::info!(
+                    "background hash: {} done in {}ms",
+                    job.path.display(),
+                    start.elapsed().as_millis()
+                );
+                if full != job.provisional {
+                    book_id::move_book_data(&job.provisional, &full);
+                }
+                let _ = app_handle.emit("books:changed", ());
+            }
+            Err(e) => log::warn!("background hash: {} failed: {}", job.path.display(), e),
+        }
+    }
+}
@@ -0,0 +1,277 @@
+use chrono::{DateTime, Utc};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+
+use crate::{annotations, conflicts, fsutil, paths, progress, sessions};
+
+/// How long after the last sign of a device that predates full-length IDs synced data is still
+/// mirrored under short IDs for it (see `mirror_short_ids`).
+const LEGACY_PEER_DAYS: i64 = 60;
+
+/// How a book ID is derived from the SHA-256 of its contents. The version is implied by the
+/// length, so IDs stay plain hex strings in file names, URLs and window labels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdVersion {
+    /// First 8 bytes of the digest (16 hex chars), used by older app versions.
+    V1,
+    /// The full digest (64 hex chars).
+    V2,
+}
+
+impl IdVersion {
+    pub const CURRENT: IdVersion = IdVersion::V2;
+
+    const fn digest_bytes(self) -> usize {
+        match self {
+            IdVersion::V1 => 8,
+            IdVersion::V2 => 32,
+        }
+    }
+
+    const fn id_len(self) -> usize {
+        self.digest_bytes() * 2
+    }
+}
+
+/// Marks a provisional ID: one derived from samples of a large file while its full hash is
+/// computed (see `pdf_info::compute_hash_cached`). Never synced or cleaned up.
+const PROVISIONAL_PREFIX: &str = "p-";
+
+/// Book ID of the given version for a SHA-256 digest.
+pub fn derive(digest: &[u8], version: IdVersion) -> String {
+    digest[..version.digest_bytes().min(digest.len())]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Provisional ID for a digest of samples of a file.
+pub fn provisional(digest: &[u8]) -> String {
+    format!("{}{}", PROVISIONAL_PREFIX, derive(digest, IdVersion::CURRENT))
+}
+
+pub fn is_provisional(id: &str) -> bool {
+    id.strip_prefix(PROVISIONAL_PREFIX)
+        .is_some_and(|rest| version_of(rest) == Some(IdVersion::CURRENT))
+}
+
+/// Version of a confirmed ID; None for provisional IDs and anything else.
+pub fn version_of(id: &str) -> Option<IdVersion> {
+    if !id.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    [IdVersion::V1, IdVersion::V2].into_iter().find(|v| v.id_len() == id.len())
+}
+
+/// The 64-bit ID older devices know a book by. A V1 ID is a prefix of the V2 one, so data
+/// written under it can be matched without rehashing. None for IDs that are not current.
+pub fn short_alias(id: &str) -> Option<&str> {
+    (version_of(id) == Some(IdVersion::V2)).then(|| &id[..IdVersion::V1.id_len()])
+}
+
+#[derive(Default)]
+struct Aliases {
+    /// Full ID by short ID, for books this device knows.
+    full: HashMap<String, String>,
+    /// Short IDs this device still keeps data under, waiting for `adopt` to move it.
+    unmigrated: HashSet<String>,
+}
+
+fn known_aliases() -> Aliases {
+    let full = progress::local_hashes()
+        .into_iter()
+        .filter_map(|id| short_alias(&id).map(|short| (short.to_string(), id.clone())))
+        .collect();
+    // Listed once per library; after the upgrade this empties as books are seen
+    let unmigrated = progress::local_hashes()
+        .into_iter()
+        .chain(annotations::local_hashes())
+        .chain(sessions::hashes())
+        .filter(|id| version_of(id) == Some(IdVersion::V1))
+        .collect();
+    Aliases { full, unmigrated }
+}
+
+fn aliases() -> &'static Mutex<Aliases> {
+    static ALIASES: OnceLock<Mutex<Aliases>> = OnceLock::new();
+    ALIASES.get_or_init(|| Mutex::new(known_aliases()))
+}
+
+/// Re-read the short IDs known to this device, after switching to another library.
+pub fn reload() {
+    *aliases().lock().unwrap() = known_aliases();
+}
+
+/// Current ID for an ID seen in synced data: short IDs written by older devices map to the
+/// full ID of the same book, if this device knows it. Other IDs are returned unchanged.
+pub fn resolve(id: &str) -> Option<String> {
+    match version_of(id)? {
+        IdVersion::V1 => aliases().lock().unwrap().full.get(id).cloned(),
+        IdVersion::V2 => Some(id.to_string()),
+    }
+}
+
+/// Remember a book's full ID, and move anything this device still keeps under its short ID
+/// (progress, annotations, reading sessions, conflict log entries) over to it. This runs for
+/// every book as it is identified; only short IDs listed with data when the library was
+/// opened are moved, each once.
+pub fn adopt(id: &str) {
+    let Some(short) = short_alias(id) else {
+        return;
+    };
+    let pending = {
+        let mut aliases = aliases().lock().unwrap();
+        aliases.full.insert(short.to_string(), id.to_string());
+        aliases.unmigrated.remove(short)
+    };
+    if pending {
+        move_book_data(short, id);
+    }
+}
+
+fn legacy_peer_file() -> PathBuf {
+    paths::data_dir().join("legacy-peer-seen")
+}
+
+/// When a device that predates full-length IDs last wrote synced data, kept across restarts.
+fn legacy_peer_seen() -> &'static Mutex<Option<DateTime<Utc>>> {
+    static SEEN: OnceLock<Mutex<Option<DateTime<Utc>>>> = OnceLock::new();
+    SEEN.get_or_init(|| {
+        let seen = std::fs::read_to_string(legacy_peer_file())
+            .ok()
+            .and_then(|data| DateTime::parse_from_rfc3339(data.trim()).ok())
+            .map(|t| t.with_timezone(&Utc));
+        Mutex::new(seen)
+    })
+}
+
+/// Note a record another device wrote under a short ID only, at `time`.
+pub fn saw_legacy_peer(time: DateTime<Utc>) {
+    let mut seen = legacy_peer_seen().lock().unwrap();
+    if seen.is_some_and(|t| t >= time) {
+        return;
+    }
+    *seen = Some(time);
+    if let Err(e) = fsutil::write_atomic(&legacy_peer_file(), time.to_rfc3339().as_bytes(), false) {
+        log::warn!("book id: cannot save when a legacy device was seen: {}", e);
+    }
+}
+
+/// Whether synced data should also be written under short IDs, for devices that predate
+/// full-length IDs: only while one of them has written anything in the last
+/// `LEGACY_PEER_DAYS`. The mirror (and this check) can be removed once no supported version
+/// reads short IDs only; reading data older devices write under them stays until then too.
+pub fn mirror_short_ids() -> bool {
+    let cutoff = Utc::now() - chrono::Duration::days(LEGACY_PEER_DAYS);
+    legacy_peer_seen().lock().unwrap().is_some_and(|t| t > cutoff)
+}
+
+/// IDs whose data was moved to another ID while the app runs, so saves from windows still
+/// using the old ID land on the new one.
+fn moved() -> &'static Mutex<HashMap<String, String>> {
+    static MOVED: OnceLock<Mutex<HashMap<String, String>>> = OnceLock::new();
+    MOVED.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// The ID a book's data lives under now: `id` itself unless it was moved.
+pub fn current(id: &str) -> String {
+    moved().lock().unwrap().get(id).cloned().unwrap_or_else(|| id.to_string())
+}
+
+/// Move progress, annotations, reading sessions and conflict log entries from one ID of a
+/// book to another, pages unchanged.
+pub fn move_book_data(from: &str, to: &str) {
+    log::info!("book id: migrating {} -> {}", from, to);
+    moved().lock().unwrap().insert(from.to_string(), to.to_string());
+    let same_page = |page: u32| page;
+    if let Err(e) = progress::migrate(from, to, None, &same_page) {
+        log::warn!("book id: failed to migrate progress for {}: {}", from, e);
+    }
+    if let Err(e) = annotations::migrate(from, to, &same_page) {
+        log::warn!("book id: failed to migrate annotations for {}: {}", from, e);
+    }
+    if let Err(e) = sessions::migrate(from, to, &same_page) {
+        log::warn!("book id: failed to migrate sessions for {}: {}", from, e);
+    }
+    if let Err(e) = conflicts::rename_hash(from, to) {
+        log::warn!("book id: failed to migrate conflict log for {}: {}", from, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::icloud;
+
+    const DIGEST: [u8; 32] = [
+        0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef, 0xfe, 0xdc, 0xba, 0x98, 0x76, 0x54, 0x32, 0x10, 0x00, 0x11,
+        0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff,
+    ];
+
+    #[test]
+    fn derive_by_version() {
+        let v1 = derive(&DIGEST, IdVersion::V1);
+        let v2 = derive(&DIGEST, IdVersion::V2);
+        assert_eq!(v1, "0123456789abcdef");
+        assert_eq!(v2, "0123456789abcdeffedcba987654321000112233445566778899aabbccddeeff");
+        assert_eq!(version_of(&v1), Some(IdVersion::V1));
+        assert_eq!(version_of(&v2), Some(IdVersion::CURRENT));
+        // A digest shorter than the version asks for is used as it is
+        assert_eq!(derive(&DIGEST[..4], IdVersion::V2), "01234567");
+    }
+
+    #[test]
+    fn version_of_rejects_other_strings() {
+        assert_eq!(version_of(""), None);
+        assert_eq!(version_of("0123456789abcde"), None);
+        assert_eq!(version_of("0123456789abcdeg"), None);
+        assert_eq!(version_of(&"0".repeat(63)), None);
+    }
+
+    #[test]
+    fn short_alias_is_the_v1_prefix_of_a_v2_id() {
+        let v2 = derive(&DIGEST, IdVersion::V2);
+        assert_eq!(short_alias(&v2), Some(derive(&DIGEST, IdVersion::V1).as_str()));
+        assert_eq!(short_alias("0123456789abcdef"), None);
+        assert_eq!(short_alias("not an id"), None);
+    }
+
+    #[test]
+    fn provisional_ids_are_told_apart() {
+        let id = provisional(&DIGEST);
+        assert!(is_provisional(&id));
+        assert_eq!(version_of(&id), None);
+        assert_eq!(short_alias(&id), None);
+        assert!(!is_provisional(&derive(&DIGEST, IdVersion::V2)));
+        assert!(!is_provisional("p-0123456789abcdef"));
+    }
+
+    #[test]
+    fn window_opened_under_a_provisional_id_closes_cleanly() {
+        let opened_as = provisional(&[7; 32]);
+        let full = derive(&[7; 32], IdVersion::V2);
+        std::fs::create_dir_all(icloud::get_local_progress_dir()).unwrap();
+        std::fs::create_dir_all(icloud::get_sessions_dir()).unwrap();
+
+        // The full hash finished while the window was open; it keeps using the ID it was
+        // opened with
+        moved().lock().unwrap().insert(opened_as.clone(), full.clone());
+        let position = progress::ReadingProgress {
+            hash: opened_as.clone(),
+            current_page: 12,
+            ..Default::default()
+        };
+        progress::save_local(&position).unwrap();
+        sessions::record_activity(&opened_as, 11);
+        sessions::record_activity(&opened_as, 12);
+
+        // Closing the window
+        progress::flush(&opened_as);
+        sessions::end(&opened_as);
+        let saved = std::fs::read_to_string(icloud::get_local_progress_dir().join(format!("{}.json", full)));
+        assert!(saved.unwrap().contains("\"current_page\": 12"));
+        assert!(sessions::hashes().contains(&full));
+        assert!(!sessions::hashes().contains(&opened_as));
+    }
+}
@@ -1,8 +1,9 @@
-use crate::{icloud, locale, pdf_info, progress, window};
+use crate::{annotations, book_id, conflicts, device, export, gc, hash_cache, icloud, icloud_placeholder, identity, library, locale, pdf_annotations, pdf_info, progress, reveal, sessions, settings, storage_migration, sync_backend, watcher, window};
+use serde::Serialize;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::time::Instant;
-use tauri::Emitter;
+use tauri::{Emitter, Manager};
 
 #[tauri::command]
 pub fn scan_books() -> Result<Vec<pdf_info::PdfInfo>, String> {
@@ -17,17 +18,27 @@ pub fn scan_books() -> Result<Vec<pdf_info::PdfInfo>, String> {
 
     let mut books = Vec::new();
     let mut pdf_count = 0;
+    let mut placeholder_count = 0;
     let entries =
         std::fs::read_dir(&books_dir).map_err(|e| format!("read_dir_failed|detail={}", e))?;
 
     for entry in entries.flatten() {
         let path = entry.path();
 
-        // Skip .icloud placeholder files
-        if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
-            if name.starts_with('.') && name.ends_with(".icloud") {
-                continue;
+        // Books in iCloud Drive that are not downloaded yet
+        if icloud_placeholder::is_placeholder(&path) {
+            match pdf_info::placeholder_info(&path) {
+                Ok(info) if info.filename.to_lowercase().ends_with(".pdf") => {
+                    // The download may have finished since the directory was read
+                    if !Path::new(&info.path).exists() {
+                        placeholder_count += 1;
+                        books.push(info);
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => log::warn!("Failed to read placeholder {:?}: {}", path, e),
             }
+            continue;
         }
 
         if path
@@ -42,6 +53,7 @@ pub fn scan_books() -> Result<Vec<pdf_info::PdfInfo>, String> {
                     if elapsed.as_millis() > 200 {
                         log::info!("scan_books: slow extract_info for {:?}: {}ms", path, elapsed.as_millis());
                     }
+                    book_id::adopt(&info.hash);
                     books.push(info);
                 }
                 Err(e) => log::warn!("Failed to extract info from {:?}: {}", path, e),
@@ -49,8 +61,17 @@ pub fn scan_books() -> Result<Vec<pdf_info::PdfInfo>, String> {
         }
     }
 
+    // Carry progress and annotations forward for books whose contents changed
+    identity::reconcile_later(&books);
+
     books.sort_by(|a, b| a.title.to_lowercase().cmp(&b.title.to_lowercase()));
-    log::info!("scan_books: done in {}ms, {} PDFs found, {} loaded", start.elapsed().as_millis(), pdf_count, books.len());
+    log::info!(
+        "scan_books: done in {}ms, {} PDFs found, {} not downloaded, {} listed",
+        start.elapsed().as_millis(),
+        pdf_count,
+        placeholder_count,
+        books.len()
+    );
     Ok(books)
 }
 
@@ -84,9 +105,70 @@ pub fn get_pdf_outline(file_path: String) -> Result<Vec<pdf_info::OutlineItem>,
     pdf_info::extract_outline(&path)
 }
 
+/// Annotations embedded in the PDF file. Parses the whole file, so it runs off the main thread.
+#[tauri::command]
+pub async fn get_pdf_annotations(file_path: String) -> Result<Vec<pdf_annotations::PdfAnnotation>, String> {
+    run_blocking(move || pdf_annotations::extract_annotations(&PathBuf::from(&file_path))).await
+}
+
+/// Write a copy of the book with the app's highlights and notes embedded as PDF annotations.
+#[tauri::command]
+pub async fn export_annotated_pdf(file_path: String, hash: String, dest_path: String) -> Result<(), String> {
+    run_blocking(move || {
+        let source = PathBuf::from(&file_path);
+        let dest = PathBuf::from(&dest_path);
+        let list = annotations::list(&hash)?;
+        pdf_annotations::export_annotated(&source, &dest, &list)?;
+        log::info!("export_annotated_pdf: {} annotations -> {}", list.len(), dest.display());
+        Ok(())
+    })
+    .await
+}
+
+/// Export one book's highlights and notes as Markdown, JSON or CSV.
+#[tauri::command]
+pub async fn export_annotations(
+    file_path: String,
+    format: export::ExportFormat,
+    dest_path: String,
+) -> Result<(), String> {
+    run_blocking(move || {
+        let book = pdf_info::extract_info(&PathBuf::from(&file_path))?;
+        export::export_book(&book, format, &PathBuf::from(&dest_path))
+    })
+    .await
+}
+
+/// Export every annotated book into a folder (e.g. an Obsidian vault), one file per book.
 #[tauri::command]
-pub fn load_progress(hash: String) -> Result<Option<progress::ReadingProgress>, String> {
-    progress::load(&hash)
+pub async fn export_all_annotations(
+    format: export::ExportFormat,
+    dest_dir: String,
+) -> Result<Vec<String>, String> {
+    run_blocking(move || {
+        let books = scan_books()?;
+        export::export_all(&books, format, &PathBuf::from(&dest_dir))
+    })
+    .await
+}
+
+/// Run blocking sync work off the main thread (a WebDAV round trip can take seconds).
+async fn run_blocking<T: Send + 'static>(
+    f: impl FnOnce() -> Result<T, String> + Send + 'static,
+) -> Result<T, String> {
+    tauri::async_runtime::spawn_blocking(f)
+        .await
+        .map_err(|e| format!("task_failed|detail={}", e))?
+}
+
+#[tauri::command]
+pub async fn load_progress(
+    app_handle: tauri::AppHandle,
+    hash: String,
+) -> Result<Option<progress::ReadingProgress>, String> {
+    let result = run_blocking(move || progress::load(&hash)).await;
+    emit_new_conflicts(&app_handle);
+    result
 }
 
 #[tauri::command]
@@ -95,18 +177,376 @@ pub fn save_progress(
     progress_data: progress::ReadingProgress,
 ) -> Result<(), String> {
     progress::save_local(&progress_data)?;
-    let _ = app_handle.emit("progress:changed", &progress_data);
+    sessions::record_activity(&progress_data.hash, progress_data.current_page);
+    emit_progress_changed(&app_handle, &progress_data, ProgressSource::Local);
+    Ok(())
+}
+
+/// Heartbeat from the reader while the user interacts without changing progress.
+#[tauri::command]
+pub fn record_reading_activity(hash: String, page: u32) {
+    sessions::record_activity(&hash, page);
+}
+
+#[tauri::command]
+pub fn get_reading_stats(days: Option<u32>) -> sessions::ReadingStats {
+    sessions::stats(days.unwrap_or(30))
+}
+
+#[tauri::command]
+pub async fn sync_progress(
+    app_handle: tauri::AppHandle,
+    hash: String,
+) -> Result<Option<progress::ReadingProgress>, String> {
+    let result = run_blocking(move || progress::sync(&hash)).await;
+    emit_new_conflicts(&app_handle);
+    result
+}
+
+#[tauri::command]
+pub async fn sync_all_progress(
+    app_handle: tauri::AppHandle,
+    hashes: Vec<String>,
+) -> Result<Vec<progress::ReadingProgress>, String> {
+    let result = run_blocking(move || progress::sync_all(&hashes)).await;
+    emit_new_conflicts(&app_handle);
+    result
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProgressSource {
+    /// Saved on this device.
+    Local,
+    /// Pulled from another device by sync.
+    Remote,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProgressChanged<'a> {
+    #[serde(flatten)]
+    pub progress: &'a progress::ReadingProgress,
+    pub source: ProgressSource,
+}
+
+pub(crate) fn emit_progress_changed(
+    app_handle: &tauri::AppHandle,
+    progress: &progress::ReadingProgress,
+    source: ProgressSource,
+) {
+    let _ = app_handle.emit("progress:changed", ProgressChanged { progress, source });
+}
+
+/// Announce conflicts that wait for the reader's choice (merge strategy "ask").
+pub(crate) fn emit_new_conflicts(app_handle: &tauri::AppHandle) {
+    for conflict in conflicts::take_new_pending() {
+        let _ = app_handle.emit("progress:conflict", &conflict);
+    }
+}
+
+/// Where each device last was in a book ("Page 120 on MacBook Air, 2 hours ago").
+#[tauri::command]
+pub async fn get_progress_records(hash: String) -> Result<Vec<progress::DeviceProgress>, String> {
+    run_blocking(move || progress::device_records(&hash)).await
+}
+
+#[tauri::command]
+pub async fn get_devices() -> Result<Vec<device::Device>, String> {
+    run_blocking(|| Ok(device::list())).await
+}
+
+#[tauri::command]
+pub async fn rename_device(name: String) -> Result<device::Device, String> {
+    run_blocking(move || device::rename(&name)).await
+}
+
+/// The sync backend configuration, without the stored WebDAV password.
+#[tauri::command]
+pub fn get_sync_config() -> settings::SyncConfig {
+    let mut config = settings::load_device().sync;
+    config.webdav_password = None;
+    config
+}
+
+/// Switch the sync backend. The new backend is contacted once before it is saved.
+/// An empty WebDAV password keeps the stored one.
+#[tauri::command]
+pub async fn set_sync_config(app_handle: tauri::AppHandle, mut config: settings::SyncConfig) -> Result<(), String> {
+    if config.webdav_password.as_deref().map_or(true, str::is_empty) {
+        config.webdav_password = settings::load_device().sync.webdav_password;
+    }
+    run_blocking(move || sync_backend::configure(config)).await?;
+    emit_settings_changed(&app_handle);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_merge_strategy() -> settings::MergeStrategy {
+    settings::load_library().merge_strategy
+}
+
+#[tauri::command]
+pub fn set_merge_strategy(app_handle: tauri::AppHandle, strategy: settings::MergeStrategy) -> Result<(), String> {
+    let mut library = settings::load_library();
+    library.merge_strategy = strategy;
+    settings::save_library(&library)?;
+    emit_settings_changed(&app_handle);
     Ok(())
 }
 
+/// All settings, without the stored WebDAV password.
 #[tauri::command]
-pub fn sync_progress(hash: String) -> Result<Option<progress::ReadingProgress>, String> {
-    progress::sync(&hash)
+pub fn get_settings() -> settings::Settings {
+    redacted(settings::load())
+}
+
+fn redacted(mut settings: settings::Settings) -> settings::Settings {
+    settings.device.sync.webdav_password = None;
+    settings
 }
 
+/// Validate and save both scopes. A changed sync configuration is applied as in
+/// `set_sync_config` (an empty WebDAV password keeps the stored one). Libraries and the
+/// default library's location are managed by their own commands and are left as they are.
 #[tauri::command]
-pub fn sync_all_progress(hashes: Vec<String>) -> Result<Vec<progress::ReadingProgress>, String> {
-    progress::sync_all(&hashes)
+pub async fn update_settings(
+    app_handle: tauri::AppHandle,
+    mut settings: settings::Settings,
+) -> Result<settings::Settings, String> {
+    run_blocking(move || {
+        let current = settings::load_device();
+        let device = &mut settings.device;
+        if device.sync.webdav_password.as_deref().map_or(true, str::is_empty) {
+            device.sync.webdav_password = current.sync.webdav_password.clone();
+        }
+        device.libraries = current.libraries.clone();
+        device.active_library = current.active_library.clone();
+        device.default_root = current.default_root.clone();
+        device.declined_root = current.declined_root.clone();
+        device.pending_import = current.pending_import.clone();
+        settings::validate(&settings)?;
+
+        if settings.device.sync != current.sync {
+            sync_backend::configure(settings.device.sync.clone())?;
+        }
+        settings::save_device(&settings.device)?;
+        settings::save_library(&settings.library)
+    })
+    .await?;
+    emit_settings_changed(&app_handle);
+    Ok(get_settings())
+}
+
+/// Tell every window the settings changed, so they can re-read what they depend on.
+fn emit_settings_changed(app_handle: &tauri::AppHandle) {
+    let _ = app_handle.emit("settings:changed", get_settings());
+}
+
+#[tauri::command]
+pub fn list_libraries() -> Vec<library::Library> {
+    library::list()
+}
+
+#[tauri::command]
+pub fn add_library(app_handle: tauri::AppHandle, name: String, root: String) -> Result<library::Library, String> {
+    let added = library::add(&name, &root)?;
+    emit_settings_changed(&app_handle);
+    Ok(added)
+}
+
+#[tauri::command]
+pub fn rename_library(app_handle: tauri::AppHandle, id: String, name: String) -> Result<(), String> {
+    library::rename(&id, &name)?;
+    emit_settings_changed(&app_handle);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn remove_library(app_handle: tauri::AppHandle, id: String) -> Result<(), String> {
+    library::remove(&id)?;
+    emit_settings_changed(&app_handle);
+    Ok(())
+}
+
+/// Show another library. Reader windows belong to the old one and are closed first, so
+/// nothing they save lands in the new library.
+#[tauri::command]
+pub async fn switch_library(app_handle: tauri::AppHandle, id: String) -> Result<(), String> {
+    for (label, window) in app_handle.webview_windows() {
+        if label.starts_with("reader-") {
+            let _ = window.destroy();
+        }
+    }
+    run_blocking(move || library::switch(&id)).await?;
+    library::allow_active(&app_handle);
+    emit_settings_changed(&app_handle);
+    let _ = app_handle.emit("books:changed", ());
+    Ok(())
+}
+
+/// Whether the default library should move because iCloud Drive was turned on or off.
+#[tauri::command]
+pub fn get_storage_change() -> Option<storage_migration::StorageChange> {
+    storage_migration::detect()
+}
+
+/// Copy or move the default library to where it now belongs; with `dry_run`, only report.
+#[tauri::command]
+pub async fn migrate_storage(
+    app_handle: tauri::AppHandle,
+    mode: storage_migration::MigrationMode,
+    dry_run: bool,
+) -> Result<storage_migration::MigrationReport, String> {
+    let report = run_blocking(move || storage_migration::migrate(mode, dry_run)).await?;
+    if !dry_run {
+        library::allow_active(&app_handle);
+        emit_settings_changed(&app_handle);
+        let _ = app_handle.emit("books:changed", ());
+    }
+    Ok(report)
+}
+
+/// Stay on the default library's current location.
+#[tauri::command]
+pub fn keep_storage_root(app_handle: tauri::AppHandle) -> Result<(), String> {
+    storage_migration::decline()?;
+    emit_settings_changed(&app_handle);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_watcher_health() -> watcher::WatcherHealth {
+    watcher::health()
+}
+
+/// Set up the Books folder watcher again now, e.g. after the reader reconnected a drive.
+#[tauri::command]
+pub fn restart_watcher() {
+    watcher::restart();
+}
+
+/// Report (with `dry_run`) or remove progress of books no longer in the library and stale
+/// cache entries.
+#[tauri::command]
+pub async fn gc_library(dry_run: bool) -> Result<gc::GcReport, String> {
+    run_blocking(move || Ok(gc::run(dry_run))).await
+}
+
+#[tauri::command]
+pub fn get_auto_gc() -> bool {
+    settings::load_device().auto_gc
+}
+
+#[tauri::command]
+pub fn set_auto_gc(app_handle: tauri::AppHandle, enabled: bool) -> Result<(), String> {
+    let mut device = settings::load_device();
+    device.auto_gc = enabled;
+    settings::save_device(&device)?;
+    emit_settings_changed(&app_handle);
+    Ok(())
+}
+
+/// Logged sync conflicts, newest first, optionally for one book.
+#[tauri::command]
+pub fn get_sync_conflicts(hash: Option<String>) -> Vec<conflicts::SyncConflict> {
+    conflicts::list(hash.as_deref())
+}
+
+/// Settle a conflict by keeping the local or the remote position.
+#[tauri::command]
+pub fn resolve_sync_conflict(
+    app_handle: tauri::AppHandle,
+    id: String,
+    keep: conflicts::ConflictDecision,
+) -> Result<progress::ReadingProgress, String> {
+    let saved = progress::resolve_conflict(&id, keep)?;
+    emit_progress_changed(&app_handle, &saved, ProgressSource::Local);
+    Ok(saved)
+}
+
+/// Reverse the decision taken for a conflict (a pending one keeps the local position).
+#[tauri::command]
+pub fn undo_sync_conflict(
+    app_handle: tauri::AppHandle,
+    id: String,
+) -> Result<progress::ReadingProgress, String> {
+    let keep = match conflicts::get(&id)?.decision {
+        conflicts::ConflictDecision::Local => conflicts::ConflictDecision::Remote,
+        _ => conflicts::ConflictDecision::Local,
+    };
+    resolve_sync_conflict(app_handle, id, keep)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AnnotationsChanged {
+    pub hash: String,
+    pub annotations: Vec<annotations::Annotation>,
+}
+
+/// Reloads (and so syncs) the book's annotations: call it off the main thread.
+fn emit_annotations_changed(app_handle: &tauri::AppHandle, hash: &str) {
+    match annotations::list(hash) {
+        Ok(list) => {
+            let _ = app_handle.emit(
+                "annotations:changed",
+                AnnotationsChanged {
+                    hash: hash.to_string(),
+                    annotations: list,
+                },
+            );
+        }
+        Err(e) => log::warn!("emit annotations:changed failed for {}: {}", hash, e),
+    }
+}
+
+#[tauri::command]
+pub async fn load_annotations(hash: String) -> Result<Vec<annotations::Annotation>, String> {
+    run_blocking(move || annotations::list(&hash)).await
+}
+
+#[tauri::command]
+pub async fn add_annotation(
+    app_handle: tauri::AppHandle,
+    annotation: annotations::Annotation,
+) -> Result<annotations::Annotation, String> {
+    run_blocking(move || {
+        let created = annotations::add(&annotation)?;
+        emit_annotations_changed(&app_handle, &created.hash);
+        Ok(created)
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn update_annotation(
+    app_handle: tauri::AppHandle,
+    annotation: annotations::Annotation,
+) -> Result<annotations::Annotation, String> {
+    run_blocking(move || {
+        let updated = annotations::update(&annotation)?;
+        emit_annotations_changed(&app_handle, &updated.hash);
+        Ok(updated)
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn delete_annotation(
+    app_handle: tauri::AppHandle,
+    hash: String,
+    id: String,
+) -> Result<(), String> {
+    run_blocking(move || {
+        annotations::remove(&hash, &id)?;
+        emit_annotations_changed(&app_handle, &hash);
+        Ok(())
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn sync_annotations(hash: String) -> Result<Option<Vec<annotations::Annotation>>, String> {
+    run_blocking(move || annotations::sync(&hash)).await
 }
 
 #[tauri::command]
@@ -123,44 +563,83 @@ pub fn get_books_directory() -> String {
     icloud::get_books_dir().to_string_lossy().to_string()
 }
 
+/// Safety: the file (or the Books directory itself) must be inside the Books directory of the
+/// active library. Returns the canonical path.
+fn ensure_in_books(path: &Path) -> Result<PathBuf, String> {
+    let canonical_path = path
+        .canonicalize()
+        .map_err(|e| format!("resolve_path_failed|detail={}", e))?;
+    let canonical_books = icloud::get_books_dir()
+        .canonicalize()
+        .map_err(|e| format!("resolve_books_dir_failed|detail={}", e))?;
+    if !canonical_path.starts_with(&canonical_books) {
+        return Err("file_not_in_books".to_string());
+    }
+    Ok(canonical_path)
+}
+
+/// Show a book, or the Books directory, in the system file manager. A book that is only in
+/// iCloud Drive is shown through its placeholder.
 #[tauri::command]
 pub fn reveal_in_finder(path: String) -> Result<(), String> {
-    std::process::Command::new("open")
-        .arg("-R")
-        .arg(&path)
-        .spawn()
-        .map_err(|e| format!("reveal_failed|detail={}", e))?;
+    let path = PathBuf::from(path);
+    let path = match icloud_placeholder::placeholder_path(&path) {
+        Some(placeholder) if !path.exists() && placeholder.is_file() => placeholder,
+        _ => path,
+    };
+    let canonical_path = ensure_in_books(&path)?;
+    reveal::reveal(&canonical_path)
+}
+
+/// Start downloading a book that is only in iCloud Drive. The watcher reports it once it is here.
+#[tauri::command]
+pub fn download_book(file_path: String) -> Result<(), String> {
+    let path = PathBuf::from(&file_path);
+    // The book itself does not exist yet, so check the folder it will appear in
+    ensure_in_books(path.parent().ok_or_else(|| "file_not_in_books".to_string())?)?;
+    icloud_placeholder::request_download(&path)?;
+    log::info!("download_book: requested {}", file_path);
     Ok(())
 }
 
 #[tauri::command]
 pub fn is_debug_enabled() -> bool {
-    std::env::var("PDF_DEBUG").as_deref() == Ok("1")
+    settings::debug_enabled()
+}
+
+#[derive(Serialize)]
+pub struct CacheReport {
+    pub pdf_info: crate::lru::CacheStats,
+    pub hash_cache_entries: usize,
+}
+
+/// In-memory cache sizes and hit rates, for diagnosing memory use in long sessions. Only
+/// available with debug logging on; there is no UI for it, call it from the web inspector.
+#[tauri::command]
+pub fn debug_cache_stats() -> Result<CacheReport, String> {
+    if !settings::debug_enabled() {
+        return Err("debug_disabled".to_string());
+    }
+    Ok(CacheReport {
+        pdf_info: pdf_info::pdf_cache_stats(),
+        hash_cache_entries: hash_cache::len(),
+    })
 }
 
 #[tauri::command]
 pub fn delete_pdf(file_path: String, hash: String) -> Result<(), String> {
     let path = PathBuf::from(&file_path);
-    let books_dir = icloud::get_books_dir();
-
-    // Safety: ensure the file is inside the Books directory
-    let canonical_path = path
-        .canonicalize()
-        .map_err(|e| format!("resolve_path_failed|detail={}", e))?;
-    let canonical_books = books_dir
-        .canonicalize()
-        .map_err(|e| format!("resolve_books_dir_failed|detail={}", e))?;
-    if !canonical_path.starts_with(&canonical_books) {
-        return Err("file_not_in_books".to_string());
-    }
+    ensure_in_books(&path)?;
 
     // Move to trash
     trash::delete(&path).map_err(|e| format!("trash_failed|detail={}", e))?;
     log::info!("delete_pdf: moved to trash: {}", file_path);
 
-    // Remove associated progress files (both local and central)
+    // Remove associated progress and annotation files (both local and central) and the session log
     progress::delete(&hash);
-    log::info!("delete_pdf: removed progress for {}", hash);
+    annotations::delete(&hash);
+    sessions::delete(&hash);
+    log::info!("delete_pdf: removed progress, annotations and sessions for {}", hash);
 
     Ok(())
 }
@@ -168,18 +647,7 @@ pub fn delete_pdf(file_path: String, hash: String) -> Result<(), String> {
 #[tauri::command]
 pub fn rename_pdf(file_path: String, new_filename: String) -> Result<(), String> {
     let path = PathBuf::from(&file_path);
-    let books_dir = icloud::get_books_dir();
-
-    // Safety: ensure the file is inside the Books directory
-    let canonical_path = path
-        .canonicalize()
-        .map_err(|e| format!("resolve_path_failed|detail={}", e))?;
-    let canonical_books = books_dir
-        .canonicalize()
-        .map_err(|e| format!("resolve_books_dir_failed|detail={}", e))?;
-    if !canonical_path.starts_with(&canonical_books) {
-        return Err("file_not_in_books".to_string());
-    }
+    ensure_in_books(&path)?;
 
     // Validate new filename
     let new_filename = new_filename.trim().to_string();
@@ -197,7 +665,7 @@ pub fn rename_pdf(file_path: String, new_filename: String) -> Result<(), String>
         format!("{}.pdf", new_filename)
     };
 
-    let new_path = books_dir.join(&final_filename);
+    let new_path = icloud::get_books_dir().join(&final_filename);
 
     // Prevent overwrite
     if new_path.exists() {
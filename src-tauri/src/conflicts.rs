@@ -0,0 +1,185 @@
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use crate::{fsutil, icloud};
+use crate::progress::ReadingProgress;
+use crate::settings::MergeStrategy;
+
+/// Oldest entries are dropped beyond this many. The log is history for the reader; which remote
+/// position was settled is kept in each book's progress record (`settled_remote`).
+const MAX_ENTRIES: usize = 200;
+
+/// The log of the active library, read once: sync looks entries up on every pass. Keyed by
+/// file so switching libraries reads the other log.
+static CONFLICTS_LOCK: Mutex<Option<(PathBuf, Vec<SyncConflict>)>> = Mutex::new(None);
+static NEW_PENDING: Mutex<Vec<SyncConflict>> = Mutex::new(Vec::new());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictDecision {
+    Local,
+    Remote,
+    /// Waiting for the reader to choose (merge strategy "ask").
+    Pending,
+}
+
+/// One disagreement between this device's position and another device's, and how it was settled.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncConflict {
+    pub id: String,
+    pub hash: String,
+    pub detected_at: String,
+    pub strategy: MergeStrategy,
+    pub local: ReadingProgress,
+    pub remote: ReadingProgress,
+    pub decision: ConflictDecision,
+    /// Set when the reader resolved or undid the automatic decision.
+    #[serde(default)]
+    pub resolved_at: Option<String>,
+}
+
+fn conflicts_file() -> PathBuf {
+    icloud::get_library_data_dir().join("sync-conflicts.json")
+}
+
+fn now() -> String {
+    chrono::Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string()
+}
+
+fn read_all() -> Vec<SyncConflict> {
+    std::fs::read_to_string(conflicts_file())
+        .ok()
+        .and_then(|data| serde_json::from_str(&data).ok())
+        .unwrap_or_default()
+}
+
+/// The cached log, read from disk on first use or after a library switch.
+fn cached(cache: &mut Option<(PathBuf, Vec<SyncConflict>)>) -> &mut Vec<SyncConflict> {
+    let path = conflicts_file();
+    if cache.as_ref().map_or(true, |(cached_path, _)| *cached_path != path) {
+        *cache = Some((path, read_all()));
+    }
+    &mut cache.as_mut().unwrap().1
+}
+
+fn write_all(entries: &[SyncConflict]) -> Result<(), String> {
+    let path = conflicts_file();
+    let data = serde_json::to_string_pretty(entries)
+        .map_err(|e| format!("serialize_conflicts_failed|detail={}", e))?;
+    fsutil::write_atomic(&path, data.as_bytes(), false)
+        .map_err(|e| format!("write_conflicts_failed|detail={}", e))
+}
+
+/// The logged conflict for a remote position, if it was already decided once. A remote
+/// record is identified by its device and timestamp.
+pub fn find(hash: &str, remote: &ReadingProgress) -> Option<SyncConflict> {
+    let mut cache = CONFLICTS_LOCK.lock().unwrap();
+    cached(&mut cache)
+        .iter()
+        .find(|c| {
+            c.hash == hash
+                && c.remote.device_id == remote.device_id
+                && c.remote.last_read == remote.last_read
+        })
+        .cloned()
+}
+
+pub fn record(
+    strategy: MergeStrategy,
+    local: &ReadingProgress,
+    remote: &ReadingProgress,
+    decision: ConflictDecision,
+) -> SyncConflict {
+    let conflict = SyncConflict {
+        id: format!("{:x}", chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default()),
+        hash: local.hash.clone(),
+        detected_at: now(),
+        strategy,
+        local: local.clone(),
+        remote: remote.clone(),
+        decision,
+        resolved_at: None,
+    };
+    log::warn!(
+        "conflict: hash={}, local page={} vs page={} from {}, strategy={:?}, decision={:?}",
+        &conflict.hash[..8.min(conflict.hash.len())],
+        local.current_page,
+        remote.current_page,
+        remote.device_id,
+        strategy,
+        decision
+    );
+
+    let mut cache = CONFLICTS_LOCK.lock().unwrap();
+    let entries = cached(&mut cache);
+    entries.push(conflict.clone());
+    if entries.len() > MAX_ENTRIES {
+        let excess = entries.len() - MAX_ENTRIES;
+        entries.drain(..excess);
+    }
+    if let Err(e) = write_all(entries) {
+        log::warn!("conflict: failed to save log: {}", e);
+    }
+    if decision == ConflictDecision::Pending {
+        NEW_PENDING.lock().unwrap().push(conflict.clone());
+    }
+    conflict
+}
+
+/// Logged conflicts, newest first, optionally for one book.
+pub fn list(hash: Option<&str>) -> Vec<SyncConflict> {
+    let mut cache = CONFLICTS_LOCK.lock().unwrap();
+    let mut entries: Vec<SyncConflict> = cached(&mut cache)
+        .iter()
+        .filter(|c| hash.map_or(true, |h| c.hash == h))
+        .cloned()
+        .collect();
+    entries.reverse();
+    entries
+}
+
+pub fn get(id: &str) -> Result<SyncConflict, String> {
+    let mut cache = CONFLICTS_LOCK.lock().unwrap();
+    cached(&mut cache)
+        .iter()
+        .find(|c| c.id == id)
+        .cloned()
+        .ok_or_else(|| "sync_conflict_not_found".to_string())
+}
+
+pub fn set_decision(id: &str, decision: ConflictDecision) -> Result<SyncConflict, String> {
+    let mut cache = CONFLICTS_LOCK.lock().unwrap();
+    let entries = cached(&mut cache);
+    let entry = entries
+        .iter_mut()
+        .find(|c| c.id == id)
+        .ok_or_else(|| "sync_conflict_not_found".to_string())?;
+    entry.decision = decision;
+    entry.resolved_at = Some(now());
+    let updated = entry.clone();
+    write_all(entries)?;
+    Ok(updated)
+}
+
+/// Re-attribute logged conflicts from one book ID to another.
+pub fn rename_hash(old: &str, new: &str) -> Result<(), String> {
+    let mut cache = CONFLICTS_LOCK.lock().unwrap();
+    let entries = cached(&mut cache);
+    let mut changed = false;
+    for entry in entries.iter_mut().filter(|c| c.hash == old) {
+        entry.hash = new.to_string();
+        entry.local.hash = new.to_string();
+        entry.remote.hash = new.to_string();
+        changed = true;
+    }
+    if changed {
+        write_all(entries)?;
+    }
+    Ok(())
+}
+
+/// Conflicts waiting for the reader that have not been announced to the UI yet.
+pub fn take_new_pending() -> Vec<SyncConflict> {
+    std::mem::take(&mut *NEW_PENDING.lock().unwrap())
+}
@@ -0,0 +1,185 @@
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+
+use crate::{fsutil, paths, sync_backend};
+
+/// Re-announce this device in the central registry at most this often.
+const HEARTBEAT_SECS: i64 = 300;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Device {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub last_seen: String,
+}
+
+fn local_device_file() -> PathBuf {
+    paths::data_dir().join("device.json")
+}
+
+fn central_device_path(id: &str) -> String {
+    format!("Devices/{}.json", id)
+}
+
+/// Names of other devices already read from the registry, to avoid re-reading it per record.
+fn known_names() -> &'static Mutex<HashMap<String, String>> {
+    static NAMES: OnceLock<Mutex<HashMap<String, String>>> = OnceLock::new();
+    NAMES.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn now() -> String {
+    chrono::Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string()
+}
+
+fn generate_id() -> String {
+    let a = RandomState::new().build_hasher().finish();
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_i64(chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default());
+    format!("{:016x}{:016x}", a, hasher.finish())
+}
+
+/// Human-readable machine name ("MacBook Air"), as shown in Finder's sharing settings.
+fn system_device_name() -> String {
+    #[cfg(target_os = "macos")]
+    let output = std::process::Command::new("scutil")
+        .args(["--get", "ComputerName"])
+        .output();
+    #[cfg(not(target_os = "macos"))]
+    let output = std::process::Command::new("hostname").output();
+
+    output
+        .ok()
+        .filter(|o| o.status.success())
+        .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
+        .filter(|n| !n.is_empty())
+        .or_else(|| std::env::var("COMPUTERNAME").ok())
+        .unwrap_or_else(|| "Unknown device".to_string())
+}
+
+fn state() -> &'static Mutex<Device> {
+    static DEVICE: OnceLock<Mutex<Device>> = OnceLock::new();
+    DEVICE.get_or_init(|| {
+        let path = local_device_file();
+        let existing = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|data| serde_json::from_str::<Device>(&data).ok())
+            .filter(|d| !d.id.is_empty());
+        let device = existing.unwrap_or_else(|| {
+            let device = Device {
+                id: generate_id(),
+                name: system_device_name(),
+                last_seen: String::new(),
+            };
+            if let Err(e) = write_device(&path, &device) {
+                log::warn!("Failed to save device identity: {}", e);
+            }
+            log::info!("Registered new device {} ({})", device.id, device.name);
+            device
+        });
+        Mutex::new(device)
+    })
+}
+
+fn write_device(path: &std::path::Path, device: &Device) -> Result<(), String> {
+    let data = serde_json::to_string_pretty(device)
+        .map_err(|e| format!("serialize_device_failed|detail={}", e))?;
+    fsutil::write_atomic(path, data.as_bytes(), false)
+        .map_err(|e| format!("write_device_failed|detail={}", e))
+}
+
+/// Stable ID of this installation.
+pub fn current_id() -> String {
+    state().lock().unwrap().id.clone()
+}
+
+pub fn current() -> Device {
+    state().lock().unwrap().clone()
+}
+
+/// Publish this device's name and last-seen time to the central registry, at most once per
+/// heartbeat interval unless forced.
+pub fn announce(force: bool) {
+    let Some(backend) = sync_backend::current() else {
+        return;
+    };
+    let mut device = state().lock().unwrap();
+    let due = chrono::DateTime::parse_from_rfc3339(&device.last_seen)
+        .map(|t| (chrono::Utc::now() - t.with_timezone(&chrono::Utc)).num_seconds() >= HEARTBEAT_SECS)
+        .unwrap_or(true);
+    if !force && !due {
+        return;
+    }
+    device.last_seen = now();
+    if let Err(e) = sync_backend::write_json(backend.as_ref(), &central_device_path(&device.id), &*device) {
+        log::warn!("Failed to announce device: {}", e);
+    }
+}
+
+pub fn rename(name: &str) -> Result<Device, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("device_name_empty".to_string());
+    }
+    {
+        let mut device = state().lock().unwrap();
+        device.name = name.to_string();
+        write_device(&local_device_file(), &device)?;
+    }
+    announce(true);
+    Ok(current())
+}
+
+/// All devices known to the central registry, plus this one.
+pub fn list() -> Vec<Device> {
+    let me = current();
+    let names = sync_backend::current()
+        .and_then(|backend| backend.list("Devices").ok().map(|names| (backend, names)));
+    let mut devices: Vec<Device> = names
+        .map(|(backend, names)| {
+            names
+                .iter()
+                .filter(|name| name.ends_with(".json"))
+                .filter_map(|name| {
+                    sync_backend::read_json::<Device>(backend.as_ref(), &format!("Devices/{}", name))
+                        .ok()
+                        .flatten()
+                })
+                .filter(|d| d.id != me.id)
+                .collect()
+        })
+        .unwrap_or_default();
+    {
+        let mut known = known_names().lock().unwrap();
+        for d in &devices {
+            known.insert(d.id.clone(), d.name.clone());
+        }
+    }
+    devices.push(me);
+    devices.sort_by(|a, b| b.last_seen.cmp(&a.last_seen));
+    devices
+}
+
+/// Display name for a device ID, if it is registered.
+pub fn name_of(id: &str) -> Option<String> {
+    let me = current();
+    if me.id == id {
+        return Some(me.name);
+    }
+    if let Some(name) = known_names().lock().unwrap().get(id) {
+        return Some(name.clone());
+    }
+    let backend = sync_backend::current()?;
+    let device = sync_backend::read_json::<Device>(backend.as_ref(), &central_device_path(id))
+        .ok()
+        .flatten()?;
+    known_names()
+        .lock()
+        .unwrap()
+        .insert(device.id.clone(), device.name.clone());
+    Some(device.name)
+}
@@ -0,0 +1,367 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::annotations::{self, Annotation, AnnotationKind};
+use crate::pdf_info::{self, OutlineItem, PdfInfo};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Markdown,
+    Json,
+    Csv,
+}
+
+impl ExportFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Markdown => "md",
+            Self::Json => "json",
+            Self::Csv => "csv",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct ExportedAnnotation<'a> {
+    kind: AnnotationKind,
+    page: u32,
+    page_label: String,
+    chapter: Option<String>,
+    text: &'a str,
+    note: &'a str,
+    color: &'a str,
+    created_at: &'a str,
+    updated_at: &'a str,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct ExportedBook<'a> {
+    title: String,
+    author: Option<String>,
+    filename: &'a str,
+    hash: &'a str,
+    annotations: Vec<ExportedAnnotation<'a>>,
+}
+
+/// Chapters used for grouping: the top-level outline entries, or the second level when the
+/// whole outline hangs under a single root (common for books whose root is the title).
+fn chapters(outline: &[OutlineItem]) -> Vec<(u32, String)> {
+    let level = match outline {
+        [single] if !single.children.is_empty() => &single.children,
+        _ => outline,
+    };
+    let mut chapters: Vec<(u32, String)> = level
+        .iter()
+        .map(|item| (item.page, item.title.trim().to_string()))
+        .filter(|(_, title)| !title.is_empty())
+        .collect();
+    chapters.sort_by_key(|(page, _)| *page);
+    chapters
+}
+
+fn chapter_for(chapters: &[(u32, String)], page: u32) -> Option<String> {
+    chapters
+        .iter()
+        .rev()
+        .find(|(start, _)| *start <= page)
+        .map(|(_, title)| title.clone())
+}
+
+fn build<'a>(book: &'a PdfInfo, list: &'a [Annotation]) -> Result<ExportedBook<'a>, String> {
+    let doc = lopdf::Document::load(&book.path)
+        .map_err(|e| format!("load_pdf_failed|detail={}", e))?;
+    let labels = pdf_info::page_labels(&doc);
+    let chapters = chapters(&pdf_info::extract_outline_from(&doc).unwrap_or_default());
+
+    let annotations = list
+        .iter()
+        .filter(|a| !a.deleted)
+        .map(|a| ExportedAnnotation {
+            kind: a.kind,
+            page: a.page,
+            page_label: (a.page as usize)
+                .checked_sub(1)
+                .and_then(|i| labels.get(i))
+                .cloned()
+                .unwrap_or_else(|| a.page.to_string()),
+            chapter: chapter_for(&chapters, a.page),
+            text: &a.text,
+            note: &a.note,
+            color: &a.color,
+            created_at: &a.created_at,
+            updated_at: &a.updated_at,
+        })
+        .collect();
+
+    Ok(ExportedBook {
+        title: book.title.clone(),
+        author: pdf_info::info_string(&doc, b"Author"),
+        filename: &book.filename,
+        hash: &book.hash,
+        annotations,
+    })
+}
+
+fn to_markdown(book: &ExportedBook) -> String {
+    let mut out = format!("# {}\n\n", book.title);
+    if let Some(author) = &book.author {
+        out.push_str(&format!("*{}*\n\n", author));
+    }
+
+    let mut current_chapter: Option<&str> = None;
+    for a in &book.annotations {
+        if a.chapter.as_deref() != current_chapter {
+            current_chapter = a.chapter.as_deref();
+            if let Some(chapter) = current_chapter {
+                out.push_str(&format!("## {}\n\n", chapter));
+            }
+        }
+        if !a.text.is_empty() {
+            for line in a.text.lines() {
+                out.push_str(&format!("> {}\n", line));
+            }
+            out.push_str(">\n");
+            out.push_str(&format!("> — p. {}\n\n", a.page_label));
+        } else {
+            out.push_str(&format!("**p. {}**\n\n", a.page_label));
+        }
+        if !a.note.is_empty() {
+            out.push_str(&format!("{}\n\n", a.note.trim()));
+        }
+    }
+    out
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn to_csv(book: &ExportedBook) -> String {
+    let mut out = String::from("title,page,page_label,chapter,kind,text,note,color,created_at,updated_at\n");
+    for a in &book.annotations {
+        let kind = serde_json::to_value(a.kind)
+            .ok()
+            .and_then(|v| v.as_str().map(str::to_string))
+            .unwrap_or_default();
+        let fields = [
+            csv_field(&book.title),
+            a.page.to_string(),
+            csv_field(&a.page_label),
+            csv_field(a.chapter.as_deref().unwrap_or("")),
+            kind,
+            csv_field(a.text),
+            csv_field(a.note),
+            csv_field(a.color),
+            csv_field(a.created_at),
+            csv_field(a.updated_at),
+        ];
+        out.push_str(&fields.join(","));
+        out.push('\n');
+    }
+    out
+}
+
+fn render(book: &PdfInfo, list: &[Annotation], format: ExportFormat) -> Result<String, String> {
+    let exported = build(book, list)?;
+    match format {
+        ExportFormat::Markdown => Ok(to_markdown(&exported)),
+        ExportFormat::Json => serde_json::to_string_pretty(&exported)
+            .map_err(|e| format!("serialize_export_failed|detail={}", e)),
+        ExportFormat::Csv => Ok(to_csv(&exported)),
+    }
+}
+
+/// Export one book's highlights and notes to `dest`.
+pub fn export_book(book: &PdfInfo, format: ExportFormat, dest: &Path) -> Result<(), String> {
+    let list = annotations::list(&book.hash)?;
+    let data = render(book, &list, format)?;
+    std::fs::write(dest, data).map_err(|e| format!("write_export_failed|detail={}", e))
+}
+
+fn safe_file_stem(title: &str) -> String {
+    let stem: String = title
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '-',
+            c if c.is_control() => ' ',
+            c => c,
+        })
+        .collect();
+    let stem = stem.trim().trim_start_matches('.').trim();
+    if stem.is_empty() {
+        "Untitled".to_string()
+    } else {
+        stem.chars().take(120).collect()
+    }
+}
+
+/// Export every book that has annotations into `dest_dir`, one file per book named after its
+/// title. Returns the paths written; existing files with the same name are overwritten so that
+/// re-exporting into a notes vault refreshes the digests in place.
+pub fn export_all(books: &[PdfInfo], format: ExportFormat, dest_dir: &Path) -> Result<Vec<String>, String> {
+    if !dest_dir.is_dir() {
+        return Err("export_dir_not_exist".to_string());
+    }
+
+    let mut written = Vec::new();
+    let mut used: Vec<PathBuf> = Vec::new();
+    for book in books {
+        let list = match annotations::list(&book.hash) {
+            Ok(list) if !list.is_empty() => list,
+            Ok(_) => continue,
+            Err(e) => {
+                log::warn!("export_all: skipping {}: {}", book.filename, e);
+                continue;
+            }
+        };
+
+        let stem = safe_file_stem(&book.title);
+        let mut dest = dest_dir.join(format!("{}.{}", stem, format.extension()));
+        if used.contains(&dest) {
+            let short = &book.hash[..8.min(book.hash.len())];
+            dest = dest_dir.join(format!("{} ({}).{}", stem, short, format.extension()));
+        }
+
+        match render(book, &list, format) {
+            Ok(data) => {
+                std::fs::write(&dest, data).map_err(|e| format!("write_export_failed|detail={}", e))?;
+                written.push(dest.to_string_lossy().to_string());
+                used.push(dest);
+            }
+            Err(e) => log::warn!("export_all: failed to render {}: {}", book.filename, e),
+        }
+    }
+
+    log::info!("export_all: wrote {} files to {}", written.len(), dest_dir.display());
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pdf_info::{tests::document, DownloadState};
+    use lopdf::{dictionary, Object};
+
+    /// A three-page book on disk, labelled i, 1, 2, with a chapter starting on page 2.
+    fn book(name: &str) -> PdfInfo {
+        let (mut doc, pages) = document(3);
+        let chapter = doc.add_object(dictionary! {
+            "Title" => Object::string_literal("Chapter One"),
+            "Dest" => vec![pages[1].into(), "Fit".into()],
+        });
+        let outlines = doc.add_object(dictionary! { "Type" => "Outlines", "First" => chapter, "Last" => chapter });
+        let labels = dictionary! {
+            "Nums" => vec![0.into(), dictionary! { "S" => "r" }.into(), 1.into(), dictionary! { "S" => "D" }.into()],
+        };
+        let catalog = doc.catalog_mut().unwrap();
+        catalog.set("Outlines", outlines);
+        catalog.set("PageLabels", labels);
+
+        let path = std::env::temp_dir().join(format!("export-{}-{}.pdf", name, std::process::id()));
+        doc.save(&path).unwrap();
+        PdfInfo {
+            path: path.to_string_lossy().to_string(),
+            filename: "book.pdf".to_string(),
+            title: "A \"Quoted\", Book".to_string(),
+            page_count: 3,
+            hash: "a".repeat(64),
+            file_size: 0,
+            download_state: DownloadState::Local,
+        }
+    }
+
+    fn annotation(page: u32, text: &str, note: &str) -> Annotation {
+        Annotation {
+            id: format!("{}-{}", page, text.len()),
+            hash: "a".repeat(64),
+            kind: AnnotationKind::Highlight,
+            page,
+            quads: Vec::new(),
+            text: text.to_string(),
+            note: note.to_string(),
+            color: "#ffd400".to_string(),
+            created_at: "2026-01-01T00:00:00.000Z".to_string(),
+            updated_at: "2026-01-01T00:00:00.000Z".to_string(),
+            deleted: false,
+        }
+    }
+
+    fn list() -> Vec<Annotation> {
+        let mut deleted = annotation(3, "gone", "");
+        deleted.deleted = true;
+        vec![
+            annotation(1, "Preface line", ""),
+            annotation(2, "first line\nsecond line", "a note, with \"quotes\""),
+            annotation(3, "", "page note"),
+            deleted,
+        ]
+    }
+
+    #[test]
+    fn markdown_groups_by_chapter_with_page_labels() {
+        let book = book("md");
+        let out = render(&book, &list(), ExportFormat::Markdown).unwrap();
+        assert_eq!(
+            out,
+            "# A \"Quoted\", Book\n\n\
+             > Preface line\n>\n> — p. i\n\n\
+             ## Chapter One\n\n\
+             > first line\n> second line\n>\n> — p. 1\n\n\
+             a note, with \"quotes\"\n\n\
+             **p. 2**\n\n\
+             page note\n\n"
+        );
+        std::fs::remove_file(&book.path).unwrap();
+    }
+
+    #[test]
+    fn json_keeps_every_field() {
+        let book = book("json");
+        let out = render(&book, &list(), ExportFormat::Json).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(value["title"], "A \"Quoted\", Book");
+        let annotations = value["annotations"].as_array().unwrap();
+        assert_eq!(annotations.len(), 3);
+        assert_eq!(annotations[0]["chapter"], serde_json::Value::Null);
+        assert_eq!(annotations[1]["text"], "first line\nsecond line");
+        assert_eq!(annotations[1]["page_label"], "1");
+        assert_eq!(annotations[1]["chapter"], "Chapter One");
+        assert_eq!(annotations[2]["kind"], "highlight");
+        std::fs::remove_file(&book.path).unwrap();
+    }
+
+    #[test]
+    fn csv_quotes_fields_that_need_it() {
+        let book = book("csv");
+        let out = render(&book, &list(), ExportFormat::Csv).unwrap();
+        let mut lines = out.split_terminator('\n');
+        assert_eq!(
+            lines.next(),
+            Some("title,page,page_label,chapter,kind,text,note,color,created_at,updated_at")
+        );
+        assert_eq!(
+            out.lines().nth(2),
+            Some("\"A \"\"Quoted\"\", Book\",2,1,Chapter One,highlight,\"first line")
+        );
+        assert!(out.contains("second line\",\"a note, with \"\"quotes\"\"\",#ffd400,"));
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a\rb"), "\"a\rb\"");
+        std::fs::remove_file(&book.path).unwrap();
+    }
+
+    #[test]
+    fn file_stems_are_safe_on_every_platform() {
+        assert_eq!(safe_file_stem("Dune: Part 1/2"), "Dune- Part 1-2");
+        assert_eq!(safe_file_stem("a*b?c\"d<e>f|g\\h"), "a-b-c-d-e-f-g-h");
+        assert_eq!(safe_file_stem("..hidden"), "hidden");
+        assert_eq!(safe_file_stem("tab\there"), "tab here");
+        assert_eq!(safe_file_stem("  "), "Untitled");
+        assert_eq!(safe_file_stem("...").as_str(), "Untitled");
+        assert_eq!(safe_file_stem(&"x".repeat(200)).chars().count(), 120);
+    }
+}
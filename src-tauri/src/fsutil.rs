@@ -0,0 +1,317 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static TMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Last good copy kept next to a file written with a backup: `<name>.bak`.
+pub fn backup_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".bak");
+    path.with_file_name(name)
+}
+
+fn tmp_path(path: &Path) -> PathBuf {
+    let seq = TMP_COUNTER.fetch_add(1, Ordering::Relaxed);
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}.tmp", seq));
+    path.with_file_name(name)
+}
+
+/// Make a rename or unlink in `dir` durable. Not supported (nor needed) on Windows.
+fn sync_dir(dir: &Path) -> std::io::Result<()> {
+    #[cfg(unix)]
+    File::open(dir)?.sync_all()?;
+    #[cfg(not(unix))]
+    let _ = dir;
+    Ok(())
+}
+
+/// Replace `path` atomically and durably: write a temp file, fsync it, rename it over the
+/// target and fsync the directory. A crash leaves either the old or the new file, plus at
+/// most a `*.tmp` that `recover_temp_files` cleans up. With `keep_backup`, the previous
+/// version stays available as `<name>.bak` for `read_with_fallback`.
+pub fn write_atomic(path: &Path, data: &[u8], keep_backup: bool) -> std::io::Result<()> {
+    write_atomic_with(path, data, keep_backup, false)
+}
+
+/// `write_atomic` for files holding secrets: on Unix the file is readable by its owner only
+/// (0600) from the moment it is created. Windows keeps the per-user folder's permissions.
+pub fn write_private(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    write_atomic_with(path, data, false, true)
+}
+
+/// Restrict an existing file to its owner, as `write_private` creates them. Unix only.
+pub fn make_private(path: &Path) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+fn create_tmp(tmp: &Path, private: bool) -> std::io::Result<File> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    if private {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    #[cfg(not(unix))]
+    let _ = private;
+    options.open(tmp)
+}
+
+fn write_atomic_with(path: &Path, data: &[u8], keep_backup: bool, private: bool) -> std::io::Result<()> {
+    let tmp = tmp_path(path);
+    let result = (|| {
+        let mut file = create_tmp(&tmp, private)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+        drop(file);
+
+        if keep_backup && path.exists() {
+            let bak = backup_path(path);
+            let _ = std::fs::remove_file(&bak);
+            if std::fs::hard_link(path, &bak).is_err() {
+                std::fs::copy(path, &bak)?;
+            }
+        }
+        std::fs::rename(&tmp, path)?;
+        if let Some(dir) = path.parent() {
+            sync_dir(dir)?;
+        }
+        Ok(())
+    })();
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+    result
+}
+
+/// Copy a file so that `to` is either absent or complete, the same way `write_atomic` writes.
+pub fn copy_atomic(from: &Path, to: &Path) -> std::io::Result<u64> {
+    let tmp = tmp_path(to);
+    let result = (|| {
+        let bytes = std::fs::copy(from, &tmp)?;
+        File::open(&tmp)?.sync_all()?;
+        std::fs::rename(&tmp, to)?;
+        if let Some(dir) = to.parent() {
+            sync_dir(dir)?;
+        }
+        Ok(bytes)
+    })();
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+    result
+}
+
+/// Read and parse a file; if it is corrupt, fall back to its `.bak` copy and restore it.
+/// Returns Ok(None) when the file does not exist: a missing file was deleted (or never
+/// written), and its backup is not brought back.
+pub fn read_with_fallback<T, E>(
+    path: &Path,
+    parse: impl Fn(&str) -> Result<T, E>,
+) -> Result<Option<T>, ReadError<E>> {
+    let primary = match std::fs::read_to_string(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(ReadError::Io(e)),
+    };
+    let parse_error = match parse(&primary) {
+        Ok(value) => return Ok(Some(value)),
+        Err(e) => e,
+    };
+
+    let backup = std::fs::read_to_string(backup_path(path)).ok();
+    match backup.as_deref().map(&parse) {
+        Some(Ok(value)) => {
+            log::warn!("{} is corrupt, using last good copy", path.display());
+            if let Some(data) = &backup {
+                let _ = write_atomic(path, data.as_bytes(), false);
+            }
+            Ok(Some(value))
+        }
+        _ => Err(ReadError::Parse(parse_error)),
+    }
+}
+
+pub enum ReadError<E> {
+    Io(std::io::Error),
+    Parse(E),
+}
+
+/// Target a leftover temp file was meant to replace: `<name>.<seq>.tmp` or `<name>.tmp`.
+fn temp_target(tmp: &Path) -> Option<PathBuf> {
+    let name = tmp.file_name()?.to_str()?.strip_suffix(".tmp")?;
+    let name = match name.rsplit_once('.') {
+        Some((stem, seq)) if !seq.is_empty() && seq.chars().all(|c| c.is_ascii_digit()) => stem,
+        _ => name,
+    };
+    Some(tmp.with_file_name(name))
+}
+
+fn is_valid_json(path: &Path) -> bool {
+    std::fs::read(path)
+        .ok()
+        .is_some_and(|data| serde_json::from_slice::<serde_json::Value>(&data).is_ok())
+}
+
+/// `recover_temp_files` for a sync folder: the folder and the folders directly in it (one per
+/// book, `Annotations`). The folder may be shared with other files, so only temp files of
+/// `.json` targets are touched.
+pub fn recover_sync_temp_files(root: &Path) -> (usize, usize) {
+    let is_ours = |target: &Path| target.extension().is_some_and(|ext| ext == "json");
+    let (mut promoted, mut removed) = recover_in(root, &is_ours);
+    if let Ok(entries) = std::fs::read_dir(root) {
+        for entry in entries.flatten() {
+            if entry.file_type().is_ok_and(|t| t.is_dir()) {
+                let (p, r) = recover_in(&entry.path(), &is_ours);
+                promoted += p;
+                removed += r;
+            }
+        }
+    }
+    (promoted, removed)
+}
+
+/// Clean up temp files left by writes interrupted by a crash. A complete temp file whose
+/// target is missing or corrupt is promoted to the target; everything else is removed.
+/// Returns (promoted, removed).
+pub fn recover_temp_files(dir: &Path) -> (usize, usize) {
+    recover_in(dir, &|_| true)
+}
+
+fn recover_in(dir: &Path, is_ours: &dyn Fn(&Path) -> bool) -> (usize, usize) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return (0, 0);
+    };
+    let mut promoted = 0;
+    let mut removed = 0;
+    for entry in entries.flatten() {
+        let tmp = entry.path();
+        let Some(target) = temp_target(&tmp).filter(|target| is_ours(target)) else {
+            continue;
+        };
+        if !tmp.is_file() {
+            continue;
+        }
+        if !is_valid_json(&target) && is_valid_json(&tmp) && std::fs::rename(&tmp, &target).is_ok() {
+            log::warn!("recovered {} from interrupted write", target.display());
+            promoted += 1;
+        } else if std::fs::remove_file(&tmp).is_ok() {
+            removed += 1;
+        }
+    }
+    if promoted + removed > 0 {
+        let _ = sync_dir(dir);
+        log::info!(
+            "temp file recovery in {}: {} promoted, {} removed",
+            dir.display(),
+            promoted,
+            removed
+        );
+    }
+    (promoted, removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("fsutil-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn parse(data: &str) -> Result<serde_json::Value, serde_json::Error> {
+        serde_json::from_str(data)
+    }
+
+    #[test]
+    fn write_atomic_keeps_the_previous_version_as_backup() {
+        let dir = scratch("backup");
+        let path = dir.join("a.json");
+        write_atomic(&path, b"1", true).unwrap();
+        assert!(!backup_path(&path).exists());
+        write_atomic(&path, b"2", true).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "2");
+        assert_eq!(std::fs::read_to_string(backup_path(&path)).unwrap(), "1");
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2, "no temp file left behind");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn corrupt_file_falls_back_to_backup_and_is_restored() {
+        let dir = scratch("corrupt");
+        let path = dir.join("a.json");
+        std::fs::write(backup_path(&path), "{\"page\":4}").unwrap();
+        std::fs::write(&path, "{\"pa").unwrap();
+        let value = read_with_fallback(&path, parse).ok().flatten().unwrap();
+        assert_eq!(value["page"], 4);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "{\"page\":4}");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn missing_file_is_not_revived_from_backup() {
+        let dir = scratch("missing");
+        let path = dir.join("a.json");
+        std::fs::write(backup_path(&path), "{}").unwrap();
+        assert!(matches!(read_with_fallback(&path, parse), Ok(None)));
+        assert!(!path.exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn corrupt_file_without_backup_is_a_parse_error() {
+        let dir = scratch("nobackup");
+        let path = dir.join("a.json");
+        std::fs::write(&path, "not json").unwrap();
+        assert!(matches!(read_with_fallback(&path, parse), Err(ReadError::Parse(_))));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn temp_targets() {
+        assert_eq!(temp_target(Path::new("/d/a.json.12.tmp")), Some(PathBuf::from("/d/a.json")));
+        assert_eq!(temp_target(Path::new("/d/a.json.tmp")), Some(PathBuf::from("/d/a.json")));
+        assert_eq!(temp_target(Path::new("/d/a.json")), None);
+    }
+
+    #[test]
+    fn sync_folder_recovery_only_touches_json_temp_files() {
+        let dir = scratch("recover");
+        let book = dir.join("abc");
+        std::fs::create_dir_all(&book).unwrap();
+        std::fs::write(book.join("device.json.3.tmp"), "{\"page\":2}").unwrap();
+        std::fs::write(dir.join("old.json"), "{}").unwrap();
+        std::fs::write(dir.join("old.json.7.tmp"), "{}").unwrap();
+        std::fs::write(dir.join("someone-else.txt.tmp"), "keep").unwrap();
+
+        assert_eq!(recover_sync_temp_files(&dir), (1, 1));
+        assert_eq!(std::fs::read_to_string(book.join("device.json")).unwrap(), "{\"page\":2}");
+        assert!(!dir.join("old.json.7.tmp").exists());
+        assert!(dir.join("someone-else.txt.tmp").exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn private_files_are_owner_only() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = scratch("private");
+        let path = dir.join("settings.json");
+        write_private(&path, b"{}").unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
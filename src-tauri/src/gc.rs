@@ -0,0 +1,208 @@
+use serde::Serialize;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::settings::{self, SyncBackendKind};
+use crate::{annotations, book_id, hash_cache, icloud, icloud_placeholder, pdf_info, progress, sessions, sync_backend};
+
+/// Reading data (progress, annotations, sessions) touched more recently than this is kept even
+/// without a matching book, in case the book is still on its way (e.g. being downloaded from
+/// iCloud on this device).
+const ORPHAN_GRACE_DAYS: i64 = 14;
+/// Delay before the first background run, so it does not compete with the startup scan.
+const BACKGROUND_DELAY: Duration = Duration::from_secs(5 * 60);
+const BACKGROUND_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+static GC_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct GcReport {
+    pub dry_run: bool,
+    /// PDFs found in the Books folder.
+    pub books: usize,
+    /// False when some books could not be identified (e.g. iCloud placeholders this device
+    /// never downloaded). Reading data is then left alone, since it may belong to one of them.
+    pub library_complete: bool,
+    /// Central records are only checked when the Books folder itself is shared through the
+    /// same store; otherwise they may belong to books that exist only on other devices.
+    pub central_checked: bool,
+    /// Hashes whose local progress file was (or would be) removed.
+    pub local_progress: Vec<String>,
+    /// Hashes whose central records were (or would be) removed.
+    pub central_progress: Vec<String>,
+    /// Hashes whose annotation sidecar on this device was (or would be) removed.
+    pub local_annotations: Vec<String>,
+    /// Hashes whose central annotation sidecar was (or would be) removed.
+    pub central_annotations: Vec<String>,
+    /// Hashes whose reading session log was (or would be) removed.
+    pub sessions: Vec<String>,
+    pub hash_cache_entries: usize,
+    pub pdf_cache_entries: usize,
+}
+
+/// Hashes of every book in the library, or None if some of them could not be identified.
+fn library_hashes() -> Option<HashSet<String>> {
+    let entries = std::fs::read_dir(icloud::get_books_dir()).ok()?;
+    let mut hashes = HashSet::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        // Not downloaded: the ID this device last saw at its path, if it ever had the book
+        if icloud_placeholder::is_placeholder(&path) {
+            match pdf_info::placeholder_info(&path) {
+                Ok(info) if !info.hash.is_empty() => {
+                    hashes.insert(info.hash);
+                    continue;
+                }
+                _ => {
+                    log::info!("gc: {} was never downloaded here, skipping progress cleanup", name);
+                    return None;
+                }
+            }
+        }
+        if !path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("pdf")) {
+            continue;
+        }
+        match pdf_info::compute_hash_cached(&path) {
+            // Its data may still be under the full ID from before; wait until it is confirmed
+            Ok(hash) if book_id::is_provisional(&hash) => {
+                log::info!("gc: {} is not fully hashed yet, skipping cleanup", path.display());
+                return None;
+            }
+            Ok(hash) => {
+                hashes.insert(hash);
+            }
+            Err(e) => {
+                log::warn!("gc: cannot hash {}: {}, skipping progress cleanup", path.display(), e);
+                return None;
+            }
+        }
+    }
+    Some(hashes)
+}
+
+/// Find progress, annotations and reading sessions of books no longer in the library and stale
+/// cache entries, and remove them unless `dry_run` is set.
+pub fn run(dry_run: bool) -> GcReport {
+    let _guard = GC_LOCK.lock().unwrap();
+    let start = Instant::now();
+    let mut report = GcReport {
+        dry_run,
+        ..Default::default()
+    };
+
+    // Stale cache entries first, so the library scan below re-hashes only what changed
+    report.hash_cache_entries = hash_cache::prune(dry_run);
+    report.pdf_cache_entries = pdf_info::prune_pdf_cache(dry_run);
+
+    let live = library_hashes();
+    report.library_complete = live.is_some();
+    // An empty library is far more likely a missing or unmounted folder than a reader who
+    // removed every book; do not treat all progress as orphaned in that case
+    let Some(live) = live.filter(|hashes| !hashes.is_empty()) else {
+        log_report(&report, start);
+        return report;
+    };
+    report.books = live.len();
+
+    let backend = sync_backend::current();
+    let shares_books = settings::load_device().sync.backend == SyncBackendKind::Icloud;
+    let central = backend.as_deref().filter(|_| shares_books);
+    report.central_checked = central.is_some();
+
+    // Records older devices still write under a book's short ID belong to the book
+    let live: HashSet<String> = live
+        .iter()
+        .flat_map(|hash| std::iter::once(hash.clone()).chain(book_id::short_alias(hash).map(str::to_string)))
+        .collect();
+    let cutoff = chrono::Utc::now() - chrono::Duration::days(ORPHAN_GRACE_DAYS);
+    // A book is orphaned when it is not in the library and none of its reading data (on this
+    // device or, when checked, central) was touched within the grace period
+    let central_backend = backend.as_deref();
+    let is_orphan = |hash: &String| {
+        !live.contains(hash)
+            && !book_id::is_provisional(hash)
+            && [
+                progress::last_read(hash, central_backend),
+                annotations::last_changed(hash, central_backend),
+                sessions::last_activity(hash),
+            ]
+            .into_iter()
+            .flatten()
+            .max()
+            .map_or(true, |t| t < cutoff)
+    };
+
+    report.local_progress = progress::local_hashes().into_iter().filter(|h| is_orphan(h)).collect();
+    report.local_annotations = annotations::local_hashes().into_iter().filter(|h| is_orphan(h)).collect();
+    report.sessions = sessions::hashes().into_iter().filter(|h| is_orphan(h)).collect();
+    if let Some(central) = central {
+        match progress::central_hashes(central) {
+            Ok(hashes) => report.central_progress = hashes.into_iter().filter(|h| is_orphan(h)).collect(),
+            Err(e) => log::warn!("gc: cannot list central progress: {}", e),
+        }
+        match annotations::central_hashes(central) {
+            Ok(hashes) => report.central_annotations = hashes.into_iter().filter(|h| is_orphan(h)).collect(),
+            Err(e) => log::warn!("gc: cannot list central annotations: {}", e),
+        }
+    }
+
+    if !dry_run {
+        for hash in &report.local_progress {
+            progress::delete_local(hash);
+        }
+        for hash in &report.local_annotations {
+            annotations::delete_local(hash);
+        }
+        for hash in &report.sessions {
+            sessions::delete(hash);
+        }
+        if let Some(central) = central {
+            for hash in &report.central_progress {
+                progress::delete_central(central, hash);
+            }
+            for hash in &report.central_annotations {
+                annotations::delete_central(central, hash);
+            }
+        }
+    }
+    log_report(&report, start);
+    report
+}
+
+fn log_report(report: &GcReport, start: Instant) {
+    log::info!(
+        "gc{}: {} books, local progress={}, central progress={}, local annotations={}, central annotations={}, \
+         sessions={}, hash cache={}, pdf cache={} in {}ms",
+        if report.dry_run { " (dry run)" } else { "" },
+        report.books,
+        report.local_progress.len(),
+        report.central_progress.len(),
+        report.local_annotations.len(),
+        report.central_annotations.len(),
+        report.sessions.len(),
+        report.hash_cache_entries,
+        report.pdf_cache_entries,
+        start.elapsed().as_millis()
+    );
+}
+
+/// Background cleanup: once shortly after startup and then daily, while enabled in the device
+/// settings. Returns when `stop` is set.
+pub fn run_periodic(stop: Arc<AtomicBool>) {
+    let mut next = Instant::now() + BACKGROUND_DELAY;
+    while !stop.load(Ordering::Relaxed) {
+        std::thread::sleep(Duration::from_secs(1));
+        if Instant::now() < next {
+            continue;
+        }
+        next = Instant::now() + BACKGROUND_INTERVAL;
+        if settings::load_device().auto_gc {
+            run(false);
+        }
+    }
+}
@@ -0,0 +1,332 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::{fsutil, icloud, paths};
+
+/// Unsaved changes are written once no new entry arrived for this long...
+const QUIET_PERIOD: Duration = Duration::from_secs(2);
+/// ...or at the latest this long after the first one, during a long first scan.
+const MAX_DELAY: Duration = Duration::from_secs(10);
+
+/// Book IDs by `<canonical path>:<mtime secs>:<size>`, so an unchanged file is not hashed again.
+#[derive(Default)]
+struct Cache {
+    entries: HashMap<String, String>,
+    /// When the first and the latest unsaved change happened.
+    dirty: Option<(Instant, Instant)>,
+    /// Written by older versions into the synced base directory; removed after the first save.
+    legacy_file: Option<PathBuf>,
+}
+
+impl Cache {
+    fn mark_dirty(&mut self) {
+        let now = Instant::now();
+        self.dirty = Some(self.dirty.map_or((now, now), |(first, _)| (first, now)));
+    }
+}
+
+fn cache_file() -> PathBuf {
+    paths::data_dir().join("hash-cache.json")
+}
+
+fn read_entries(path: &Path) -> Option<HashMap<String, String>> {
+    let data = std::fs::read_to_string(path).ok()?;
+    match serde_json::from_str(&data) {
+        Ok(entries) => Some(entries),
+        Err(e) => {
+            log::warn!("Hash cache {} is unreadable, starting over: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+fn cache() -> &'static Mutex<Cache> {
+    static CACHE: OnceLock<Mutex<Cache>> = OnceLock::new();
+    CACHE.get_or_init(|| {
+        let mut cache = Cache::default();
+        let legacy = icloud::get_base_dir().join("hash-cache.json");
+        match read_entries(&cache_file()) {
+            Some(entries) => cache.entries = entries,
+            None => {
+                if let Some(entries) = read_entries(&legacy) {
+                    log::info!("Hash cache: moving {} entries out of the synced folder", entries.len());
+                    cache.entries = entries;
+                    cache.mark_dirty();
+                }
+            }
+        }
+        if legacy.exists() {
+            cache.legacy_file = Some(legacy);
+            cache.mark_dirty();
+        }
+        // Entries for missing files stay until `run_saver` prunes them, so a library moved at
+        // startup can still carry them over (see `carry_over`)
+        log::info!("Hash cache loaded: {} entries", cache.entries.len());
+        Mutex::new(cache)
+    })
+}
+
+/// Symlinks, `..` and redundant separators resolved, so the same file always gets the same
+/// key however its path was spelled. Of a path that no longer exists, the part that still does
+/// is resolved, so a folder that was moved away still matches its entries.
+fn canonical(path: &Path) -> PathBuf {
+    if let Ok(resolved) = path.canonicalize() {
+        return resolved;
+    }
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) if !parent.as_os_str().is_empty() => canonical(parent).join(name),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Path, mtime and size of a cache key. Split from the right, since the path may itself
+/// contain colons (e.g. `C:\` on Windows).
+fn parse_key(key: &str) -> Option<(&str, u64, u64)> {
+    let mut parts = key.rsplitn(3, ':');
+    let size = parts.next()?.parse().ok()?;
+    let mtime = parts.next()?.parse().ok()?;
+    let path = parts.next().filter(|path| !path.is_empty())?;
+    Some((path, mtime, size))
+}
+
+fn path_of(key: &str) -> Option<&str> {
+    parse_key(key).map(|(path, _, _)| path)
+}
+
+fn stamp(path: &Path) -> Option<(u64, u64)> {
+    let meta = std::fs::metadata(path).ok()?;
+    let mtime_secs = meta
+        .modified()
+        .ok()?
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    Some((mtime_secs, meta.len()))
+}
+
+/// Cache key for a file as it is now, and its size.
+pub fn key_for(path: &Path) -> Result<(String, u64), String> {
+    let meta = std::fs::metadata(path).map_err(|e| format!("get_metadata_failed|detail={}", e))?;
+    let size = meta.len();
+    let mtime_secs = meta
+        .modified()
+        .map_err(|e| format!("get_mtime_failed|detail={}", e))?
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    Ok((format!("{}:{}:{}", canonical(path).display(), mtime_secs, size), size))
+}
+
+pub fn len() -> usize {
+    cache().lock().unwrap().entries.len()
+}
+
+pub fn get(key: &str) -> Option<String> {
+    cache().lock().unwrap().entries.get(key).cloned()
+}
+
+/// Store an ID, replacing entries for earlier versions of the same file. Saved in the
+/// background (see `run_saver`).
+pub fn insert(key: String, hash: String) {
+    let mut cache = cache().lock().unwrap();
+    if let Some(path) = path_of(&key).map(str::to_string) {
+        cache.entries.retain(|k, _| path_of(k) != Some(path.as_str()));
+    }
+    cache.entries.insert(key, hash);
+    cache.mark_dirty();
+}
+
+/// Forget every entry for a path (e.g. the file was renamed).
+pub fn remove_path(path: &Path) {
+    let target = canonical(path).display().to_string();
+    let given = path.display().to_string();
+    let mut cache = cache().lock().unwrap();
+    let before = cache.entries.len();
+    cache.entries.retain(|k, _| path_of(k).is_some_and(|p| p != target && p != given));
+    if cache.entries.len() != before {
+        cache.mark_dirty();
+    }
+}
+
+/// Give files copied from `from` to `to` (a library moving to another folder) the IDs of their
+/// originals, so they are not hashed again. Only files of unchanged size are carried over.
+/// Returns how many entries were added.
+pub fn carry_over(from: &Path, to: &Path) -> usize {
+    let resolved = canonical(from);
+    let originals: Vec<(PathBuf, u64, String)> = {
+        let cache = cache().lock().unwrap();
+        cache
+            .entries
+            .iter()
+            .filter_map(|(key, hash)| {
+                let (path, _, size) = parse_key(key)?;
+                let path = Path::new(path);
+                let rel = path.strip_prefix(&resolved).or_else(|_| path.strip_prefix(from)).ok()?;
+                Some((to.join(rel), size, hash.clone()))
+            })
+            .collect()
+    };
+    let mut added = 0;
+    for (path, size, hash) in originals {
+        if let Ok((key, new_size)) = key_for(&path) {
+            if new_size == size {
+                insert(key, hash);
+                added += 1;
+            }
+        }
+    }
+    added
+}
+
+/// Drop entries for files that were moved, deleted or modified since they were hashed.
+/// Returns how many entries were (or, with `dry_run`, would be) removed.
+pub fn prune(dry_run: bool) -> usize {
+    let live = |key: &str| {
+        parse_key(key).is_some_and(|(path, mtime, size)| stamp(Path::new(path)) == Some((mtime, size)))
+    };
+    let mut cache = cache().lock().unwrap();
+    let stale: Vec<String> = cache.entries.keys().filter(|k| !live(k)).cloned().collect();
+    if !dry_run && !stale.is_empty() {
+        for key in &stale {
+            cache.entries.remove(key);
+        }
+        cache.mark_dirty();
+    }
+    stale.len()
+}
+
+/// Write unsaved changes now.
+pub fn flush() {
+    let (data, legacy) = {
+        let mut cache = cache().lock().unwrap();
+        if cache.dirty.take().is_none() {
+            return;
+        }
+        match serde_json::to_string(&cache.entries) {
+            Ok(data) => (data, cache.legacy_file.take()),
+            Err(e) => {
+                log::warn!("Failed to serialize hash cache: {}", e);
+                return;
+            }
+        }
+    };
+    if let Err(e) = fsutil::write_atomic(&cache_file(), data.as_bytes(), false) {
+        log::warn!("Failed to save hash cache: {}", e);
+        let mut cache = cache().lock().unwrap();
+        cache.mark_dirty();
+        cache.legacy_file = cache.legacy_file.take().or(legacy);
+        return;
+    }
+    if let Some(legacy) = legacy {
+        let _ = std::fs::remove_file(legacy);
+    }
+}
+
+/// Drop stale entries, then save changes once they settle (see QUIET_PERIOD and MAX_DELAY)
+/// until `stop` is set, and once more.
+pub fn run_saver(stop: Arc<AtomicBool>) {
+    let evicted = prune(false);
+    if evicted > 0 {
+        log::info!("Hash cache: {} entries for moved, deleted or modified files evicted", evicted);
+    }
+    while !stop.load(Ordering::Relaxed) {
+        std::thread::sleep(Duration::from_millis(500));
+        let due = cache()
+            .lock()
+            .unwrap()
+            .dirty
+            .is_some_and(|(first, last)| last.elapsed() >= QUIET_PERIOD || first.elapsed() >= MAX_DELAY);
+        if due {
+            flush();
+        }
+    }
+    flush();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("hash-cache-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn parses_keys() {
+        assert_eq!(parse_key("/books/a.pdf:1700000000:4096"), Some(("/books/a.pdf", 1_700_000_000, 4096)));
+        assert_eq!(parse_key(r"C:\Books\a.pdf:1:2"), Some((r"C:\Books\a.pdf", 1, 2)));
+        assert_eq!(parse_key("/books/12:30 notes.pdf:0:0"), Some(("/books/12:30 notes.pdf", 0, 0)));
+        assert_eq!(path_of("/books/a.pdf:1:2"), Some("/books/a.pdf"));
+    }
+
+    #[test]
+    fn rejects_malformed_keys() {
+        for key in ["", "/books/a.pdf", "/books/a.pdf:1", ":1:2", "/books/a.pdf:x:2", "/books/a.pdf:1:-2"] {
+            assert_eq!(parse_key(key), None, "{}", key);
+        }
+    }
+
+    #[test]
+    fn key_for_matches_the_file() {
+        let root = temp_dir("key-for");
+        let path = root.join("book.pdf");
+        std::fs::write(&path, b"%PDF-1.4").unwrap();
+        let (key, size) = key_for(&path).unwrap();
+        let (parsed, mtime, parsed_size) = parse_key(&key).unwrap();
+        assert_eq!(Path::new(parsed), canonical(&path));
+        assert_eq!((size, parsed_size), (8, 8));
+        assert_eq!(stamp(&path), Some((mtime, size)));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn carry_over_after_the_originals_moved() {
+        let root = temp_dir("carry-over");
+        // However the temporary folder is spelled (on macOS it is behind a symlink)
+        let spellings = [root.clone(), root.canonicalize().unwrap()];
+        std::fs::create_dir_all(root.join("0")).unwrap();
+        std::fs::write(root.join("0").join("book.pdf"), b"%PDF-1.4 contents").unwrap();
+        let (key, _) = key_for(&root.join("0").join("book.pdf")).unwrap();
+        insert(key, "a".repeat(64));
+
+        // The whole folder moved before the cache was used again
+        for (i, spelling) in spellings.iter().enumerate() {
+            let (from, to) = (spelling.join(i.to_string()), spelling.join((i + 1).to_string()));
+            std::fs::rename(&from, &to).unwrap();
+            assert_eq!(carry_over(&from, &to), 1, "{}", from.display());
+            let (key, _) = key_for(&to.join("book.pdf")).unwrap();
+            assert_eq!(get(&key), Some("a".repeat(64)));
+        }
+
+        // A copy whose size differs is hashed again
+        let elsewhere = root.join("elsewhere");
+        std::fs::create_dir_all(&elsewhere).unwrap();
+        std::fs::write(elsewhere.join("book.pdf"), b"%PDF-1.4 changed contents").unwrap();
+        assert_eq!(carry_over(&root.join("2"), &elsewhere), 0);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn carry_over_through_a_symlinked_root() {
+        let root = temp_dir("carry-over-link");
+        std::fs::create_dir_all(root.join("real").join("old")).unwrap();
+        std::os::unix::fs::symlink(root.join("real"), root.join("link")).unwrap();
+        let (from, to) = (root.join("link").join("old"), root.join("link").join("new"));
+        std::fs::write(from.join("book.pdf"), b"%PDF-1.4 linked").unwrap();
+        let (key, _) = key_for(&from.join("book.pdf")).unwrap();
+        insert(key, "b".repeat(64));
+
+        std::fs::rename(&from, &to).unwrap();
+        assert_eq!(carry_over(&from, &to), 1);
+        let (key, _) = key_for(&to.join("book.pdf")).unwrap();
+        assert_eq!(get(&key), Some("b".repeat(64)));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -1,5 +1,7 @@
 use std::path::PathBuf;
 
+use crate::{library, paths, settings};
+
 const ICLOUD_CONTAINER: &str = "com~apple~CloudDocs";
 const APP_FOLDER: &str = "PDFReader";
 
@@ -12,11 +14,9 @@ pub fn get_icloud_base() -> Option<PathBuf> {
     })
 }
 
-pub fn get_local_fallback() -> Option<PathBuf> {
-    dirs::home_dir().map(|home| home.join(".pdf-reader"))
-}
-
-pub fn get_base_dir() -> PathBuf {
+/// Where the default library belongs: the iCloud Drive app folder when iCloud Drive is
+/// available, the local library folder otherwise (see `paths::local_library_dir`).
+pub fn detect_default_base_dir() -> PathBuf {
     if let Some(icloud) = get_icloud_base() {
         // Check that the iCloud CloudDocs container exists
         let cloud_docs = dirs::home_dir()
@@ -25,11 +25,25 @@ pub fn get_base_dir() -> PathBuf {
             return icloud;
         }
     }
-    get_local_fallback().unwrap_or_else(|| {
-        let mut tmp = std::env::temp_dir();
-        tmp.push("pdf-reader");
-        tmp
-    })
+    paths::local_library_dir()
+}
+
+/// Root of the default library. It stays where it was last used when iCloud Drive is turned
+/// on or off, until the reader moves it (see `storage_migration`).
+pub fn get_default_base_dir() -> PathBuf {
+    settings::load_device()
+        .default_root
+        .map(PathBuf::from)
+        .filter(|root| root.is_dir())
+        .unwrap_or_else(detect_default_base_dir)
+}
+
+/// Root of the active library (see `library`).
+pub fn get_base_dir() -> PathBuf {
+    match library::active() {
+        Some(lib) => PathBuf::from(lib.root),
+        None => get_default_base_dir(),
+    }
 }
 
 pub fn get_books_dir() -> PathBuf {
@@ -40,30 +54,58 @@ pub fn get_progress_dir() -> PathBuf {
     get_base_dir().join("Progress")
 }
 
+pub fn get_annotations_dir() -> PathBuf {
+    get_progress_dir().join("Annotations")
+}
+
+/// Device-local data of the active library. The default library keeps it directly in the app
+/// data folder, where it was before there were several libraries.
+pub fn get_library_data_dir() -> PathBuf {
+    match library::active() {
+        Some(lib) => paths::data_dir().join("Libraries").join(lib.id),
+        None => paths::data_dir(),
+    }
+}
+
 pub fn get_local_progress_dir() -> PathBuf {
-    dirs::home_dir()
-        .unwrap_or_else(std::env::temp_dir)
-        .join("Library")
-        .join("Application Support")
-        .join("com.yangguanlin.pdf-reader")
-        .join("Progress")
+    get_library_data_dir().join("Progress")
+}
+
+pub fn get_sessions_dir() -> PathBuf {
+    get_library_data_dir().join("Sessions")
+}
+
+pub fn get_local_annotations_dir() -> PathBuf {
+    get_local_progress_dir().join("Annotations")
 }
 
+/// Whether the active library lives in iCloud Drive (the app folder or any other folder there).
 pub fn is_icloud_active() -> bool {
     let base = get_base_dir();
-    get_icloud_base().map_or(false, |icloud| base == icloud)
+    let cloud_docs = dirs::home_dir()
+        .map(|h| h.join("Library").join("Mobile Documents").join(ICLOUD_CONTAINER));
+    cloud_docs.map_or(false, |docs| docs.exists() && base.starts_with(docs))
 }
 
 pub fn ensure_directories() -> Result<(), String> {
     let books = get_books_dir();
     let progress = get_progress_dir();
     let local_progress = get_local_progress_dir();
+    let annotations = get_annotations_dir();
+    let local_annotations = get_local_annotations_dir();
+    let sessions = get_sessions_dir();
 
     std::fs::create_dir_all(&books).map_err(|e| format!("create_books_dir_failed|detail={}", e))?;
     std::fs::create_dir_all(&progress)
         .map_err(|e| format!("create_progress_dir_failed|detail={}", e))?;
     std::fs::create_dir_all(&local_progress)
         .map_err(|e| format!("create_local_progress_dir_failed|detail={}", e))?;
+    std::fs::create_dir_all(&annotations)
+        .map_err(|e| format!("create_annotations_dir_failed|detail={}", e))?;
+    std::fs::create_dir_all(&local_annotations)
+        .map_err(|e| format!("create_local_annotations_dir_failed|detail={}", e))?;
+    std::fs::create_dir_all(&sessions)
+        .map_err(|e| format!("create_sessions_dir_failed|detail={}", e))?;
 
     log::info!("Directories initialized at: {}", get_base_dir().display());
     log::info!("Local progress dir: {}", local_progress.display());
@@ -0,0 +1,158 @@
+use std::path::{Path, PathBuf};
+
+/// What iCloud Drive leaves in a folder for a file it has not downloaded: `.Name.pdf.icloud`,
+/// a binary property list with the original name and size.
+#[derive(Debug, Clone)]
+pub struct Placeholder {
+    pub name: String,
+    pub size: Option<u64>,
+}
+
+/// The original name encoded in a placeholder's file name, or None if it is not one.
+pub fn original_name(file_name: &str) -> Option<&str> {
+    file_name
+        .strip_prefix('.')?
+        .strip_suffix(".icloud")
+        .filter(|name| !name.is_empty())
+}
+
+pub fn is_placeholder(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .and_then(original_name)
+        .is_some()
+}
+
+/// Read a placeholder. The name falls back to the one encoded in the file name when the
+/// property list is unreadable or names something outside the folder.
+pub fn read(path: &Path) -> Result<Placeholder, String> {
+    let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+    let fallback = original_name(file_name)
+        .ok_or_else(|| format!("not_icloud_placeholder|path={}", path.display()))?;
+
+    let dict = match plist::Value::from_file(path) {
+        Ok(plist::Value::Dictionary(dict)) => dict,
+        Ok(_) => {
+            log::debug!("icloud: {} is not a dictionary", path.display());
+            plist::Dictionary::new()
+        }
+        Err(e) => {
+            log::debug!("icloud: cannot read {}: {}", path.display(), e);
+            plist::Dictionary::new()
+        }
+    };
+    let name = dict
+        .get("NSURLNameKey")
+        .and_then(|v| v.as_string())
+        .filter(|name| !name.is_empty() && !name.contains(['/', '\\']) && *name != "..")
+        .unwrap_or(fallback)
+        .to_string();
+    let size = dict.get("NSURLFileSizeKey").and_then(|v| v.as_unsigned_integer());
+    Ok(Placeholder { name, size })
+}
+
+/// Where the file a placeholder stands for appears once downloaded.
+pub fn download_path(placeholder_path: &Path, placeholder: &Placeholder) -> PathBuf {
+    placeholder_path.with_file_name(&placeholder.name)
+}
+
+/// Where the placeholder for a file that is not downloaded would be.
+pub fn placeholder_path(download_path: &Path) -> Option<PathBuf> {
+    let name = download_path.file_name()?.to_str()?;
+    Some(download_path.with_file_name(format!(".{}.icloud", name)))
+}
+
+/// Ask iCloud Drive to download the file at `path` (or its placeholder). Returns right away;
+/// the file appears in the folder when the download finishes.
+#[cfg(target_os = "macos")]
+pub fn request_download(path: &Path) -> Result<(), String> {
+    let status = std::process::Command::new("brctl")
+        .arg("download")
+        .arg(path)
+        .status()
+        .map_err(|e| format!("icloud_download_failed|detail={}", e))?;
+    if !status.success() {
+        return Err(format!("icloud_download_failed|detail={}", status));
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn request_download(_path: &Path) -> Result<(), String> {
+    Err("icloud_download_unsupported".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures").join(name)
+    }
+
+    #[test]
+    fn original_name_from_file_name() {
+        assert_eq!(original_name(".Dune.pdf.icloud"), Some("Dune.pdf"));
+        assert_eq!(original_name(".icloud"), None);
+        assert_eq!(original_name("Dune.pdf"), None);
+        assert_eq!(original_name("Dune.pdf.icloud"), None);
+    }
+
+    #[test]
+    fn reads_name_and_size() {
+        let placeholder = read(&fixture(".Dune.pdf.icloud")).unwrap();
+        assert_eq!(placeholder.name, "Dune.pdf");
+        assert_eq!(placeholder.size, Some(4_211_712));
+        assert_eq!(
+            download_path(&fixture(".Dune.pdf.icloud"), &placeholder),
+            fixture("Dune.pdf")
+        );
+    }
+
+    #[test]
+    fn unreadable_falls_back_to_encoded_name() {
+        for (file, name) in [(".Corrupt.pdf.icloud", "Corrupt.pdf"), (".Array.pdf.icloud", "Array.pdf")] {
+            let placeholder = read(&fixture(file)).unwrap();
+            assert_eq!(placeholder.name, name);
+            assert_eq!(placeholder.size, None);
+        }
+    }
+
+    #[test]
+    fn names_outside_the_folder_are_rejected() {
+        for (file, name, size) in [
+            (".Parent.pdf.icloud", "Parent.pdf", Some(1024)),
+            (".Nested.pdf.icloud", "Nested.pdf", Some(2048)),
+            (".Backslash.pdf.icloud", "Backslash.pdf", None),
+        ] {
+            let placeholder = read(&fixture(file)).unwrap();
+            assert_eq!(placeholder.name, name);
+            assert_eq!(placeholder.size, size);
+        }
+    }
+
+    #[test]
+    fn not_a_placeholder() {
+        assert!(read(&fixture("Dune.pdf")).unwrap_err().starts_with("not_icloud_placeholder|"));
+        assert!(!is_placeholder(&fixture("Dune.pdf")));
+        assert!(is_placeholder(&fixture(".Dune.pdf.icloud")));
+    }
+
+    #[test]
+    fn placeholder_path_round_trip() {
+        assert_eq!(placeholder_path(&fixture("Dune.pdf")), Some(fixture(".Dune.pdf.icloud")));
+    }
+
+    #[test]
+    fn placeholder_info_describes_the_cloud_book() {
+        let info = crate::pdf_info::placeholder_info(&fixture(".Dune.pdf.icloud")).unwrap();
+        assert_eq!(info.path, fixture("Dune.pdf").to_string_lossy());
+        assert_eq!(info.filename, "Dune.pdf");
+        assert_eq!(info.file_size, 4_211_712);
+        assert!(matches!(info.download_state, crate::pdf_info::DownloadState::Cloud));
+        // Never seen on this device
+        assert_eq!(info.hash, "");
+        assert_eq!(info.title, "Dune");
+        assert_eq!(info.page_count, 0);
+    }
+}
@@ -0,0 +1,444 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+use tauri::Emitter;
+
+use crate::fsutil::{self, ReadError};
+use crate::pdf_info::PdfInfo;
+use crate::{annotations, book_id, icloud, progress, sessions};
+
+/// Entries for files that disappeared are kept this long, so a book that is briefly missing
+/// (re-downloaded, moved out and back) is still recognised when it returns with new contents.
+const MISSING_RETENTION_DAYS: i64 = 30;
+/// Largest number of differing bits between two page fingerprints that still counts as the
+/// same page.
+const PAGE_MATCH_MAX_DISTANCE: u32 = 10;
+
+/// Titles PDF producers fill in when the author did not, shared by unrelated books.
+const GENERIC_TITLES: &[&str] = &["untitled", "untitled document", "document", "title", "no title"];
+const GENERIC_TITLE_PREFIXES: &[&str] = &["microsoft word - ", "microsoft powerpoint - ", "untitled-"];
+
+static INDEX_LOCK: Mutex<()> = Mutex::new(());
+/// The latest library scan, waiting for `run` to reconcile it. A newer scan replaces one that
+/// has not been picked up yet.
+static PENDING_SCAN: Mutex<Option<Vec<PdfInfo>>> = Mutex::new(None);
+static SCAN_READY: Condvar = Condvar::new();
+
+/// What this device last saw at a library path. A book's identity is the hash of its contents,
+/// so this is what lets an edited or re-downloaded book keep its progress and annotations.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct KnownBook {
+    hash: String,
+    title: String,
+    page_count: u32,
+    /// Text fingerprint per page (see `page_prints`). Only computed for books that have
+    /// something to carry forward, in the background.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    page_prints: Vec<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    missing_since: Option<String>,
+}
+
+fn index_file() -> PathBuf {
+    icloud::get_library_data_dir().join("book-index.json")
+}
+
+fn load_index() -> HashMap<String, KnownBook> {
+    match fsutil::read_with_fallback(&index_file(), |data| {
+        serde_json::from_str::<HashMap<String, KnownBook>>(data)
+    }) {
+        Ok(index) => index.unwrap_or_default(),
+        Err(ReadError::Io(e)) => {
+            log::warn!("identity: failed to read book index: {}", e);
+            HashMap::new()
+        }
+        Err(ReadError::Parse(e)) => {
+            log::warn!("identity: book index is corrupt, starting over: {}", e);
+            HashMap::new()
+        }
+    }
+}
+
+fn save_index(index: &HashMap<String, KnownBook>) {
+    let result = serde_json::to_string(index)
+        .map_err(|e| e.to_string())
+        .and_then(|data| fsutil::write_atomic(&index_file(), data.as_bytes(), true).map_err(|e| e.to_string()));
+    if let Err(e) = result {
+        log::warn!("identity: failed to save book index: {}", e);
+    }
+}
+
+fn fnv1a(word: &str) -> u64 {
+    word.bytes().fold(0xcbf29ce484222325, |h, b| (h ^ b as u64).wrapping_mul(0x100000001b3))
+}
+
+/// 64-bit SimHash of the words on a page: pages sharing most of their text get fingerprints
+/// that differ in only a few bits. 0 for pages without extractable text.
+fn simhash(text: &str) -> u64 {
+    let mut weights = [0i32; 64];
+    let mut words = 0;
+    for word in text.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()) {
+        words += 1;
+        let h = fnv1a(&word.to_lowercase());
+        for (bit, weight) in weights.iter_mut().enumerate() {
+            *weight += if (h >> bit) & 1 == 1 { 1 } else { -1 };
+        }
+    }
+    if words == 0 {
+        return 0;
+    }
+    weights
+        .iter()
+        .enumerate()
+        .fold(0, |acc, (bit, &weight)| if weight > 0 { acc | 1 << bit } else { acc })
+}
+
+/// Text fingerprint of every page, in page order.
+fn page_prints(path: &Path) -> Result<Vec<u64>, String> {
+    let doc = lopdf::Document::load(path).map_err(|e| format!("load_pdf_failed|detail={}", e))?;
+    Ok(doc
+        .get_pages()
+        .keys()
+        .map(|&page| doc.extract_text(&[page]).map(|text| simhash(&text)).unwrap_or(0))
+        .collect())
+}
+
+/// Maps page numbers of an old edition onto a new one.
+struct PageMap<'a> {
+    old_count: u32,
+    new_count: u32,
+    old_prints: &'a [u64],
+    new_prints: &'a [u64],
+}
+
+impl PageMap<'_> {
+    /// Page numbers start at 1; page 0 is treated as page 1.
+    fn proportional(&self, page: u32) -> u32 {
+        if self.old_count <= 1 {
+            return page.min(self.new_count).max(1);
+        }
+        let ratio = (self.new_count.max(1) - 1) as f64 / (self.old_count - 1) as f64;
+        (((page.max(1) - 1) as f64 * ratio).round() as u32 + 1).min(self.new_count.max(1))
+    }
+
+    /// The new page whose text is closest to the old page, nearest the proportional position
+    /// among equally close ones. Falls back to the proportional position when the old page has
+    /// no fingerprint or nothing is similar enough.
+    fn map(&self, page: u32) -> u32 {
+        let page = page.max(1);
+        if self.old_count == self.new_count {
+            return page;
+        }
+        let fallback = self.proportional(page);
+        let print = self.old_prints.get(page as usize - 1).copied().unwrap_or(0);
+        if print == 0 {
+            return fallback;
+        }
+        self.new_prints
+            .iter()
+            .enumerate()
+            .filter(|(_, &p)| p != 0)
+            .map(|(i, &p)| ((p ^ print).count_ones(), (i as i64 + 1 - fallback as i64).abs(), i as u32 + 1))
+            .filter(|&(distance, _, _)| distance <= PAGE_MATCH_MAX_DISTANCE)
+            .min()
+            .map_or(fallback, |(_, _, new_page)| new_page)
+    }
+}
+
+/// Carry progress, annotations and reading sessions from an old edition over to `book`.
+/// Returns the new edition's page fingerprints when they had to be computed for the mapping.
+fn migrate(old: &KnownBook, book: &PdfInfo) -> Vec<u64> {
+    let new_prints = if old.page_count != book.page_count && !old.page_prints.is_empty() {
+        page_prints(Path::new(&book.path)).unwrap_or_else(|e| {
+            log::warn!("identity: cannot fingerprint {}: {}", book.path, e);
+            Vec::new()
+        })
+    } else {
+        Vec::new()
+    };
+    let pages = PageMap {
+        old_count: old.page_count,
+        new_count: book.page_count,
+        old_prints: &old.page_prints,
+        new_prints: &new_prints,
+    };
+    let map_page = |page: u32| pages.map(page);
+
+    match progress::migrate(&old.hash, &book.hash, Some(book.page_count), &map_page) {
+        Ok(true) => log::info!("identity: moved progress {} -> {}", old.hash, book.hash),
+        Ok(false) => {}
+        Err(e) => log::warn!("identity: failed to move progress {} -> {}: {}", old.hash, book.hash, e),
+    }
+    match annotations::migrate(&old.hash, &book.hash, &map_page) {
+        Ok(0) => {}
+        Ok(n) => log::info!("identity: moved {} annotations {} -> {}", n, old.hash, book.hash),
+        Err(e) => log::warn!("identity: failed to move annotations {} -> {}: {}", old.hash, book.hash, e),
+    }
+    if let Err(e) = sessions::migrate(&old.hash, &book.hash, &map_page) {
+        log::warn!("identity: failed to move sessions {} -> {}: {}", old.hash, book.hash, e);
+    }
+    new_prints
+}
+
+/// Whether a title says enough about a book to recognise it under another file name: not
+/// empty, not the file name itself (what books without a title are listed under) and not a
+/// placeholder producers fill in.
+fn is_distinctive_title(title: &str, filename: &str) -> bool {
+    let title = title.trim().to_lowercase();
+    let stem = Path::new(filename)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or(filename)
+        .trim()
+        .to_lowercase();
+    !title.is_empty()
+        && title != stem
+        && !GENERIC_TITLES.contains(&title.as_str())
+        && !GENERIC_TITLE_PREFIXES.iter().any(|prefix| title.starts_with(prefix))
+}
+
+/// Compare a library scan with what was seen before, and carry a book's data forward when its
+/// contents changed: a new hash at a known path, or a new hash whose distinctive title and page
+/// count match exactly one book that disappeared. An old edition that is still in the library
+/// is a separate copy and is left alone. Returns whether any data was carried forward.
+fn reconcile(books: &[PdfInfo]) -> bool {
+    let _guard = INDEX_LOCK.lock().unwrap();
+    let mut index = load_index();
+    let before = serde_json::to_string(&index).unwrap_or_default();
+    let current_hashes: HashSet<&str> = books.iter().map(|b| b.hash.as_str()).collect();
+    let current_paths: HashSet<&str> = books.iter().map(|b| b.path.as_str()).collect();
+    let mut migrated = false;
+
+    for book in books {
+        // Not downloaded and never seen on this device, or not fully hashed yet: nothing to
+        // match it with until its ID is known (the confirmed hash triggers another scan)
+        if book.hash.is_empty() || book_id::is_provisional(&book.hash) {
+            continue;
+        }
+        let previous_path = match index.get(&book.path) {
+            // The same contents, possibly still under the short ID used before full-length IDs
+            Some(known) if known.hash == book.hash || book_id::short_alias(&book.hash) == Some(known.hash.as_str()) => {
+                let known = index.get_mut(&book.path).unwrap();
+                known.hash = book.hash.clone();
+                known.missing_since = None;
+                continue;
+            }
+            Some(_) => Some(book.path.clone()),
+            None if is_distinctive_title(&book.title, &book.filename) => {
+                let mut candidates = index.iter().filter(|(path, known)| {
+                    !current_paths.contains(path.as_str())
+                        && known.title == book.title
+                        && known.page_count == book.page_count
+                });
+                // Two missing books with the same title and length: no telling which one it is
+                match (candidates.next(), candidates.next()) {
+                    (Some((path, _)), None) => Some(path.clone()),
+                    _ => None,
+                }
+            }
+            None => None,
+        };
+        let previous = previous_path.and_then(|path| index.remove(&path));
+
+        let page_prints = match previous {
+            // Moved or renamed, contents unchanged
+            Some(known) if known.hash == book.hash => known.page_prints,
+            Some(known) if !current_hashes.contains(known.hash.as_str()) => {
+                log::info!(
+                    "identity: {} changed ({} pages -> {}), carrying data forward",
+                    book.filename,
+                    known.page_count,
+                    book.page_count
+                );
+                migrated = true;
+                migrate(&known, book)
+            }
+            _ => Vec::new(),
+        };
+        index.insert(
+            book.path.clone(),
+            KnownBook {
+                hash: book.hash.clone(),
+                title: book.title.clone(),
+                page_count: book.page_count,
+                page_prints,
+                missing_since: None,
+            },
+        );
+    }
+
+    let now = chrono::Utc::now();
+    let cutoff = now - chrono::Duration::days(MISSING_RETENTION_DAYS);
+    index.retain(|path, known| {
+        if current_paths.contains(path.as_str()) {
+            return true;
+        }
+        let since = known.missing_since.get_or_insert_with(|| now.to_rfc3339());
+        chrono::DateTime::parse_from_rfc3339(since).map_or(false, |t| t > cutoff)
+    });
+
+    if serde_json::to_string(&index).unwrap_or_default() != before {
+        save_index(&index);
+    }
+    migrated
+}
+
+/// Hand a library scan to `run`. Reconciling can fingerprint pages and sync the moved data, so
+/// it does not hold up the scan.
+pub fn reconcile_later(books: &[PdfInfo]) {
+    *PENDING_SCAN.lock().unwrap() = Some(books.to_vec());
+    SCAN_READY.notify_one();
+}
+
+/// Reconcile scans handed over by `reconcile_later`, then fingerprint what needs it, until
+/// `stop` is set. The library is told to rescan when data moved to another book.
+pub fn run(app_handle: tauri::AppHandle, stop: Arc<AtomicBool>) {
+    while !stop.load(Ordering::Relaxed) {
+        let books = {
+            let pending = PENDING_SCAN.lock().unwrap();
+            let (mut pending, _) = SCAN_READY.wait_timeout(pending, Duration::from_secs(1)).unwrap();
+            match pending.take() {
+                Some(books) => books,
+                None => continue,
+            }
+        };
+        if reconcile(&books) {
+            let _ = app_handle.emit("books:changed", ());
+        }
+        fingerprint(&stop);
+    }
+}
+
+/// ID, title and page count of the book this device last saw at a library path, for books
+/// that are in iCloud Drive but no longer downloaded.
+pub fn last_seen(path: &str) -> Option<(String, String, u32)> {
+    let _guard = INDEX_LOCK.lock().unwrap();
+    load_index()
+        .remove(path)
+        .filter(|known| book_id::version_of(&known.hash) == Some(book_id::IdVersion::CURRENT))
+        .map(|known| (known.hash, known.title, known.page_count))
+}
+
+/// Fingerprint the pages of books that have progress or annotations and no fingerprints yet,
+/// so a later edition with a different page count can be mapped.
+fn fingerprint(stop: &AtomicBool) {
+    let with_progress: HashSet<String> = progress::local_hashes().into_iter().collect();
+    let pending: Vec<(String, String)> = {
+        let _guard = INDEX_LOCK.lock().unwrap();
+        load_index()
+            .into_iter()
+            .filter(|(path, known)| {
+                known.page_prints.is_empty()
+                    && known.missing_since.is_none()
+                    && known.page_count > 0
+                    && (with_progress.contains(&known.hash) || annotations::has_local(&known.hash))
+                    // Books only in iCloud Drive wait until they are downloaded
+                    && Path::new(path).is_file()
+            })
+            .map(|(path, known)| (path, known.hash))
+            .collect()
+    };
+
+    for (path, hash) in pending {
+        if stop.load(Ordering::Relaxed) {
+            return;
+        }
+        let prints = match page_prints(Path::new(&path)) {
+            Ok(prints) => prints,
+            Err(e) => {
+                log::warn!("identity: cannot fingerprint {}: {}", path, e);
+                continue;
+            }
+        };
+        let _guard = INDEX_LOCK.lock().unwrap();
+        let mut index = load_index();
+        if let Some(known) = index.get_mut(&path).filter(|k| k.hash == hash) {
+            known.page_prints = prints;
+            save_index(&index);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pages<'a>(old_count: u32, new_count: u32, old_prints: &'a [u64], new_prints: &'a [u64]) -> PageMap<'a> {
+        PageMap {
+            old_count,
+            new_count,
+            old_prints,
+            new_prints,
+        }
+    }
+
+    #[test]
+    fn proportional_keeps_first_and_last_page() {
+        let map = pages(101, 201, &[], &[]);
+        assert_eq!(map.proportional(1), 1);
+        assert_eq!(map.proportional(101), 201);
+        assert_eq!(map.proportional(51), 101);
+        // Past the end of the old edition
+        assert_eq!(map.proportional(150), 201);
+    }
+
+    #[test]
+    fn proportional_handles_single_and_empty_editions() {
+        assert_eq!(pages(1, 10, &[], &[]).proportional(1), 1);
+        assert_eq!(pages(10, 1, &[], &[]).proportional(7), 1);
+        assert_eq!(pages(10, 0, &[], &[]).proportional(7), 1);
+        assert_eq!(pages(0, 10, &[], &[]).proportional(3), 3);
+    }
+
+    #[test]
+    fn page_zero_is_page_one() {
+        assert_eq!(pages(10, 20, &[], &[]).proportional(0), 1);
+        assert_eq!(pages(10, 20, &[1, 2], &[1, 2]).map(0), 1);
+        assert_eq!(pages(10, 10, &[], &[]).map(0), 1);
+        assert_eq!(pages(1, 5, &[], &[]).map(0), 1);
+    }
+
+    #[test]
+    fn map_follows_the_page_text() {
+        let a = simhash("the quick brown fox jumps over the lazy dog near the river bank");
+        let b = simhash("call me ishmael some years ago never mind how long precisely");
+        let c = simhash("it was the best of times it was the worst of times it was the age");
+        // A preface was added: every old page moved two pages on
+        let old = [a, b, c];
+        let new = [0, simhash("preface text about this edition of the book"), a, b, c];
+        let map = pages(3, 5, &old, &new);
+        assert_eq!(map.map(1), 3);
+        assert_eq!(map.map(2), 4);
+        assert_eq!(map.map(3), 5);
+    }
+
+    #[test]
+    fn map_falls_back_to_proportional() {
+        // No fingerprint for the old page, or nothing close enough in the new edition
+        assert_eq!(pages(10, 20, &[], &[]).map(10), 20);
+        let unrelated = [simhash("completely different words on every single page here")];
+        let old = [simhash("the quick brown fox jumps over the lazy dog near the river bank")];
+        assert_eq!(pages(1, 2, &old, &unrelated).map(1), 1);
+        // Same page count: pages stay where they are
+        assert_eq!(pages(5, 5, &old, &unrelated).map(4), 4);
+    }
+
+    #[test]
+    fn simhash_ignores_case_and_punctuation() {
+        assert_eq!(simhash("Hello, World!"), simhash("hello world"));
+        assert_eq!(simhash("  ... "), 0);
+    }
+
+    #[test]
+    fn only_distinctive_titles_identify_a_book() {
+        assert!(is_distinctive_title("Moby-Dick; or, The Whale", "moby.pdf"));
+        assert!(!is_distinctive_title("moby", "moby.pdf"));
+        assert!(!is_distinctive_title("Moby", "moby.pdf"));
+        assert!(!is_distinctive_title("", "moby.pdf"));
+        assert!(!is_distinctive_title("Untitled", "a.pdf"));
+        assert!(!is_distinctive_title("Microsoft Word - Report.docx", "report.pdf"));
+    }
+}
@@ -1,14 +1,38 @@
+mod annotations;
+mod background_hash;
+mod book_id;
 mod commands;
+mod conflicts;
+mod device;
+mod export;
+mod fsutil;
+mod gc;
+mod hash_cache;
 mod icloud;
+mod icloud_placeholder;
+mod identity;
+mod library;
 mod locale;
+mod lru;
+mod paths;
+mod pdf_annotations;
 mod pdf_info;
+mod pdf_meta;
 mod progress;
+mod reveal;
+mod sessions;
+mod settings;
+mod storage_migration;
+mod sync_backend;
 mod watcher;
 mod window;
 
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
+/// How often buffered progress saves are written to disk.
+const PROGRESS_FLUSH_SECS: u64 = 2;
+
 pub fn run() {
     let watcher_stop = Arc::new(AtomicBool::new(false));
 
@@ -22,36 +46,119 @@ pub fn run() {
             commands::scan_books,
             commands::import_pdf,
             commands::get_pdf_outline,
+            commands::get_pdf_annotations,
+            commands::export_annotated_pdf,
+            commands::export_annotations,
+            commands::export_all_annotations,
             commands::load_progress,
             commands::save_progress,
             commands::sync_progress,
             commands::sync_all_progress,
+            commands::get_progress_records,
+            commands::get_devices,
+            commands::rename_device,
+            commands::get_sync_config,
+            commands::set_sync_config,
+            commands::get_merge_strategy,
+            commands::set_merge_strategy,
+            commands::get_settings,
+            commands::update_settings,
+            commands::list_libraries,
+            commands::add_library,
+            commands::rename_library,
+            commands::remove_library,
+            commands::switch_library,
+            commands::get_storage_change,
+            commands::migrate_storage,
+            commands::keep_storage_root,
+            commands::get_watcher_health,
+            commands::restart_watcher,
+            commands::get_sync_conflicts,
+            commands::resolve_sync_conflict,
+            commands::undo_sync_conflict,
+            commands::gc_library,
+            commands::get_auto_gc,
+            commands::set_auto_gc,
+            commands::record_reading_activity,
+            commands::get_reading_stats,
+            commands::load_annotations,
+            commands::add_annotation,
+            commands::update_annotation,
+            commands::delete_annotation,
+            commands::sync_annotations,
             commands::open_reader_window,
             commands::get_books_directory,
             commands::reveal_in_finder,
+            commands::download_book,
             commands::delete_pdf,
             commands::rename_pdf,
             commands::is_debug_enabled,
+            commands::debug_cache_stats,
             commands::reset_magnification,
             commands::get_system_locale,
         ])
         .setup(move |app| {
             let app_handle = app.handle().clone();
 
+            // Move data from legacy locations before anything reads settings
+            paths::init();
+            // Pin the default library's location before anything uses it
+            storage_migration::detect();
+            library::check_active();
+            library::allow_active(&app_handle);
             // Initialize iCloud directory
             if let Err(e) = icloud::ensure_directories() {
                 log::error!("Failed to initialize directories: {}", e);
             }
+            // Clean up after writes interrupted by a crash or power loss
+            for dir in [
+                paths::data_dir(),
+                paths::config_dir(),
+                icloud::get_local_progress_dir(),
+                icloud::get_local_annotations_dir(),
+            ] {
+                fsutil::recover_temp_files(&dir);
+            }
+            // Central copies kept by a folder backend (iCloud `Progress/` or the sync folder)
+            if let Some(root) = sync_backend::current().as_deref().and_then(|b| b.local_root()) {
+                fsutil::recover_sync_temp_files(root);
+            }
+            device::announce(true);
 
             // Start file watcher
             let handle = app_handle.clone();
             let stop = stop_flag.clone();
+            std::thread::spawn(move || watcher::supervise(handle, stop));
+
+            // Write buffered reading progress to disk
+            let stop = stop_flag.clone();
             std::thread::spawn(move || {
-                if let Err(e) = watcher::start_watching(handle, stop) {
-                    log::error!("File watcher error: {}", e);
-                }
+                progress::run_flusher(stop, std::time::Duration::from_secs(PROGRESS_FLUSH_SECS))
             });
 
+            // Push progress changes from other devices
+            let handle = app_handle.clone();
+            let stop = stop_flag.clone();
+            std::thread::spawn(move || watcher::watch_progress(handle, stop));
+
+            // Drop stale hash cache entries, then save the cache once a scan settles
+            let stop = stop_flag.clone();
+            std::thread::spawn(move || hash_cache::run_saver(stop));
+
+            // Full hashes for large books shown under a provisional ID
+            let handle = app_handle.clone();
+            let stop = stop_flag.clone();
+            std::thread::spawn(move || background_hash::run(handle, stop));
+
+            // Carry data forward for books whose contents changed, after each scan
+            let handle = app_handle.clone();
+            let stop = stop_flag.clone();
+            std::thread::spawn(move || identity::run(handle, stop));
+
+            // Clean up progress of removed books, when enabled
+            let stop = stop_flag.clone();
+            std::thread::spawn(move || gc::run_periodic(stop));
+
             Ok(())
         })
         .build(tauri::generate_context!())
@@ -65,8 +172,21 @@ pub fn run() {
                 } if label == "library" => {
                     app.exit(0);
                 }
+                tauri::RunEvent::WindowEvent {
+                    label,
+                    event: tauri::WindowEvent::Destroyed,
+                    ..
+                } => {
+                    if let Some(hash) = label.strip_prefix("reader-") {
+                        progress::flush(hash);
+                        sessions::end(hash);
+                    }
+                }
                 tauri::RunEvent::Exit => {
                     watcher_stop.store(true, Ordering::Relaxed);
+                    progress::flush_all();
+                    hash_cache::flush();
+                    sessions::end_all();
                 }
                 _ => {}
             }
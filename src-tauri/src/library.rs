@@ -0,0 +1,178 @@
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use tauri::Manager;
+use tauri_plugin_fs::FsExt;
+
+use crate::settings::{self, LibraryEntry};
+use crate::{book_id, icloud, progress, sessions, sync_backend};
+
+/// ID of the library that exists without being registered: the iCloud Drive app folder when
+/// iCloud Drive is available, the local library folder otherwise (see `paths`).
+pub const DEFAULT_ID: &str = "default";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Library {
+    pub id: String,
+    /// Empty for the default library, which the UI names itself.
+    pub name: String,
+    pub root: String,
+    pub active: bool,
+}
+
+/// The registered library in use, or None for the default one.
+pub fn active() -> Option<LibraryEntry> {
+    let device = settings::load_device();
+    let id = device.active_library?;
+    device.libraries.into_iter().find(|lib| lib.id == id)
+}
+
+/// The default library first, then registered ones in the order they were added.
+pub fn list() -> Vec<Library> {
+    let device = settings::load_device();
+    let active_id = active().map_or(DEFAULT_ID.to_string(), |lib| lib.id);
+    let default = Library {
+        id: DEFAULT_ID.to_string(),
+        name: String::new(),
+        root: icloud::get_default_base_dir().to_string_lossy().to_string(),
+        active: active_id == DEFAULT_ID,
+    };
+    std::iter::once(default)
+        .chain(device.libraries.into_iter().map(|lib| Library {
+            active: lib.id == active_id,
+            id: lib.id,
+            name: lib.name,
+            root: lib.root,
+        }))
+        .collect()
+}
+
+/// Derived from the folder, so a library removed and added again finds the data this device
+/// kept for it.
+fn id_for(root: &Path) -> String {
+    let canonical = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+    let digest = Sha256::digest(canonical.to_string_lossy().as_bytes());
+    digest[..8].iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Register a folder as a library. `Books/` and `Progress/` are created in it when missing.
+pub fn add(name: &str, root: &str) -> Result<Library, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("library_name_empty".to_string());
+    }
+    let root = PathBuf::from(root.trim());
+    if !root.is_absolute() || !root.is_dir() {
+        return Err("library_root_invalid".to_string());
+    }
+    let id = id_for(&root);
+    let mut device = settings::load_device();
+    let default_id = id_for(&icloud::get_default_base_dir());
+    if id == default_id || device.libraries.iter().any(|lib| lib.id == id) {
+        return Err("library_exists".to_string());
+    }
+    for dir in [root.join("Books"), root.join("Progress")] {
+        std::fs::create_dir_all(&dir).map_err(|e| format!("create_library_failed|detail={}", e))?;
+    }
+
+    let entry = LibraryEntry {
+        id,
+        name: name.to_string(),
+        root: root.to_string_lossy().to_string(),
+    };
+    device.libraries.push(entry.clone());
+    settings::save_device(&device)?;
+    log::info!("library: added {} ({}) at {}", entry.name, entry.id, entry.root);
+    Ok(Library {
+        id: entry.id,
+        name: entry.name,
+        root: entry.root,
+        active: false,
+    })
+}
+
+pub fn rename(id: &str, name: &str) -> Result<(), String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("library_name_empty".to_string());
+    }
+    let mut device = settings::load_device();
+    let entry = device
+        .libraries
+        .iter_mut()
+        .find(|lib| lib.id == id)
+        .ok_or_else(|| format!("library_not_found|id={}", id))?;
+    entry.name = name.to_string();
+    settings::save_device(&device)
+}
+
+/// Forget a library. Its folder and the data this device kept for it stay where they are.
+pub fn remove(id: &str) -> Result<(), String> {
+    if active().is_some_and(|lib| lib.id == id) {
+        return Err("library_active".to_string());
+    }
+    let mut device = settings::load_device();
+    let before = device.libraries.len();
+    device.libraries.retain(|lib| lib.id != id);
+    if device.libraries.len() == before {
+        return Err(format!("library_not_found|id={}", id));
+    }
+    settings::save_device(&device)?;
+    log::info!("library: removed {}", id);
+    Ok(())
+}
+
+/// Show another library. Buffered progress and open reading sessions are written to the
+/// current one first; callers close reader windows of the old library beforehand and rescan.
+pub fn switch(id: &str) -> Result<(), String> {
+    let mut device = settings::load_device();
+    let target = if id == DEFAULT_ID {
+        None
+    } else {
+        let entry = device
+            .libraries
+            .iter()
+            .find(|lib| lib.id == id)
+            .ok_or_else(|| format!("library_not_found|id={}", id))?;
+        if !Path::new(&entry.root).is_dir() {
+            return Err("library_root_missing".to_string());
+        }
+        Some(entry.id.clone())
+    };
+    if target == device.active_library {
+        return Ok(());
+    }
+
+    progress::flush_all();
+    sessions::end_all();
+    device.active_library = target;
+    settings::save_device(&device)?;
+    icloud::ensure_directories()?;
+    sync_backend::reload();
+    book_id::reload();
+    log::info!("library: switched to {} at {}", id, icloud::get_base_dir().display());
+    Ok(())
+}
+
+/// Let windows load books from the active library. Only the default locations are allowed in
+/// the app config; registered libraries can be anywhere.
+pub fn allow_active(app_handle: &tauri::AppHandle) {
+    let root = icloud::get_base_dir();
+    if let Err(e) = app_handle.asset_protocol_scope().allow_directory(&root, true) {
+        log::warn!("library: cannot allow asset access to {}: {}", root.display(), e);
+    }
+    if let Err(e) = app_handle.fs_scope().allow_directory(&root, true) {
+        log::warn!("library: cannot allow file access to {}: {}", root.display(), e);
+    }
+}
+
+/// At startup: fall back to the default library if the active one's folder is gone (e.g. on an
+/// external drive that is not connected), rather than recreating it somewhere unexpected.
+pub fn check_active() {
+    if let Some(lib) = active().filter(|lib| !Path::new(&lib.root).is_dir()) {
+        log::warn!("library: {} at {} is not available, using the default library", lib.name, lib.root);
+        if let Err(e) = switch(DEFAULT_ID) {
+            log::warn!("library: cannot switch to the default library: {}", e);
+        }
+    }
+}
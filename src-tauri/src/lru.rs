@@ -0,0 +1,149 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// A small least-recently-used map. Eviction scans for the oldest entry, which is cheap at
+/// the sizes used here (a few hundred entries) and needs no extra bookkeeping.
+pub struct LruCache<K, V> {
+    capacity: usize,
+    entries: HashMap<K, (V, u64)>,
+    tick: u64,
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CacheStats {
+    pub entries: usize,
+    pub capacity: usize,
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+}
+
+impl<K: Hash + Eq + Clone, V> LruCache<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            entries: HashMap::new(),
+            tick: 0,
+            hits: 0,
+            misses: 0,
+            evictions: 0,
+        }
+    }
+
+    /// The entry for `key` if `valid` accepts it, marking it as recently used. A rejected
+    /// entry is dropped and counts as a miss.
+    pub fn get_if(&mut self, key: &K, valid: impl FnOnce(&V) -> bool) -> Option<&V> {
+        self.tick += 1;
+        let tick = self.tick;
+        match self.entries.get(key) {
+            Some((value, _)) if valid(value) => {}
+            Some(_) => {
+                self.entries.remove(key);
+                self.misses += 1;
+                return None;
+            }
+            None => {
+                self.misses += 1;
+                return None;
+            }
+        }
+        self.hits += 1;
+        let entry = self.entries.get_mut(key)?;
+        entry.1 = tick;
+        Some(&entry.0)
+    }
+
+    pub fn insert(&mut self, key: K, value: V) {
+        self.tick += 1;
+        if !self.entries.contains_key(&key) && self.entries.len() >= self.capacity {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(k, _)| k.clone());
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+                self.evictions += 1;
+            }
+        }
+        self.entries.insert(key, (value, self.tick));
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.entries.remove(key).map(|(value, _)| value)
+    }
+
+    pub fn retain(&mut self, mut keep: impl FnMut(&K, &V) -> bool) {
+        self.entries.retain(|k, (v, _)| keep(k, v));
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.entries.iter().map(|(k, (v, _))| (k, v))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            entries: self.entries.len(),
+            capacity: self.capacity,
+            hits: self.hits,
+            misses: self.misses,
+            evictions: self.evictions,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evicts_the_least_recently_used() {
+        let mut cache = LruCache::new(2);
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        assert_eq!(cache.get_if(&"a", |_| true), Some(&1));
+        cache.insert("c", 3);
+        assert_eq!(cache.get_if(&"b", |_| true), None);
+        assert_eq!(cache.get_if(&"a", |_| true), Some(&1));
+        assert_eq!(cache.get_if(&"c", |_| true), Some(&3));
+        let stats = cache.stats();
+        assert_eq!((stats.entries, stats.hits, stats.misses, stats.evictions), (2, 3, 1, 1));
+    }
+
+    #[test]
+    fn replacing_a_key_does_not_evict() {
+        let mut cache = LruCache::new(2);
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        cache.insert("a", 10);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get_if(&"a", |_| true), Some(&10));
+        assert_eq!(cache.stats().evictions, 0);
+    }
+
+    #[test]
+    fn rejected_entries_are_dropped() {
+        let mut cache = LruCache::new(4);
+        cache.insert("a", 1);
+        assert_eq!(cache.get_if(&"a", |v| *v == 2), None);
+        assert_eq!(cache.len(), 0);
+        assert_eq!(cache.stats().misses, 1);
+    }
+
+    #[test]
+    fn capacity_is_at_least_one() {
+        let mut cache = LruCache::new(0);
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.remove(&"b"), Some(2));
+    }
+}
@@ -0,0 +1,143 @@
+use std::path::{Path, PathBuf};
+
+/// Folder name under the OS data and config folders. macOS keeps the bundle identifier, which
+/// is where the app has always kept its data there.
+#[cfg(target_os = "macos")]
+const APP_DIR: &str = "com.yangguanlin.pdf-reader";
+#[cfg(not(target_os = "macos"))]
+const APP_DIR: &str = "pdf-reader";
+
+fn home() -> PathBuf {
+    dirs::home_dir().unwrap_or_else(std::env::temp_dir)
+}
+
+/// Device-local app data (never synced): `~/Library/Application Support/<id>` on macOS,
+/// `$XDG_DATA_HOME/pdf-reader` on Linux, `%LOCALAPPDATA%\pdf-reader` on Windows.
+pub fn data_dir() -> PathBuf {
+    // Tests never touch the reader's own data
+    if cfg!(test) {
+        return std::env::temp_dir().join(format!("{}-test-{}", APP_DIR, std::process::id()));
+    }
+    dirs::data_local_dir().unwrap_or_else(home).join(APP_DIR)
+}
+
+/// Device settings: the data folder on macOS, `$XDG_CONFIG_HOME/pdf-reader` on Linux and
+/// `%LOCALAPPDATA%\pdf-reader` on Windows.
+pub fn config_dir() -> PathBuf {
+    if cfg!(test) {
+        return data_dir();
+    }
+    dirs::config_local_dir()
+        .map(|dir| dir.join(APP_DIR))
+        .unwrap_or_else(data_dir)
+}
+
+/// The default library when iCloud Drive is not available: `~/.pdf-reader` on macOS,
+/// `Library` in the data folder elsewhere.
+pub fn local_library_dir() -> PathBuf {
+    if cfg!(target_os = "macos") {
+        legacy_library_dir()
+    } else {
+        data_dir().join("Library")
+    }
+}
+
+/// Where every OS kept app data before it followed the platform's conventions.
+fn legacy_data_dir() -> PathBuf {
+    home()
+        .join("Library")
+        .join("Application Support")
+        .join("com.yangguanlin.pdf-reader")
+}
+
+/// Where every OS kept the local library before it followed the platform's conventions.
+fn legacy_library_dir() -> PathBuf {
+    home().join(".pdf-reader")
+}
+
+/// Move a folder, falling back to copying it when it is on another file system. The copy
+/// leaves the original in place. Fails if `to` exists.
+fn move_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+    if to.exists() {
+        return Err(std::io::Error::new(std::io::ErrorKind::AlreadyExists, to.display().to_string()));
+    }
+    if let Some(parent) = to.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    if std::fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    let result = copy_dir(from, to);
+    if result.is_err() {
+        let _ = std::fs::remove_dir_all(to);
+    }
+    result
+}
+
+fn copy_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(to)?;
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            std::fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Move data from the legacy locations (see `legacy_data_dir`) on first start with the current
+/// ones, then create the data and config folders. Runs before anything reads settings. Nothing
+/// is moved when the current location already exists, so a half-finished move is not redone.
+pub fn init() {
+    let legacy_data = legacy_data_dir();
+    let data = data_dir();
+    if legacy_data != data && legacy_data.is_dir() && !data.exists() {
+        match move_dir(&legacy_data, &data) {
+            Ok(()) => {
+                log::info!("paths: moved app data {} -> {}", legacy_data.display(), data.display());
+                // `~/Library/Application Support` is not otherwise used outside macOS; only
+                // removed when empty
+                for dir in legacy_data.ancestors().skip(1).take(2) {
+                    let _ = std::fs::remove_dir(dir);
+                }
+            }
+            Err(e) => log::warn!("paths: cannot move app data from {}: {}", legacy_data.display(), e),
+        }
+    }
+
+    let config = config_dir();
+    for dir in [&data, &config] {
+        if let Err(e) = std::fs::create_dir_all(dir) {
+            log::warn!("paths: cannot create {}: {}", dir.display(), e);
+        }
+    }
+    let old_settings = data.join("settings.json");
+    let settings = config.join("settings.json");
+    if config != data && old_settings.is_file() && !settings.exists() {
+        match std::fs::rename(&old_settings, &settings) {
+            Ok(()) => log::info!("paths: moved settings to {}", settings.display()),
+            Err(e) => log::warn!("paths: cannot move settings to {}: {}", settings.display(), e),
+        }
+    }
+    // Earlier versions wrote the settings (and the WebDAV password in them) readable by anyone
+    if settings.is_file() {
+        if let Err(e) = crate::fsutil::make_private(&settings) {
+            log::warn!("paths: cannot restrict {}: {}", settings.display(), e);
+        }
+    }
+
+    let legacy_library = legacy_library_dir();
+    let library = local_library_dir();
+    if legacy_library != library && legacy_library.is_dir() && !library.exists() {
+        match move_dir(&legacy_library, &library) {
+            Ok(()) => {
+                log::info!("paths: moved library {} -> {}", legacy_library.display(), library.display());
+                crate::storage_migration::relocated(&legacy_library, &library);
+            }
+            Err(e) => log::warn!("paths: cannot move library from {}: {}", legacy_library.display(), e),
+        }
+    }
+}
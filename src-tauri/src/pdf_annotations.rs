@@ -0,0 +1,896 @@
+use lopdf::{dictionary, Dictionary, Document, Object, ObjectId, Stream};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+
+use crate::annotations::{Annotation, AnnotationKind};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PdfAnnotationKind {
+    Highlight,
+    Underline,
+    StrikeOut,
+    Text,
+    FreeText,
+    Ink,
+}
+
+impl PdfAnnotationKind {
+    fn from_subtype(subtype: &[u8]) -> Option<Self> {
+        match subtype {
+            b"Highlight" => Some(Self::Highlight),
+            b"Underline" => Some(Self::Underline),
+            b"StrikeOut" => Some(Self::StrikeOut),
+            b"Text" => Some(Self::Text),
+            b"FreeText" => Some(Self::FreeText),
+            b"Ink" => Some(Self::Ink),
+            _ => None,
+        }
+    }
+
+    fn is_markup(self) -> bool {
+        matches!(self, Self::Highlight | Self::Underline | Self::StrikeOut)
+    }
+}
+
+/// An annotation stored inside the PDF file itself (made in Preview, Acrobat, etc.).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PdfAnnotation {
+    pub kind: PdfAnnotationKind,
+    pub page: u32,
+    /// [llx, lly, urx, ury] in PDF user space.
+    pub rect: [f64; 4],
+    pub quad_points: Vec<[f64; 8]>,
+    /// Ink strokes as flat x/y coordinate lists.
+    pub ink_list: Vec<Vec<f64>>,
+    pub color: Option<String>,
+    pub author: Option<String>,
+    pub contents: Option<String>,
+    pub modified: Option<String>,
+    /// Text under a highlight, underline or strike-out, when it can be recovered.
+    pub text: Option<String>,
+}
+
+pub fn extract_annotations(path: &Path) -> Result<Vec<PdfAnnotation>, String> {
+    let doc = Document::load(path).map_err(|e| format!("load_pdf_failed|detail={}", e))?;
+
+    let mut result = Vec::new();
+    for (page_num, page_id) in doc.get_pages() {
+        let Ok(annots) = doc.get_page_annotations(page_id) else {
+            continue;
+        };
+        let mut runs: Option<Vec<TextRun>> = None;
+        for annot in annots {
+            let Some(kind) = annot
+                .get(b"Subtype")
+                .and_then(Object::as_name)
+                .ok()
+                .and_then(PdfAnnotationKind::from_subtype)
+            else {
+                continue;
+            };
+
+            let quad_points = quads(&doc, annot);
+            let text = if kind.is_markup() && !quad_points.is_empty() {
+                let runs = runs.get_or_insert_with(|| page_text_runs(&doc, page_id));
+                Some(text_in_quads(runs, &quad_points)).filter(|t| !t.is_empty())
+            } else {
+                None
+            };
+
+            result.push(PdfAnnotation {
+                kind,
+                page: page_num,
+                rect: rect(&doc, annot).unwrap_or_default(),
+                quad_points,
+                ink_list: ink_list(&doc, annot),
+                color: color(&doc, annot),
+                author: text_entry(&doc, annot, b"T"),
+                contents: text_entry(&doc, annot, b"Contents"),
+                modified: text_entry(&doc, annot, b"M").map(|m| pdf_date_to_iso(&m).unwrap_or(m)),
+                text,
+            });
+        }
+    }
+
+    Ok(result)
+}
+
+// --- Dictionary helpers ---
+
+fn deref<'a>(doc: &'a Document, obj: &'a Object) -> &'a Object {
+    doc.dereference(obj).map(|(_, o)| o).unwrap_or(obj)
+}
+
+fn numbers(doc: &Document, obj: &Object) -> Vec<f64> {
+    match deref(doc, obj) {
+        Object::Array(arr) => arr
+            .iter()
+            .filter_map(|o| deref(doc, o).as_float().ok())
+            .map(f64::from)
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn rect(doc: &Document, annot: &Dictionary) -> Option<[f64; 4]> {
+    let n = numbers(doc, annot.get(b"Rect").ok()?);
+    (n.len() == 4).then(|| {
+        [
+            n[0].min(n[2]),
+            n[1].min(n[3]),
+            n[0].max(n[2]),
+            n[1].max(n[3]),
+        ]
+    })
+}
+
+fn quads(doc: &Document, annot: &Dictionary) -> Vec<[f64; 8]> {
+    let Ok(obj) = annot.get(b"QuadPoints") else {
+        return Vec::new();
+    };
+    numbers(doc, obj)
+        .chunks_exact(8)
+        .map(|c| [c[0], c[1], c[2], c[3], c[4], c[5], c[6], c[7]])
+        .collect()
+}
+
+fn ink_list(doc: &Document, annot: &Dictionary) -> Vec<Vec<f64>> {
+    match annot.get(b"InkList").map(|o| deref(doc, o)) {
+        Ok(Object::Array(strokes)) => strokes
+            .iter()
+            .map(|s| numbers(doc, s))
+            .filter(|s| !s.is_empty())
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn color(doc: &Document, annot: &Dictionary) -> Option<String> {
+    let c = numbers(doc, annot.get(b"C").ok()?);
+    let (r, g, b) = match c.as_slice() {
+        [gray] => (*gray, *gray, *gray),
+        [r, g, b] => (*r, *g, *b),
+        [c, m, y, k] => ((1.0 - c) * (1.0 - k), (1.0 - m) * (1.0 - k), (1.0 - y) * (1.0 - k)),
+        _ => return None,
+    };
+    let to_byte = |v: f64| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+    Some(format!("#{:02x}{:02x}{:02x}", to_byte(r), to_byte(g), to_byte(b)))
+}
+
+fn text_entry(doc: &Document, annot: &Dictionary, key: &[u8]) -> Option<String> {
+    let obj = deref(doc, annot.get(key).ok()?);
+    lopdf::decode_text_string(obj)
+        .ok()
+        .or_else(|| obj.as_str().ok().map(|b| String::from_utf8_lossy(b).to_string()))
+        .filter(|s| !s.trim().is_empty())
+}
+
+/// Convert a PDF date (`D:YYYYMMDDHHmmSSOHH'mm'`) to ISO 8601. Missing parts default per spec.
+pub(crate) fn pdf_date_to_iso(raw: &str) -> Option<String> {
+    let s = raw.trim().trim_start_matches("D:");
+    let digits: String = s.chars().take_while(|c| c.is_ascii_digit()).collect();
+    if digits.len() < 4 {
+        return None;
+    }
+    let part = |start: usize, default: u32| -> u32 {
+        digits
+            .get(start..start + 2)
+            .and_then(|p| p.parse().ok())
+            .unwrap_or(default)
+    };
+    let year: i32 = digits[..4].parse().ok()?;
+    let (month, day) = (part(4, 1), part(6, 1));
+    let (hour, minute, second) = (part(8, 0), part(10, 0), part(12, 0));
+
+    let rest = &s[digits.len()..];
+    let offset_secs = match rest.chars().next() {
+        Some(sign @ ('+' | '-')) => {
+            let tz: String = rest[1..].chars().filter(|c| c.is_ascii_digit()).collect();
+            let h: i32 = tz.get(0..2).and_then(|p| p.parse().ok()).unwrap_or(0);
+            let m: i32 = tz.get(2..4).and_then(|p| p.parse().ok()).unwrap_or(0);
+            let secs = h * 3600 + m * 60;
+            if sign == '-' {
+                -secs
+            } else {
+                secs
+            }
+        }
+        _ => 0,
+    };
+
+    let offset = chrono::FixedOffset::east_opt(offset_secs)?;
+    let local = chrono::NaiveDate::from_ymd_opt(year, month, day)?
+        .and_hms_opt(hour, minute, second)?
+        .and_local_timezone(offset)
+        .single()?;
+    Some(
+        local
+            .with_timezone(&chrono::Utc)
+            .format("%Y-%m-%dT%H:%M:%S%.3fZ")
+            .to_string(),
+    )
+}
+
+// --- Positioned text extraction ---
+
+/// A run of text drawn by one show-text operator, with an estimated box in user space.
+#[derive(Debug, Clone)]
+pub(crate) struct TextRun {
+    pub text: String,
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub size: f64,
+}
+
+type Matrix = [f64; 6];
+
+const IDENTITY: Matrix = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+
+fn multiply(m: &Matrix, n: &Matrix) -> Matrix {
+    [
+        m[0] * n[0] + m[1] * n[2],
+        m[0] * n[1] + m[1] * n[3],
+        m[2] * n[0] + m[3] * n[2],
+        m[2] * n[1] + m[3] * n[3],
+        m[4] * n[0] + m[5] * n[2] + n[4],
+        m[4] * n[1] + m[5] * n[3] + n[5],
+    ]
+}
+
+fn translate(m: &Matrix, tx: f64, ty: f64) -> Matrix {
+    multiply(&[1.0, 0.0, 0.0, 1.0, tx, ty], m)
+}
+
+fn operand_numbers(operands: &[Object]) -> Vec<f64> {
+    operands
+        .iter()
+        .filter_map(|o| o.as_float().ok())
+        .map(f64::from)
+        .collect()
+}
+
+/// Walk a page's content stream and collect text runs with their approximate positions.
+/// Glyph widths are not read from the fonts; each character is assumed to be half an em wide,
+/// which is close enough to decide which runs fall under an annotation's quads.
+pub(crate) fn page_text_runs(doc: &Document, page_id: ObjectId) -> Vec<TextRun> {
+    const AVG_CHAR_WIDTH: f64 = 0.5;
+
+    let encodings: BTreeMap<Vec<u8>, lopdf::Encoding> = doc
+        .get_page_fonts(page_id)
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|(name, font)| font.get_font_encoding(doc).ok().map(|e| (name, e)))
+        .collect();
+    let Ok(content) = doc
+        .get_page_content(page_id)
+        .and_then(|data| lopdf::content::Content::decode(&data))
+    else {
+        return Vec::new();
+    };
+
+    let mut runs = Vec::new();
+    let mut ctm = IDENTITY;
+    let mut ctm_stack: Vec<Matrix> = Vec::new();
+    let mut tm = IDENTITY;
+    let mut tlm = IDENTITY;
+    let mut font_size = 0.0;
+    let mut leading = 0.0;
+    let mut encoding: Option<&lopdf::Encoding> = None;
+
+    let mut show = |text: String, tm: &mut Matrix, ctm: &Matrix, font_size: f64, adjust: f64| {
+        let chars = text.chars().count() as f64;
+        let advance = chars * AVG_CHAR_WIDTH * font_size - adjust / 1000.0 * font_size;
+        let m = multiply(tm, ctm);
+        let scale = (m[0] * m[3] - m[1] * m[2]).abs().sqrt();
+        let width = chars * AVG_CHAR_WIDTH * font_size * scale;
+        if !text.trim().is_empty() {
+            runs.push(TextRun {
+                text,
+                x: m[4],
+                y: m[5],
+                width,
+                size: font_size * scale,
+            });
+        } else if let (false, Some(last)) = (text.is_empty(), runs.last_mut()) {
+            // Keep word breaks on the previous run so they survive quad filtering
+            last.text.push(' ');
+            last.width += width;
+        }
+        *tm = translate(tm, advance, 0.0);
+    };
+
+    for op in &content.operations {
+        let nums = || operand_numbers(&op.operands);
+        match op.operator.as_str() {
+            "q" => ctm_stack.push(ctm),
+            "Q" => ctm = ctm_stack.pop().unwrap_or(IDENTITY),
+            "cm" => {
+                if let [a, b, c, d, e, f] = nums()[..] {
+                    ctm = multiply(&[a, b, c, d, e, f], &ctm);
+                }
+            }
+            "BT" => {
+                tm = IDENTITY;
+                tlm = IDENTITY;
+            }
+            "Tf" => {
+                encoding = op
+                    .operands
+                    .first()
+                    .and_then(|o| o.as_name().ok())
+                    .and_then(|name| encodings.get(name));
+                if let Some(size) = op.operands.get(1).and_then(|o| o.as_float().ok()) {
+                    font_size = f64::from(size);
+                }
+            }
+            "TL" => {
+                if let [l] = nums()[..] {
+                    leading = l;
+                }
+            }
+            "Tm" => {
+                if let [a, b, c, d, e, f] = nums()[..] {
+                    tm = [a, b, c, d, e, f];
+                    tlm = tm;
+                }
+            }
+            "Td" | "TD" => {
+                if let [tx, ty] = nums()[..] {
+                    if op.operator == "TD" {
+                        leading = -ty;
+                    }
+                    tlm = translate(&tlm, tx, ty);
+                    tm = tlm;
+                }
+            }
+            "T*" => {
+                tlm = translate(&tlm, 0.0, -leading);
+                tm = tlm;
+            }
+            "Tj" | "'" | "\"" => {
+                if op.operator != "Tj" {
+                    tlm = translate(&tlm, 0.0, -leading);
+                    tm = tlm;
+                }
+                if let (Some(enc), Some(Object::String(bytes, _))) = (encoding, op.operands.last()) {
+                    if let Ok(text) = Document::decode_text(enc, bytes) {
+                        show(text, &mut tm, &ctm, font_size, 0.0);
+                    }
+                }
+            }
+            "TJ" => {
+                let (Some(enc), Some(Object::Array(items))) = (encoding, op.operands.first()) else {
+                    continue;
+                };
+                for item in items {
+                    match item {
+                        Object::String(bytes, _) => {
+                            if let Ok(text) = Document::decode_text(enc, bytes) {
+                                show(text, &mut tm, &ctm, font_size, 0.0);
+                            }
+                        }
+                        other => {
+                            if let Ok(adjust) = other.as_float() {
+                                // Large negative adjustments are how many producers encode spaces
+                                let text = if adjust < -200.0 { " " } else { "" };
+                                show(text.to_string(), &mut tm, &ctm, font_size, f64::from(adjust));
+                            }
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    runs
+}
+
+fn quad_bounds(q: &[f64; 8]) -> (f64, f64, f64, f64) {
+    let xs = [q[0], q[2], q[4], q[6]];
+    let ys = [q[1], q[3], q[5], q[7]];
+    (
+        xs.iter().cloned().fold(f64::INFINITY, f64::min),
+        ys.iter().cloned().fold(f64::INFINITY, f64::min),
+        xs.iter().cloned().fold(f64::NEG_INFINITY, f64::max),
+        ys.iter().cloned().fold(f64::NEG_INFINITY, f64::max),
+    )
+}
+
+/// Collect the characters of the runs whose vertical centre lies inside a quad, trimming runs
+/// that only partly overlap it horizontally.
+pub(crate) fn text_in_quads(runs: &[TextRun], quads: &[[f64; 8]]) -> String {
+    let mut lines: Vec<String> = Vec::new();
+    for quad in quads {
+        let (x0, y0, x1, y1) = quad_bounds(quad);
+        let mut line = String::new();
+        for run in runs {
+            let mid_y = run.y + run.size * 0.3;
+            if mid_y < y0 || mid_y > y1 || run.width <= 0.0 {
+                continue;
+            }
+            let chars: Vec<char> = run.text.chars().collect();
+            let char_w = run.width / chars.len() as f64;
+            let picked: String = chars
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| {
+                    let cx = run.x + (*i as f64 + 0.5) * char_w;
+                    cx >= x0 && cx <= x1
+                })
+                .map(|(_, c)| *c)
+                .collect();
+            line.push_str(&picked);
+        }
+        let line = line.split_whitespace().collect::<Vec<_>>().join(" ");
+        if !line.is_empty() {
+            lines.push(line);
+        }
+    }
+    lines.join(" ")
+}
+
+// --- Export ---
+
+/// Author written into exported annotations (`/T`).
+const EXPORT_AUTHOR: &str = "PDF Reader";
+const HIGHLIGHT_OPACITY: f64 = 0.4;
+const NOTE_ICON_SIZE: f64 = 20.0;
+const FREE_TEXT_FONT_SIZE: f64 = 12.0;
+
+/// Write a copy of `source` to `dest` with the app's annotations embedded as real PDF
+/// annotations. Annotations previously exported under the same `/NM` are replaced, so exporting
+/// an exported copy again does not duplicate them. `source` is never modified.
+pub fn export_annotated(source: &Path, dest: &Path, annotations: &[Annotation]) -> Result<(), String> {
+    let same_file = match (source.canonicalize(), dest.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => source == dest,
+    };
+    if same_file {
+        return Err("export_overwrites_source".to_string());
+    }
+
+    let mut doc = Document::load(source).map_err(|e| format!("load_pdf_failed|detail={}", e))?;
+    if doc.is_encrypted() {
+        return Err("export_encrypted_pdf".to_string());
+    }
+    let pages = doc.get_pages();
+
+    let mut by_page: BTreeMap<u32, Vec<&Annotation>> = BTreeMap::new();
+    for a in annotations.iter().filter(|a| !a.deleted) {
+        if pages.contains_key(&a.page) {
+            by_page.entry(a.page).or_default().push(a);
+        } else {
+            log::warn!("export: skipping annotation {} on missing page {}", a.id, a.page);
+        }
+    }
+
+    for (page_num, page_annotations) in by_page {
+        let page_id = pages[&page_num];
+        let names: HashSet<&str> = page_annotations.iter().map(|a| a.id.as_str()).collect();
+
+        let mut refs = existing_annots(&doc, page_id);
+        refs.retain(|obj| {
+            let name = deref(&doc, obj)
+                .as_dict()
+                .ok()
+                .and_then(|d| d.get(b"NM").ok())
+                .and_then(|nm| lopdf::decode_text_string(nm).ok());
+            !name.is_some_and(|n| names.contains(n.as_str()))
+        });
+
+        for annotation in page_annotations {
+            let dict = build_annotation(&mut doc, page_id, annotation);
+            refs.push(Object::Reference(doc.add_object(dict)));
+        }
+
+        let page = doc
+            .get_dictionary_mut(page_id)
+            .map_err(|e| format!("export_page_failed|detail={}", e))?;
+        page.set("Annots", Object::Array(refs));
+    }
+
+    let tmp_path = dest.with_extension("pdf.tmp");
+    doc.save(&tmp_path).map_err(|e| {
+        let _ = std::fs::remove_file(&tmp_path);
+        format!("save_pdf_failed|detail={}", e)
+    })?;
+    std::fs::rename(&tmp_path, dest).map_err(|e| {
+        let _ = std::fs::remove_file(&tmp_path);
+        format!("save_pdf_failed|detail={}", e)
+    })?;
+    Ok(())
+}
+
+/// The page's current `/Annots` entries, whether stored inline or behind a reference.
+fn existing_annots(doc: &Document, page_id: ObjectId) -> Vec<Object> {
+    doc.get_dictionary(page_id)
+        .ok()
+        .and_then(|page| page.get(b"Annots").ok())
+        .map(|annots| deref(doc, annots))
+        .and_then(|annots| annots.as_array().ok())
+        .cloned()
+        .unwrap_or_default()
+}
+
+fn parse_color(hex: &str) -> (f64, f64, f64) {
+    let hex = hex.trim_start_matches('#');
+    let channel = |i: usize| {
+        hex.get(i..i + 2)
+            .and_then(|c| u8::from_str_radix(c, 16).ok())
+            .map(|v| f64::from(v) / 255.0)
+    };
+    match (channel(0), channel(2), channel(4)) {
+        (Some(r), Some(g), Some(b)) => (r, g, b),
+        _ => (1.0, 0.83, 0.0),
+    }
+}
+
+fn iso_to_pdf_date(iso: &str) -> String {
+    chrono::DateTime::parse_from_rfc3339(iso)
+        .map(|d| d.with_timezone(&chrono::Utc))
+        .unwrap_or_else(|_| chrono::Utc::now())
+        .format("D:%Y%m%d%H%M%SZ")
+        .to_string()
+}
+
+fn bounds(quads: &[[f64; 8]]) -> [f64; 4] {
+    quads.iter().map(quad_bounds).fold(
+        [f64::INFINITY, f64::INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY],
+        |acc, (x0, y0, x1, y1)| [acc[0].min(x0), acc[1].min(y0), acc[2].max(x1), acc[3].max(y1)],
+    )
+}
+
+fn real_array(values: &[f64]) -> Object {
+    Object::Array(values.iter().map(|v| Object::Real(*v as f32)).collect())
+}
+
+/// A Form XObject used as the `/N` appearance of an annotation.
+fn appearance(doc: &mut Document, rect: [f64; 4], content: String, resources: Dictionary) -> ObjectId {
+    let dict = dictionary! {
+        "Type" => "XObject",
+        "Subtype" => "Form",
+        "BBox" => real_array(&rect),
+        "Resources" => resources,
+    };
+    doc.add_object(Stream::new(dict, content.into_bytes()))
+}
+
+/// A text string as UTF-16BE with a byte order mark (PDF 7.9.2.2), which every reader decodes
+/// the same way whatever the characters.
+fn utf16_text(text: &str) -> Object {
+    let mut bytes = vec![0xfe, 0xff];
+    bytes.extend(text.encode_utf16().flat_map(u16::to_be_bytes));
+    Object::String(bytes, lopdf::StringFormat::Hexadecimal)
+}
+
+/// WinAnsiEncoding code of the characters it has beyond ASCII (Latin-1 plus 0x80-0x9f).
+fn win_ansi_code(c: char) -> Option<u8> {
+    const HIGH: [(char, u8); 27] = [
+        ('€', 0x80), ('‚', 0x82), ('ƒ', 0x83), ('„', 0x84), ('…', 0x85), ('†', 0x86), ('‡', 0x87),
+        ('ˆ', 0x88), ('‰', 0x89), ('Š', 0x8a), ('‹', 0x8b), ('Œ', 0x8c), ('Ž', 0x8e), ('‘', 0x91),
+        ('’', 0x92), ('“', 0x93), ('”', 0x94), ('•', 0x95), ('–', 0x96), ('—', 0x97), ('˜', 0x98),
+        ('™', 0x99), ('š', 0x9a), ('›', 0x9b), ('œ', 0x9c), ('ž', 0x9e), ('Ÿ', 0x9f),
+    ];
+    match u32::from(c) {
+        0xa0..=0xff => Some(u32::from(c) as u8),
+        _ => HIGH.iter().find(|(ch, _)| *ch == c).map(|(_, code)| *code),
+    }
+}
+
+/// A literal string for the Helvetica (WinAnsiEncoding) appearance of free text. Characters the
+/// font cannot show become `?`; the note itself is kept in full in `/Contents`.
+fn escape_pdf_string(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '(' | ')' | '\\' => format!("\\{}", c),
+            c if c.is_ascii() && !c.is_ascii_control() => c.to_string(),
+            c => win_ansi_code(c).map_or_else(|| "?".to_string(), |code| format!("\\{:03o}", code)),
+        })
+        .collect()
+}
+
+fn build_annotation(doc: &mut Document, page_id: ObjectId, a: &Annotation) -> Dictionary {
+    let (r, g, b) = parse_color(&a.color);
+    let quads = &a.quads;
+    let mut rect = bounds(quads);
+
+    let mut dict = dictionary! {
+        "Type" => "Annot",
+        "P" => page_id,
+        "NM" => lopdf::text_string(&a.id),
+        "T" => utf16_text(EXPORT_AUTHOR),
+        "M" => Object::string_literal(iso_to_pdf_date(&a.updated_at)),
+        "C" => real_array(&[r, g, b]),
+        "F" => 4,
+    };
+    if !a.note.is_empty() {
+        dict.set("Contents", utf16_text(&a.note));
+    }
+
+    match a.kind {
+        AnnotationKind::Highlight | AnnotationKind::Underline => {
+            let flat: Vec<f64> = quads.iter().flatten().copied().collect();
+            let mut content = String::new();
+            let mut resources = Dictionary::new();
+            if a.kind == AnnotationKind::Highlight {
+                dict.set("Subtype", "Highlight");
+                resources.set(
+                    "ExtGState",
+                    dictionary! {
+                        "GS0" => dictionary! {
+                            "Type" => "ExtGState",
+                            "BM" => "Multiply",
+                            "ca" => Object::Real(HIGHLIGHT_OPACITY as f32),
+                        },
+                    },
+                );
+                content.push_str(&format!("q /GS0 gs {:.3} {:.3} {:.3} rg\n", r, g, b));
+                for q in quads {
+                    // QuadPoints order is top-left, top-right, bottom-left, bottom-right
+                    content.push_str(&format!(
+                        "{:.2} {:.2} m {:.2} {:.2} l {:.2} {:.2} l {:.2} {:.2} l h f\n",
+                        q[0], q[1], q[2], q[3], q[6], q[7], q[4], q[5]
+                    ));
+                }
+            } else {
+                dict.set("Subtype", "Underline");
+                content.push_str(&format!("q {:.3} {:.3} {:.3} RG 1 w\n", r, g, b));
+                for q in quads {
+                    content.push_str(&format!(
+                        "{:.2} {:.2} m {:.2} {:.2} l S\n",
+                        q[4],
+                        q[5] + 1.0,
+                        q[6],
+                        q[7] + 1.0
+                    ));
+                }
+            }
+            content.push('Q');
+            dict.set("QuadPoints", real_array(&flat));
+            let ap = appearance(doc, rect, content, resources);
+            dict.set("AP", dictionary! { "N" => ap });
+        }
+        AnnotationKind::Note => {
+            rect = [rect[0], rect[3] - NOTE_ICON_SIZE, rect[0] + NOTE_ICON_SIZE, rect[3]];
+            dict.set("Subtype", "Text");
+            dict.set("Name", "Comment");
+            dict.set("Open", false);
+            let content = format!(
+                "q {:.3} {:.3} {:.3} rg 0 G 0.5 w {:.2} {:.2} {:.2} {:.2} re B Q",
+                r,
+                g,
+                b,
+                rect[0] + 1.0,
+                rect[1] + 1.0,
+                NOTE_ICON_SIZE - 2.0,
+                NOTE_ICON_SIZE - 2.0
+            );
+            let ap = appearance(doc, rect, content, Dictionary::new());
+            dict.set("AP", dictionary! { "N" => ap });
+        }
+        AnnotationKind::FreeText => {
+            dict.set("Subtype", "FreeText");
+            dict.set(
+                "DA",
+                Object::string_literal(format!("/Helv {} Tf 0 g", FREE_TEXT_FONT_SIZE)),
+            );
+            let font = doc.add_object(dictionary! {
+                "Type" => "Font",
+                "Subtype" => "Type1",
+                "BaseFont" => "Helvetica",
+                "Encoding" => "WinAnsiEncoding",
+            });
+            let mut content = format!(
+                "q BT /Helv {} Tf 0 g {:.2} {:.2} Td {} TL\n",
+                FREE_TEXT_FONT_SIZE,
+                rect[0] + 2.0,
+                rect[3] - FREE_TEXT_FONT_SIZE - 2.0,
+                FREE_TEXT_FONT_SIZE * 1.2
+            );
+            for line in a.note.lines() {
+                content.push_str(&format!("({}) Tj T*\n", escape_pdf_string(line)));
+            }
+            content.push_str("ET Q");
+            let resources = dictionary! { "Font" => dictionary! { "Helv" => font } };
+            let ap = appearance(doc, rect, content, resources);
+            dict.set("AP", dictionary! { "N" => ap });
+        }
+    }
+
+    dict.set("Rect", real_array(&rect));
+    dict
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pdf_info::tests::document;
+
+    fn temp_pdf(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("pdf-annotations-{}-{}.pdf", name, std::process::id()))
+    }
+
+    /// Two pages; "Hello world" in 10pt Helvetica at (100, 700) on the first.
+    fn book() -> (Document, Vec<ObjectId>) {
+        let (mut doc, pages) = document(2);
+        let font = doc.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Helvetica",
+            "Encoding" => "WinAnsiEncoding",
+        });
+        let content = doc.add_object(Stream::new(
+            Dictionary::new(),
+            b"BT /F1 10 Tf 100 700 Td (Hello world) Tj ET".to_vec(),
+        ));
+        let page = doc.get_dictionary_mut(pages[0]).unwrap();
+        page.set("Resources", dictionary! { "Font" => dictionary! { "F1" => font } });
+        page.set("Contents", content);
+        (doc, pages)
+    }
+
+    /// A quad over "Hello" (each character is taken as 5pt wide at 10pt).
+    const HELLO: [f64; 8] = [100.0, 712.0, 126.0, 712.0, 100.0, 698.0, 126.0, 698.0];
+
+    #[test]
+    fn extracts_markup_notes_and_ink() {
+        let (mut doc, pages) = book();
+        let highlight = doc.add_object(dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Highlight",
+            "Rect" => real_array(&[100.0, 698.0, 126.0, 712.0]),
+            "QuadPoints" => real_array(&HELLO),
+            "C" => real_array(&[1.0, 0.0, 0.0]),
+            "T" => Object::string_literal("Preview"),
+            "Contents" => lopdf::text_string("Grüße, 世界"),
+            "M" => Object::string_literal("D:20260102030405+01'00'"),
+        });
+        let note = doc.add_object(dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Text",
+            "Rect" => real_array(&[10.0, 20.0, 30.0, 40.0]),
+            "Contents" => Object::string_literal("Remember this"),
+        });
+        let ink = doc.add_object(dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Ink",
+            "Rect" => real_array(&[0.0, 0.0, 10.0, 10.0]),
+            "InkList" => vec![real_array(&[1.0, 2.0, 3.0, 4.0])],
+        });
+        let link = doc.add_object(dictionary! { "Type" => "Annot", "Subtype" => "Link" });
+        let page = doc.get_dictionary_mut(pages[0]).unwrap();
+        page.set("Annots", vec![highlight.into(), link.into()]);
+        let page = doc.get_dictionary_mut(pages[1]).unwrap();
+        page.set("Annots", vec![note.into(), ink.into()]);
+        let path = temp_pdf("extract");
+        doc.save(&path).unwrap();
+
+        let found = extract_annotations(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(found.len(), 3);
+        let highlight = &found[0];
+        assert_eq!((highlight.kind, highlight.page), (PdfAnnotationKind::Highlight, 1));
+        assert_eq!(highlight.quad_points, [HELLO]);
+        assert_eq!(highlight.text.as_deref(), Some("Hello"));
+        assert_eq!(highlight.color.as_deref(), Some("#ff0000"));
+        assert_eq!(highlight.author.as_deref(), Some("Preview"));
+        assert_eq!(highlight.contents.as_deref(), Some("Grüße, 世界"));
+        assert_eq!(highlight.modified.as_deref(), Some("2026-01-02T02:04:05.000Z"));
+
+        let note = &found[1];
+        assert_eq!((note.kind, note.page), (PdfAnnotationKind::Text, 2));
+        assert_eq!(note.rect, [10.0, 20.0, 30.0, 40.0]);
+        assert_eq!(note.contents.as_deref(), Some("Remember this"));
+        assert_eq!(note.text, None);
+        assert_eq!(found[2].ink_list, [vec![1.0, 2.0, 3.0, 4.0]]);
+    }
+
+    #[test]
+    fn pdf_dates() {
+        let cases = [
+            ("D:20260102030405Z", Some("2026-01-02T03:04:05.000Z")),
+            ("D:20260102030405+05'30'", Some("2026-01-01T21:34:05.000Z")),
+            ("D:20260102030405-08'00", Some("2026-01-02T11:04:05.000Z")),
+            // Missing parts default to the start of the period
+            ("D:2026", Some("2026-01-01T00:00:00.000Z")),
+            ("20260315", Some("2026-03-15T00:00:00.000Z")),
+            ("D:202", None),
+            ("D:20261301", None),
+            ("yesterday", None),
+        ];
+        for (raw, iso) in cases {
+            assert_eq!(pdf_date_to_iso(raw).as_deref(), iso, "{}", raw);
+        }
+    }
+
+    fn run(text: &str, x: f64, y: f64) -> TextRun {
+        TextRun {
+            text: text.to_string(),
+            x,
+            y,
+            width: text.chars().count() as f64 * 5.0,
+            size: 10.0,
+        }
+    }
+
+    #[test]
+    fn text_under_quads() {
+        let runs = [run("Hello world", 100.0, 700.0), run("next line", 100.0, 686.0)];
+        assert_eq!(text_in_quads(&runs, &[HELLO]), "Hello");
+        // A quad per line, joined with a space; the partial run is trimmed to the quad
+        let second = [100.0, 698.0, 130.0, 698.0, 100.0, 684.0, 130.0, 684.0];
+        let whole_first = [100.0, 712.0, 155.0, 712.0, 100.0, 698.0, 155.0, 698.0];
+        assert_eq!(text_in_quads(&runs, &[whole_first, second]), "Hello world next l");
+        // Nothing there
+        let empty = [300.0, 500.0, 320.0, 500.0, 300.0, 490.0, 320.0, 490.0];
+        assert_eq!(text_in_quads(&runs, &[empty]), "");
+    }
+
+    fn annotation(id: &str, kind: AnnotationKind, note: &str) -> Annotation {
+        Annotation {
+            id: id.to_string(),
+            hash: "a".repeat(64),
+            kind,
+            page: 1,
+            quads: vec![HELLO],
+            text: "Hello".to_string(),
+            note: note.to_string(),
+            color: "#00ff00".to_string(),
+            created_at: "2026-01-01T00:00:00.000Z".to_string(),
+            updated_at: "2026-01-02T03:04:05.000Z".to_string(),
+            deleted: false,
+        }
+    }
+
+    #[test]
+    fn export_round_trip_keeps_non_ascii_notes() {
+        let (mut doc, _) = book();
+        let (source, dest) = (temp_pdf("export-source"), temp_pdf("export-dest"));
+        doc.save(&source).unwrap();
+        let notes = [
+            annotation("h1", AnnotationKind::Highlight, "Grüße — 世界 😀"),
+            annotation("f1", AnnotationKind::FreeText, "Café (crème)\n日本"),
+            annotation("n1", AnnotationKind::Note, "plain"),
+        ];
+
+        export_annotated(&source, &dest, &notes).unwrap();
+        // Exporting the exported copy again replaces its annotations instead of adding more
+        export_annotated(&dest, &temp_pdf("export-again"), &notes).unwrap();
+        let found = extract_annotations(&temp_pdf("export-again")).unwrap();
+        assert_eq!(found.len(), 3);
+        let highlight = found.iter().find(|a| a.kind == PdfAnnotationKind::Highlight).unwrap();
+        assert_eq!(highlight.contents.as_deref(), Some("Grüße — 世界 😀"));
+        assert_eq!(highlight.author.as_deref(), Some(EXPORT_AUTHOR));
+        assert_eq!(highlight.text.as_deref(), Some("Hello"));
+        assert_eq!(highlight.color.as_deref(), Some("#00ff00"));
+        assert_eq!(highlight.modified.as_deref(), Some("2026-01-02T03:04:05.000Z"));
+        let free_text = found.iter().find(|a| a.kind == PdfAnnotationKind::FreeText).unwrap();
+        assert_eq!(free_text.contents.as_deref(), Some("Café (crème)\n日本"));
+        let note = found.iter().find(|a| a.kind == PdfAnnotationKind::Text).unwrap();
+        assert_eq!(note.contents.as_deref(), Some("plain"));
+        for path in [source, dest, temp_pdf("export-again")] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn free_text_appearance_keeps_win_ansi_characters() {
+        assert_eq!(escape_pdf_string("Café (1) \\"), "Caf\\351 \\(1\\) \\\\");
+        assert_eq!(escape_pdf_string("“Ok” – €5"), "\\223Ok\\224 \\226 \\2005");
+        assert_eq!(escape_pdf_string("世界\t"), "???");
+    }
+
+    #[test]
+    fn positioned_text_runs() {
+        let (doc, pages) = book();
+        let runs = page_text_runs(&doc, pages[0]);
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].text, "Hello world");
+        assert_eq!((runs[0].x, runs[0].y, runs[0].width, runs[0].size), (100.0, 700.0, 55.0, 10.0));
+        assert!(page_text_runs(&doc, pages[1]).is_empty());
+    }
+}
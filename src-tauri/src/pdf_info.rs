@@ -1,11 +1,22 @@
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
-use std::collections::HashMap;
-use std::io::Read;
+use std::io::{Read, Seek, SeekFrom};
 use std::path::{Path, PathBuf};
 use std::sync::{Mutex, OnceLock};
 use std::time::{Instant, SystemTime};
 
+use crate::lru::{CacheStats, LruCache};
+use crate::{background_hash, book_id, hash_cache, icloud_placeholder, identity, pdf_meta};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DownloadState {
+    #[default]
+    Local,
+    /// In iCloud Drive but not downloaded to this device; only a placeholder is here.
+    Cloud,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PdfInfo {
     pub path: String,
@@ -14,6 +25,8 @@ pub struct PdfInfo {
     pub page_count: u32,
     pub hash: String,
     pub file_size: u64,
+    #[serde(default)]
+    pub download_state: DownloadState,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -23,6 +36,18 @@ pub struct OutlineItem {
     pub children: Vec<OutlineItem>,
 }
 
+/// Files at least this large get a provisional ID from samples first and are fully hashed
+/// in the background, so they show up in the library right away.
+const LARGE_FILE_BYTES: u64 = 64 * 1024 * 1024;
+/// Size of each sample (head, middle and tail) for the provisional ID.
+const SAMPLE_BYTES: u64 = 1024 * 1024;
+/// Largest page label number taken from a file (`/St` plus the page's offset in its range).
+/// Ranges starting beyond it keep plain page numbers.
+const MAX_LABEL_NUMBER: u32 = 1_000_000;
+/// Roman and letter labels grow with the number (`alpha(n)` repeats a letter n/26 times);
+/// past this they are written as digits.
+const MAX_LETTER_LABEL: u32 = 10_000;
+
 pub fn compute_hash(path: &Path) -> Result<String, String> {
     let file = std::fs::File::open(path).map_err(|e| format!("open_file_failed|detail={}", e))?;
     let mut reader = std::io::BufReader::new(file);
@@ -37,77 +62,44 @@ pub fn compute_hash(path: &Path) -> Result<String, String> {
         }
         hasher.update(&buf[..n]);
     }
-    let hash = hasher.finalize();
-    Ok(hex::encode(&hash[..8]))
-}
-
-mod hex {
-    pub fn encode(bytes: &[u8]) -> String {
-        bytes.iter().map(|b| format!("{:02x}", b)).collect()
-    }
-}
-
-// --- Persistent hash cache ---
-
-fn hash_cache_path() -> PathBuf {
-    crate::icloud::get_base_dir().join("hash-cache.json")
-}
-
-fn hash_cache() -> &'static Mutex<HashMap<String, String>> {
-    static CACHE: OnceLock<Mutex<HashMap<String, String>>> = OnceLock::new();
-    CACHE.get_or_init(|| {
-        let path = hash_cache_path();
-        let map = if path.exists() {
-            match std::fs::read_to_string(&path) {
-                Ok(data) => serde_json::from_str(&data).unwrap_or_default(),
-                Err(e) => {
-                    log::warn!("Failed to load hash cache: {}", e);
-                    HashMap::new()
-                }
-            }
-        } else {
-            HashMap::new()
-        };
-        log::info!("Hash cache loaded: {} entries", map.len());
-        Mutex::new(map)
-    })
-}
-
-fn hash_cache_key(path: &Path, mtime_secs: u64, size: u64) -> String {
-    format!("{}:{}:{}", path.display(), mtime_secs, size)
+    Ok(book_id::derive(&hasher.finalize(), book_id::IdVersion::CURRENT))
 }
 
-fn save_hash_cache() {
-    if let Ok(cache) = hash_cache().lock() {
-        let path = hash_cache_path();
-        if let Ok(data) = serde_json::to_string(&*cache) {
-            if let Err(e) = std::fs::write(&path, data) {
-                log::warn!("Failed to save hash cache: {}", e);
-            }
-        }
+/// Provisional ID from the file size and samples of its head, middle and tail. Marked as such
+/// (see `book_id::provisional`), so it is never taken for a real ID, but still the same on
+/// every device that samples the same file.
+fn compute_sample_hash(path: &Path, size: u64) -> Result<String, String> {
+    let mut file = std::fs::File::open(path).map_err(|e| format!("open_file_failed|detail={}", e))?;
+    let mut hasher = Sha256::new();
+    hasher.update(b"pdf-reader-sample-v1");
+    hasher.update(size.to_le_bytes());
+    let mut buf = vec![0u8; SAMPLE_BYTES as usize];
+    for offset in [0, size / 2 - SAMPLE_BYTES / 2, size - SAMPLE_BYTES] {
+        file.seek(SeekFrom::Start(offset))
+            .and_then(|_| file.read_exact(&mut buf))
+            .map_err(|e| format!("read_file_failed|detail={}", e))?;
+        hasher.update(&buf);
     }
+    Ok(book_id::provisional(&hasher.finalize()))
 }
 
+/// Book ID for a file, from the cache when it is unchanged. Large files not hashed yet get a
+/// provisional ID (see `compute_sample_hash`) and are queued for a full hash.
 pub fn compute_hash_cached(path: &Path) -> Result<String, String> {
-    let meta = std::fs::metadata(path).map_err(|e| format!("get_metadata_failed|detail={}", e))?;
-    let size = meta.len();
-    let mtime_secs = meta
-        .modified()
-        .map_err(|e| format!("get_mtime_failed|detail={}", e))?
-        .duration_since(SystemTime::UNIX_EPOCH)
-        .map(|d| d.as_secs())
-        .unwrap_or(0);
-
-    let key = hash_cache_key(path, mtime_secs, size);
+    let (key, size) = hash_cache::key_for(path)?;
 
-    if let Ok(cache) = hash_cache().lock() {
-        if let Some(hash) = cache.get(&key) {
-            log::debug!("Hash cache hit: {}", path.display());
-            return Ok(hash.clone());
-        }
+    // Entries from before full-length IDs are recomputed
+    if let Some(hash) = hash_cache::get(&key).filter(|h| book_id::version_of(h) == Some(book_id::IdVersion::CURRENT)) {
+        log::debug!("Hash cache hit: {}", path.display());
+        return Ok(hash);
     }
 
     log::debug!("Hash cache miss: {}", path.display());
+    if size >= LARGE_FILE_BYTES {
+        let provisional = compute_sample_hash(path, size)?;
+        background_hash::enqueue(path.to_path_buf(), provisional.clone());
+        return Ok(provisional);
+    }
     let start = Instant::now();
     let hash = compute_hash(path)?;
     let elapsed = start.elapsed();
@@ -116,57 +108,93 @@ pub fn compute_hash_cached(path: &Path) -> Result<String, String> {
         log::info!("Slow hash for {}: {}ms", path.display(), elapsed.as_millis());
     }
 
-    if let Ok(mut cache) = hash_cache().lock() {
-        cache.insert(key, hash.clone());
-    }
-    save_hash_cache();
+    hash_cache::insert(key, hash.clone());
+    Ok(hash)
+}
 
+/// Full hash of a file that was given a provisional ID. Cached, and the file's cached info is
+/// dropped so the next scan picks up the confirmed ID.
+pub fn confirm_hash(path: &Path) -> Result<String, String> {
+    let (key, _) = hash_cache::key_for(path)?;
+    let hash = compute_hash(path)?;
+    // Only cache it if the file did not change while it was being read
+    if hash_cache::key_for(path)?.0 == key {
+        hash_cache::insert(key, hash.clone());
+    }
+    pdf_cache().lock().unwrap().remove(&path.to_path_buf());
     Ok(hash)
 }
 
 // --- PDF info cache ---
 
-type CacheKey = (PathBuf, SystemTime);
+/// Enough for a large library; least recently used entries go first beyond this.
+const PDF_CACHE_CAPACITY: usize = 1000;
+
+struct CachedInfo {
+    mtime: SystemTime,
+    size: u64,
+    info: PdfInfo,
+}
+
+fn pdf_cache() -> &'static Mutex<LruCache<PathBuf, CachedInfo>> {
+    static CACHE: OnceLock<Mutex<LruCache<PathBuf, CachedInfo>>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(LruCache::new(PDF_CACHE_CAPACITY)))
+}
 
-fn pdf_cache() -> &'static Mutex<HashMap<CacheKey, PdfInfo>> {
-    static CACHE: OnceLock<Mutex<HashMap<CacheKey, PdfInfo>>> = OnceLock::new();
-    CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+pub fn pdf_cache_stats() -> CacheStats {
+    pdf_cache().lock().unwrap().stats()
 }
 
 /// Remove all cached entries (pdf info + hash) for a given path.
 pub fn invalidate_cache(path: &Path) {
-    if let Ok(mut cache) = pdf_cache().lock() {
-        cache.retain(|(p, _), _| p != path);
-    }
-    if let Ok(mut cache) = hash_cache().lock() {
-        let prefix = format!("{}:", path.display());
-        cache.retain(|k, _| !k.starts_with(&prefix));
+    pdf_cache().lock().unwrap().remove(&path.to_path_buf());
+    hash_cache::remove_path(path);
+}
+
+fn file_changed(path: &Path, cached: &CachedInfo) -> bool {
+    std::fs::metadata(path)
+        .and_then(|m| Ok((m.modified()?, m.len())))
+        .map_or(true, |(mtime, size)| mtime != cached.mtime || size != cached.size)
+}
+
+/// Drop PDF info entries whose file is gone or has changed. Returns how many entries were
+/// (or, with `dry_run`, would be) removed.
+pub fn prune_pdf_cache(dry_run: bool) -> usize {
+    let mut cache = pdf_cache().lock().unwrap();
+    let before = cache.len();
+    if dry_run {
+        return cache.iter().filter(|(path, cached)| file_changed(path, cached)).count();
     }
-    save_hash_cache();
+    cache.retain(|path, cached| !file_changed(path, cached));
+    before - cache.len()
 }
 
 pub fn extract_info(path: &Path) -> Result<PdfInfo, String> {
-    // Check cache by path + mtime
-    let mtime = std::fs::metadata(path)
-        .and_then(|m| m.modified())
+    // Check cache by path + mtime + size
+    let meta = std::fs::metadata(path).map_err(|e| format!("get_metadata_failed|detail={}", e))?;
+    let mtime = meta
+        .modified()
         .map_err(|e| format!("get_metadata_failed|detail={}", e))?;
-
-    let key = (path.to_path_buf(), mtime);
-
-    if let Ok(cache) = pdf_cache().lock() {
-        if let Some(cached) = cache.get(&key) {
-            return Ok(cached.clone());
-        }
+    let size = meta.len();
+    let key = path.to_path_buf();
+
+    if let Some(cached) = pdf_cache()
+        .lock()
+        .unwrap()
+        .get_if(&key, |c| c.mtime == mtime && c.size == size)
+    {
+        return Ok(cached.info.clone());
     }
 
     let info = extract_info_uncached(path)?;
-
-    if let Ok(mut cache) = pdf_cache().lock() {
-        // Remove stale entries for the same path with different mtime
-        cache.retain(|(p, _), _| p != &key.0);
-        cache.insert(key, info.clone());
-    }
-
+    pdf_cache().lock().unwrap().insert(
+        key,
+        CachedInfo {
+            mtime,
+            size,
+            info: info.clone(),
+        },
+    );
     Ok(info)
 }
 
@@ -187,57 +215,194 @@ fn extract_info_uncached(path: &Path) -> Result<PdfInfo, String> {
     let hash = compute_hash_cached(path)?;
     log::debug!("  hash: {}ms", hash_start.elapsed().as_millis());
 
+    let meta_start = Instant::now();
+    let (page_count, title) = match pdf_meta::read_metadata(path) {
+        Ok(meta) => {
+            log::debug!("  metadata: {}ms", meta_start.elapsed().as_millis());
+            (meta.page_count, meta.title)
+        }
+        Err(e) => {
+            log::debug!("  metadata fast path failed for {} ({}), loading full document", filename, e);
+            load_info_full(path)?
+        }
+    };
+    let title = title.unwrap_or_else(|| {
+        // Fallback: use filename without extension
+        path.file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("")
+            .to_string()
+    });
+
+    log::debug!("  extract_info total for {}: {}ms", filename, total_start.elapsed().as_millis());
+
+    Ok(PdfInfo {
+        path: path.to_string_lossy().to_string(),
+        filename,
+        title,
+        page_count,
+        hash,
+        file_size,
+        download_state: DownloadState::Local,
+    })
+}
+
+/// Info for a book iCloud Drive has not downloaded yet, from its placeholder. `path` is where
+/// the book will appear. The ID, title and page count are what this device last saw there, if
+/// it ever had the book; otherwise the ID is empty and the page count 0.
+pub fn placeholder_info(placeholder_path: &Path) -> Result<PdfInfo, String> {
+    let placeholder = icloud_placeholder::read(placeholder_path)?;
+    let path = icloud_placeholder::download_path(placeholder_path, &placeholder);
+    let path_str = path.to_string_lossy().to_string();
+    let (hash, title, page_count) = identity::last_seen(&path_str).unwrap_or_else(|| {
+        let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("").to_string();
+        (String::new(), stem, 0)
+    });
+    Ok(PdfInfo {
+        path: path_str,
+        filename: placeholder.name,
+        title,
+        page_count,
+        hash,
+        file_size: placeholder.size.unwrap_or(0),
+        download_state: DownloadState::Cloud,
+    })
+}
+
+/// Page count and title from a full parse, for files the metadata fast path cannot read.
+fn load_info_full(path: &Path) -> Result<(u32, Option<String>), String> {
     let load_start = Instant::now();
     let doc =
         lopdf::Document::load(path).map_err(|e| format!("load_pdf_failed|detail={}", e))?;
     log::debug!("  lopdf load: {}ms", load_start.elapsed().as_millis());
 
     let page_count = doc.get_pages().len() as u32;
-
-    // Try to extract title from PDF metadata
     let title = doc
         .trailer
         .get(b"Info")
         .ok()
         .and_then(|info| doc.dereference(info).ok())
-        .and_then(|(_, obj)| {
-            if let lopdf::Object::Dictionary(dict) = obj {
-                dict.get(b"Title")
-                    .ok()
-                    .and_then(|t| match t {
-                        lopdf::Object::String(bytes, _) => {
-                            String::from_utf8(bytes.clone()).ok().filter(|s| !s.trim().is_empty())
-                        }
-                        _ => None,
-                    })
-            } else {
-                None
-            }
-        })
-        .unwrap_or_else(|| {
-            // Fallback: use filename without extension
-            path.file_stem()
-                .and_then(|s| s.to_str())
-                .unwrap_or("")
-                .to_string()
+        .and_then(|(_, obj)| match obj {
+            lopdf::Object::Dictionary(dict) => match dict.get(b"Title").ok()? {
+                lopdf::Object::String(bytes, _) => pdf_meta::decode_text(bytes),
+                _ => None,
+            },
+            _ => None,
         });
-
-    log::debug!("  extract_info total for {}: {}ms", filename, total_start.elapsed().as_millis());
-
-    Ok(PdfInfo {
-        path: path.to_string_lossy().to_string(),
-        filename,
-        title,
-        page_count,
-        hash,
-        file_size,
-    })
+    Ok((page_count, title))
 }
 
 pub fn extract_outline(path: &Path) -> Result<Vec<OutlineItem>, String> {
     let doc =
         lopdf::Document::load(path).map_err(|e| format!("load_pdf_failed|detail={}", e))?;
+    extract_outline_from(&doc)
+}
+
+/// Read a string entry (`/Title`, `/Author`, ...) from the document's Info dictionary.
+pub fn info_string(doc: &lopdf::Document, key: &[u8]) -> Option<String> {
+    let (_, info) = doc
+        .trailer
+        .get(b"Info")
+        .ok()
+        .and_then(|info| doc.dereference(info).ok())?;
+    let value = info.as_dict().ok()?.get(key).ok()?;
+    lopdf::decode_text_string(value)
+        .ok()
+        .filter(|s| !s.trim().is_empty())
+}
+
+/// Page labels as shown by PDF viewers (`/PageLabels`), falling back to 1-based page numbers.
+pub fn page_labels(doc: &lopdf::Document) -> Vec<String> {
+    let page_count = doc.get_pages().len();
+    let mut labels: Vec<String> = (1..=page_count).map(|n| n.to_string()).collect();
+
+    let nums = doc
+        .catalog()
+        .ok()
+        .and_then(|c| c.get(b"PageLabels").ok())
+        .and_then(|o| doc.dereference(o).ok())
+        .and_then(|(_, o)| o.as_dict().ok())
+        .and_then(|d| d.get(b"Nums").ok())
+        .and_then(|o| doc.dereference(o).ok())
+        .and_then(|(_, o)| o.as_array().ok());
+    let Some(nums) = nums else {
+        return labels;
+    };
+
+    let mut ranges: Vec<(usize, &lopdf::Dictionary)> = nums
+        .chunks_exact(2)
+        .filter_map(|pair| {
+            let start = pair[0].as_i64().ok().filter(|n| *n >= 0)? as usize;
+            let (_, dict) = doc.dereference(&pair[1]).ok()?;
+            Some((start, dict.as_dict().ok()?))
+        })
+        .collect();
+    ranges.sort_by_key(|(start, _)| *start);
+
+    for (i, (start, dict)) in ranges.iter().enumerate() {
+        let end = ranges.get(i + 1).map_or(page_count, |(next, _)| *next).min(page_count);
+        let style = dict.get(b"S").and_then(lopdf::Object::as_name).ok();
+        let prefix = dict
+            .get(b"P")
+            .ok()
+            .and_then(|p| lopdf::decode_text_string(p).ok())
+            .unwrap_or_default();
+        let first = match dict.get(b"St").and_then(lopdf::Object::as_i64) {
+            Ok(st) => match u32::try_from(st) {
+                Ok(st) if (1..=MAX_LABEL_NUMBER).contains(&st) => st,
+                _ => continue,
+            },
+            Err(_) => 1,
+        };
+        for (offset, label) in labels.iter_mut().enumerate().take(end).skip(*start) {
+            let Some(n) = u32::try_from(offset - start)
+                .ok()
+                .and_then(|offset| first.checked_add(offset))
+                .filter(|n| *n <= MAX_LABEL_NUMBER)
+            else {
+                break;
+            };
+            let number = match style {
+                Some(b"D") => n.to_string(),
+                Some(b"R" | b"r" | b"A" | b"a") if n > MAX_LETTER_LABEL => n.to_string(),
+                Some(b"R") => roman(n).to_uppercase(),
+                Some(b"r") => roman(n),
+                Some(b"A") => alpha(n).to_uppercase(),
+                Some(b"a") => alpha(n),
+                _ => String::new(),
+            };
+            *label = format!("{}{}", prefix, number);
+        }
+    }
+
+    labels
+}
 
+fn roman(mut n: u32) -> String {
+    const TABLE: [(u32, &str); 13] = [
+        (1000, "m"), (900, "cm"), (500, "d"), (400, "cd"), (100, "c"), (90, "xc"),
+        (50, "l"), (40, "xl"), (10, "x"), (9, "ix"), (5, "v"), (4, "iv"), (1, "i"),
+    ];
+    let mut out = String::new();
+    for (value, numeral) in TABLE {
+        while n >= value {
+            out.push_str(numeral);
+            n -= value;
+        }
+    }
+    out
+}
+
+/// a..z, then aa..zz, and so on (PDF spec 12.4.2). Empty for 0.
+fn alpha(n: u32) -> String {
+    let Some(n) = n.checked_sub(1) else {
+        return String::new();
+    };
+    let letter = (b'a' + (n % 26) as u8) as char;
+    letter.to_string().repeat((n / 26 + 1) as usize)
+}
+
+pub fn extract_outline_from(doc: &lopdf::Document) -> Result<Vec<OutlineItem>, String> {
     let mut page_entries: Vec<(u32, lopdf::ObjectId)> = doc.get_pages().into_iter().collect();
     page_entries.sort_by_key(|(num, _)| *num);
     let pages: Vec<lopdf::ObjectId> = page_entries.into_iter().map(|(_, id)| id).collect();
@@ -348,5 +513,108 @@ pub fn extract_outline(path: &Path) -> Result<Vec<OutlineItem>, String> {
         _ => return Ok(Vec::new()),
     };
 
-    Ok(parse_outline_items(&doc, first_id, &pages, 0))
+    Ok(parse_outline_items(doc, first_id, &pages, 0))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use lopdf::{dictionary, Object, ObjectId};
+
+    /// An in-memory PDF with `pages` empty US Letter pages; returns it and the page IDs.
+    pub(crate) fn document(pages: usize) -> (lopdf::Document, Vec<ObjectId>) {
+        let mut doc = lopdf::Document::with_version("1.7");
+        let pages_id = doc.new_object_id();
+        let page_ids: Vec<ObjectId> = (0..pages)
+            .map(|_| {
+                doc.add_object(dictionary! {
+                    "Type" => "Page",
+                    "Parent" => pages_id,
+                    "MediaBox" => vec![0.into(), 0.into(), 612.into(), 792.into()],
+                })
+            })
+            .collect();
+        let kids: Vec<Object> = page_ids.iter().map(|&id| id.into()).collect();
+        doc.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! { "Type" => "Pages", "Kids" => kids, "Count" => pages as i64 }),
+        );
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        doc.trailer.set("Root", catalog_id);
+        (doc, page_ids)
+    }
+
+    fn labelled(pages: usize, nums: Vec<Object>) -> Vec<String> {
+        let (mut doc, _) = document(pages);
+        doc.catalog_mut().unwrap().set("PageLabels", dictionary! { "Nums" => nums });
+        page_labels(&doc)
+    }
+
+    fn style(s: &str) -> Object {
+        dictionary! { "S" => Object::Name(s.as_bytes().to_vec()) }.into()
+    }
+
+    #[test]
+    fn roman_numerals() {
+        let cases = [
+            (1, "i"), (4, "iv"), (9, "ix"), (14, "xiv"), (40, "xl"), (90, "xc"), (400, "cd"),
+            (1994, "mcmxciv"), (3999, "mmmcmxcix"),
+        ];
+        for (n, numeral) in cases {
+            assert_eq!(roman(n), numeral);
+        }
+        assert_eq!(roman(0), "");
+    }
+
+    #[test]
+    fn letter_labels() {
+        assert_eq!(alpha(1), "a");
+        assert_eq!(alpha(26), "z");
+        assert_eq!(alpha(27), "aa");
+        assert_eq!(alpha(53), "aaa");
+        assert_eq!(alpha(0), "");
+    }
+
+    #[test]
+    fn page_labels_by_range() {
+        let mut appendix = dictionary! { "S" => "A", "P" => Object::string_literal("App. ") };
+        appendix.set("St", 2);
+        let labels = labelled(
+            7,
+            vec![0.into(), style("r"), 2.into(), style("D"), 5.into(), appendix.into()],
+        );
+        assert_eq!(labels, ["i", "ii", "1", "2", "3", "App. B", "App. C"]);
+    }
+
+    #[test]
+    fn page_labels_without_a_tree_are_page_numbers() {
+        assert_eq!(page_labels(&document(3).0), ["1", "2", "3"]);
+    }
+
+    #[test]
+    fn page_labels_with_hostile_start_numbers() {
+        // Does not fit in u32, would wrap to 0
+        let mut wraps = dictionary! { "S" => "a" };
+        wraps.set("St", 1i64 << 32);
+        assert_eq!(labelled(2, vec![0.into(), wraps.into()]), ["1", "2"]);
+
+        // Valid but absurd: kept as page numbers
+        let mut huge = dictionary! { "S" => "r" };
+        huge.set("St", i64::from(u32::MAX));
+        assert_eq!(labelled(2, vec![0.into(), huge.into()]), ["1", "2"]);
+
+        // Near the limit the numbering stops instead of overflowing
+        let mut edge = dictionary! { "S" => "D" };
+        edge.set("St", i64::from(MAX_LABEL_NUMBER));
+        assert_eq!(labelled(2, vec![0.into(), edge.into()]), ["1000000", "2"]);
+
+        // Long letter labels are written as digits
+        let mut long = dictionary! { "S" => "a" };
+        long.set("St", i64::from(MAX_LETTER_LABEL) + 1);
+        assert_eq!(labelled(1, vec![0.into(), long.into()]), ["10001"]);
+
+        let mut negative = dictionary! { "S" => "D" };
+        negative.set("St", -5);
+        assert_eq!(labelled(1, vec![0.into(), negative.into()]), ["1"]);
+    }
 }
@@ -0,0 +1,1100 @@
+//! Page count and title read straight from the cross-reference data and the few objects that
+//! hold them (trailer, catalog, page tree root, Info dictionary), without parsing the rest of
+//! the file. Anything unusual (encryption, broken offsets, unsupported filters) is reported as
+//! an error so the caller can fall back to a full parse.
+
+use flate2::read::ZlibDecoder;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// `startxref` must appear this close to the end of the file.
+const TAIL_BYTES: u64 = 2048;
+/// Largest object (without its stream) or classic xref section read in one go.
+const MAX_READ_BYTES: usize = 32 * 1024 * 1024;
+/// Incremental updates followed through `/Prev` before giving up.
+const MAX_XREF_SECTIONS: usize = 256;
+/// Largest decoded stream (xref or object stream). Real ones are far smaller; this stops a
+/// small compressed stream from inflating into gigabytes.
+const MAX_DECODED_BYTES: u64 = 64 * 1024 * 1024;
+/// Largest predictor row. Cross-reference rows are a few bytes wide.
+const MAX_PREDICTOR_ROW_BYTES: usize = 64 * 1024;
+/// Arrays and dictionaries nested deeper than this are treated as malformed.
+const MAX_NESTING: usize = 64;
+
+#[derive(Debug, Clone)]
+pub struct PdfMetadata {
+    pub page_count: u32,
+    pub title: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Obj {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Real(f64),
+    Name(Vec<u8>),
+    Str(Vec<u8>),
+    Array(Vec<Obj>),
+    Dict(Dict),
+    Ref(u32),
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+struct Dict(Vec<(Vec<u8>, Obj)>);
+
+impl Dict {
+    fn get(&self, key: &[u8]) -> Option<&Obj> {
+        self.0.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+}
+
+impl Obj {
+    fn as_int(&self) -> Option<i64> {
+        match self {
+            Obj::Int(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    fn as_dict(&self) -> Option<&Dict> {
+        match self {
+            Obj::Dict(d) => Some(d),
+            _ => None,
+        }
+    }
+}
+
+// --- Tokenizer / object parser ---
+
+#[derive(Debug)]
+enum ParseError {
+    /// Ran off the end of the buffer; a larger read may succeed.
+    Incomplete,
+    Invalid,
+}
+
+type ParseResult<T> = Result<T, ParseError>;
+
+fn is_whitespace(b: u8) -> bool {
+    matches!(b, b'\0' | b'\t' | b'\n' | b'\x0c' | b'\r' | b' ')
+}
+
+fn is_delimiter(b: u8) -> bool {
+    matches!(b, b'(' | b')' | b'<' | b'>' | b'[' | b']' | b'{' | b'}' | b'/' | b'%')
+}
+
+struct Parser<'a> {
+    data: &'a [u8],
+    pos: usize,
+    /// Arrays and dictionaries currently open.
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0, depth: 0 }
+    }
+
+    fn peek(&self) -> ParseResult<u8> {
+        self.data.get(self.pos).copied().ok_or(ParseError::Incomplete)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(&b) = self.data.get(self.pos) {
+            if is_whitespace(b) {
+                self.pos += 1;
+            } else if b == b'%' {
+                while self.data.get(self.pos).is_some_and(|&b| b != b'\n' && b != b'\r') {
+                    self.pos += 1;
+                }
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// A run of regular characters (a number or keyword).
+    fn token(&mut self) -> ParseResult<&'a [u8]> {
+        self.skip_whitespace();
+        let start = self.pos;
+        while let Some(&b) = self.data.get(self.pos) {
+            if is_whitespace(b) || is_delimiter(b) {
+                break;
+            }
+            self.pos += 1;
+        }
+        if self.pos == self.data.len() {
+            // The token may continue past the buffer
+            return Err(ParseError::Incomplete);
+        }
+        if self.pos == start {
+            return Err(ParseError::Invalid);
+        }
+        Ok(&self.data[start..self.pos])
+    }
+
+    fn keyword(&mut self, expected: &[u8]) -> ParseResult<()> {
+        if self.token()? == expected {
+            Ok(())
+        } else {
+            Err(ParseError::Invalid)
+        }
+    }
+
+    fn unsigned(&mut self) -> ParseResult<u64> {
+        std::str::from_utf8(self.token()?)
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .ok_or(ParseError::Invalid)
+    }
+
+    fn object(&mut self) -> ParseResult<Obj> {
+        self.skip_whitespace();
+        match self.peek()? {
+            b'/' => self.name().map(Obj::Name),
+            b'<' => {
+                if self.data.get(self.pos + 1) == Some(&b'<') {
+                    self.nested(Self::dict).map(Obj::Dict)
+                } else {
+                    self.hex_string().map(Obj::Str)
+                }
+            }
+            b'(' => self.literal_string().map(Obj::Str),
+            b'[' => self.nested(Self::array).map(Obj::Array),
+            _ => {
+                let token = self.token()?;
+                match token {
+                    b"true" => Ok(Obj::Bool(true)),
+                    b"false" => Ok(Obj::Bool(false)),
+                    b"null" => Ok(Obj::Null),
+                    _ => self.number(token),
+                }
+            }
+        }
+    }
+
+    /// Parse an array or dictionary one level deeper, within `MAX_NESTING`.
+    fn nested<T>(&mut self, parse: fn(&mut Self) -> ParseResult<T>) -> ParseResult<T> {
+        if self.depth >= MAX_NESTING {
+            return Err(ParseError::Invalid);
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn array(&mut self) -> ParseResult<Vec<Obj>> {
+        self.pos += 1;
+        let mut items = Vec::new();
+        loop {
+            self.skip_whitespace();
+            if self.peek()? == b']' {
+                self.pos += 1;
+                return Ok(items);
+            }
+            items.push(self.object()?);
+        }
+    }
+
+    /// A number, or an indirect reference `num gen R` starting with it.
+    fn number(&mut self, token: &[u8]) -> ParseResult<Obj> {
+        let text = std::str::from_utf8(token).map_err(|_| ParseError::Invalid)?;
+        let Ok(int) = text.parse::<i64>() else {
+            return text.parse::<f64>().map(Obj::Real).map_err(|_| ParseError::Invalid);
+        };
+        let after = self.pos;
+        let is_ref = (|| {
+            self.unsigned()?;
+            self.keyword(b"R")
+        })();
+        match is_ref {
+            Ok(()) => u32::try_from(int).map(Obj::Ref).map_err(|_| ParseError::Invalid),
+            Err(ParseError::Incomplete) => Err(ParseError::Incomplete),
+            Err(ParseError::Invalid) => {
+                self.pos = after;
+                Ok(Obj::Int(int))
+            }
+        }
+    }
+
+    fn name(&mut self) -> ParseResult<Vec<u8>> {
+        self.pos += 1;
+        let mut name = Vec::new();
+        while let Some(&b) = self.data.get(self.pos) {
+            if is_whitespace(b) || is_delimiter(b) {
+                return Ok(name);
+            }
+            if b == b'#' {
+                let hex = self.data.get(self.pos + 1..self.pos + 3).ok_or(ParseError::Incomplete)?;
+                let value = std::str::from_utf8(hex)
+                    .ok()
+                    .and_then(|h| u8::from_str_radix(h, 16).ok())
+                    .ok_or(ParseError::Invalid)?;
+                name.push(value);
+                self.pos += 3;
+            } else {
+                name.push(b);
+                self.pos += 1;
+            }
+        }
+        Err(ParseError::Incomplete)
+    }
+
+    fn dict(&mut self) -> ParseResult<Dict> {
+        self.pos += 2;
+        let mut entries = Vec::new();
+        loop {
+            self.skip_whitespace();
+            match self.peek()? {
+                b'>' => {
+                    if self.data.get(self.pos + 1) != Some(&b'>') {
+                        return Err(ParseError::Invalid);
+                    }
+                    self.pos += 2;
+                    return Ok(Dict(entries));
+                }
+                b'/' => {
+                    let key = self.name()?;
+                    let value = self.object()?;
+                    entries.push((key, value));
+                }
+                _ => return Err(ParseError::Invalid),
+            }
+        }
+    }
+
+    fn hex_string(&mut self) -> ParseResult<Vec<u8>> {
+        self.pos += 1;
+        let mut digits = Vec::new();
+        loop {
+            let b = self.peek()?;
+            self.pos += 1;
+            match b {
+                b'>' => break,
+                b if b.is_ascii_hexdigit() => digits.push(b),
+                b if is_whitespace(b) => {}
+                _ => return Err(ParseError::Invalid),
+            }
+        }
+        if digits.len() % 2 == 1 {
+            digits.push(b'0');
+        }
+        Ok(digits
+            .chunks(2)
+            .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).unwrap_or("00"), 16).unwrap_or(0))
+            .collect())
+    }
+
+    fn literal_string(&mut self) -> ParseResult<Vec<u8>> {
+        self.pos += 1;
+        let mut out = Vec::new();
+        let mut depth = 1;
+        loop {
+            let b = self.peek()?;
+            self.pos += 1;
+            match b {
+                b'(' => {
+                    depth += 1;
+                    out.push(b);
+                }
+                b')' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(out);
+                    }
+                    out.push(b);
+                }
+                b'\\' => {
+                    let escaped = self.peek()?;
+                    self.pos += 1;
+                    match escaped {
+                        b'n' => out.push(b'\n'),
+                        b'r' => out.push(b'\r'),
+                        b't' => out.push(b'\t'),
+                        b'b' => out.push(8),
+                        b'f' => out.push(12),
+                        b'\r' => {
+                            if self.data.get(self.pos) == Some(&b'\n') {
+                                self.pos += 1;
+                            }
+                        }
+                        b'\n' => {}
+                        b'0'..=b'7' => {
+                            let mut value = (escaped - b'0') as u32;
+                            for _ in 0..2 {
+                                match self.data.get(self.pos) {
+                                    Some(&d @ b'0'..=b'7') => {
+                                        value = value * 8 + (d - b'0') as u32;
+                                        self.pos += 1;
+                                    }
+                                    _ => break,
+                                }
+                            }
+                            out.push(value as u8);
+                        }
+                        other => out.push(other),
+                    }
+                }
+                _ => out.push(b),
+            }
+        }
+    }
+}
+
+// --- Stream decoding ---
+
+fn decode_stream(dict: &Dict, raw: Vec<u8>) -> Result<Vec<u8>, String> {
+    let filter = match dict.get(b"Filter") {
+        None => None,
+        Some(Obj::Name(name)) => Some(name.as_slice()),
+        Some(Obj::Array(items)) if items.len() == 1 => match &items[0] {
+            Obj::Name(name) => Some(name.as_slice()),
+            _ => return Err("unsupported filter".to_string()),
+        },
+        Some(Obj::Array(items)) if items.is_empty() => None,
+        _ => return Err("unsupported filter".to_string()),
+    };
+    let data = match filter {
+        None => raw,
+        Some(b"FlateDecode") => {
+            let mut out = Vec::new();
+            ZlibDecoder::new(raw.as_slice())
+                .take(MAX_DECODED_BYTES + 1)
+                .read_to_end(&mut out)
+                .map_err(|e| format!("inflate failed: {}", e))?;
+            if out.len() as u64 > MAX_DECODED_BYTES {
+                return Err("stream too large".to_string());
+            }
+            out
+        }
+        Some(_) => return Err("unsupported filter".to_string()),
+    };
+
+    let params = match dict.get(b"DecodeParms") {
+        Some(Obj::Dict(d)) => Some(d),
+        Some(Obj::Array(items)) => items.first().and_then(Obj::as_dict),
+        _ => None,
+    };
+    let predictor = params.and_then(|p| p.get(b"Predictor")).and_then(Obj::as_int).unwrap_or(1);
+    if predictor < 10 {
+        return if predictor == 1 { Ok(data) } else { Err("unsupported predictor".to_string()) };
+    }
+    let int_param = |key: &[u8], default: i64| {
+        let value = params.and_then(|p| p.get(key)).and_then(Obj::as_int).unwrap_or(default);
+        usize::try_from(value.max(1)).map_err(|_| "bad predictor parameters".to_string())
+    };
+    let colors = int_param(b"Colors", 1)?;
+    let bits = int_param(b"BitsPerComponent", 8)?;
+    let columns = int_param(b"Columns", 1)?;
+    let pixel_bits = colors.checked_mul(bits).ok_or("bad predictor parameters")?;
+    let row_len = pixel_bits
+        .checked_mul(columns)
+        .map(|row_bits| row_bits.div_ceil(8))
+        .filter(|&len| len <= MAX_PREDICTOR_ROW_BYTES)
+        .ok_or("bad predictor parameters")?;
+    Ok(unpredict_png(&data, pixel_bits.div_ceil(8), row_len))
+}
+
+/// Undo PNG row filters (predictors 10-15), as used by cross-reference streams.
+fn unpredict_png(data: &[u8], bpp: usize, row_len: usize) -> Vec<u8> {
+    let mut out: Vec<u8> = Vec::with_capacity(data.len());
+    let mut prev = vec![0u8; row_len];
+    for chunk in data.chunks(row_len + 1) {
+        let (filter, row) = (chunk[0], &chunk[1..]);
+        let mut cur = row.to_vec();
+        cur.resize(row_len, 0);
+        for i in 0..row_len {
+            let left = if i >= bpp { cur[i - bpp] } else { 0 };
+            let up = prev[i];
+            let up_left = if i >= bpp { prev[i - bpp] } else { 0 };
+            cur[i] = cur[i].wrapping_add(match filter {
+                1 => left,
+                2 => up,
+                3 => ((left as u16 + up as u16) / 2) as u8,
+                4 => {
+                    let p = left as i16 + up as i16 - up_left as i16;
+                    let (pa, pb, pc) = ((p - left as i16).abs(), (p - up as i16).abs(), (p - up_left as i16).abs());
+                    if pa <= pb && pa <= pc {
+                        left
+                    } else if pb <= pc {
+                        up
+                    } else {
+                        up_left
+                    }
+                }
+                _ => 0,
+            });
+        }
+        out.extend_from_slice(&cur[..row.len().min(row_len)]);
+        prev = cur;
+    }
+    out
+}
+
+// --- File access ---
+
+#[derive(Debug, Clone, Copy)]
+enum XrefEntry {
+    Offset(u64),
+    /// Inside an object stream: (stream object number, index in the stream).
+    Compressed(u32, u32),
+}
+
+/// Decoded contents of an object stream and the (object number, offset) of each object in it.
+type ObjectStream = (Vec<u8>, Vec<(u32, usize)>);
+
+struct Reader {
+    file: File,
+    len: u64,
+    xref: HashMap<u32, XrefEntry>,
+    object_streams: HashMap<u32, ObjectStream>,
+    /// Offsets of the cross-reference sections read so far, through `/Prev` and `/XRefStm`.
+    xref_sections: HashSet<u64>,
+    /// Objects being read, so one whose `/Length` (or object stream) leads back to itself is
+    /// an error rather than endless recursion.
+    resolving: HashSet<u32>,
+}
+
+impl Reader {
+    fn read_at(&mut self, offset: u64, len: usize) -> Result<Vec<u8>, String> {
+        let len = len.min(self.len.saturating_sub(offset) as usize);
+        let mut buf = vec![0u8; len];
+        self.file
+            .seek(SeekFrom::Start(offset))
+            .and_then(|_| self.file.read_exact(&mut buf))
+            .map_err(|e| e.to_string())?;
+        Ok(buf)
+    }
+
+    /// Parse with `parse` on a buffer starting at `offset`, growing it while the parser runs
+    /// off its end.
+    fn parse_at<T>(
+        &mut self,
+        offset: u64,
+        mut parse: impl FnMut(&mut Parser) -> ParseResult<T>,
+    ) -> Result<(T, usize), String> {
+        let mut size = 4096;
+        loop {
+            let buf = self.read_at(offset, size)?;
+            let mut parser = Parser::new(&buf);
+            match parse(&mut parser) {
+                Ok(value) => return Ok((value, parser.pos)),
+                Err(ParseError::Incomplete) if buf.len() == size && size < MAX_READ_BYTES => size *= 4,
+                Err(_) => return Err(format!("unparsable data at {}", offset)),
+            }
+        }
+    }
+
+    /// The object at `offset` (`num gen obj ...`) and its stream, decoded.
+    fn object_at(&mut self, offset: u64, expected: Option<u32>) -> Result<(Obj, Option<Vec<u8>>), String> {
+        let ((num, obj), end) = self.parse_at(offset, |p| {
+            let num = p.unsigned()?;
+            p.unsigned()?;
+            p.keyword(b"obj")?;
+            let obj = p.object()?;
+            // Make sure a following `stream` keyword is inside the buffer too
+            p.skip_whitespace();
+            p.data.get(p.pos + 7).ok_or(ParseError::Incomplete)?;
+            Ok((num, obj))
+        })?;
+        if expected.is_some_and(|e| e as u64 != num) {
+            return Err(format!("xref points at object {} instead of {:?}", num, expected));
+        }
+
+        let Obj::Dict(dict) = &obj else {
+            return Ok((obj, None));
+        };
+        let head = self.read_at(offset + end as u64, 32)?;
+        let mut p = Parser::new(&head);
+        p.skip_whitespace();
+        if !head[p.pos..].starts_with(b"stream") {
+            return Ok((obj, None));
+        }
+        let mut start = p.pos + 6;
+        if head.get(start) == Some(&b'\r') {
+            start += 1;
+        }
+        if head.get(start) == Some(&b'\n') {
+            start += 1;
+        }
+        let length = match dict.get(b"Length") {
+            Some(Obj::Int(n)) => *n,
+            Some(Obj::Ref(r)) => self.object(*r)?.as_int().ok_or("bad stream length")?,
+            _ => return Err("stream without length".to_string()),
+        };
+        let length = usize::try_from(length).map_err(|_| "bad stream length")?;
+        let raw = self.read_at(offset + (end + start) as u64, length)?;
+        let data = decode_stream(dict, raw)?;
+        Ok((obj, Some(data)))
+    }
+
+    fn object(&mut self, num: u32) -> Result<Obj, String> {
+        let entry = self.xref.get(&num).copied();
+        self.guarded(num, |reader| match entry {
+            Some(XrefEntry::Offset(offset)) => Ok(reader.object_at(offset, Some(num))?.0),
+            Some(XrefEntry::Compressed(stream, index)) => reader.compressed_object(stream, index, num),
+            None => Ok(Obj::Null),
+        })
+    }
+
+    /// Run `read` for object `num`, failing if that object is already being read further up.
+    fn guarded<T>(&mut self, num: u32, read: impl FnOnce(&mut Self) -> Result<T, String>) -> Result<T, String> {
+        if !self.resolving.insert(num) {
+            return Err(format!("object {} refers to itself", num));
+        }
+        let result = read(self);
+        self.resolving.remove(&num);
+        result
+    }
+
+    fn compressed_object(&mut self, stream: u32, index: u32, num: u32) -> Result<Obj, String> {
+        if !self.object_streams.contains_key(&stream) {
+            let Some(XrefEntry::Offset(offset)) = self.xref.get(&stream).copied() else {
+                return Err("object stream not found".to_string());
+            };
+            let (obj, data) = self.guarded(stream, |reader| reader.object_at(offset, Some(stream)))?;
+            let dict = obj.as_dict().ok_or("object stream is not a stream")?;
+            let data = data.ok_or("object stream is not a stream")?;
+            let count = dict.get(b"N").and_then(Obj::as_int).ok_or("object stream without /N")?;
+            let first = dict
+                .get(b"First")
+                .and_then(Obj::as_int)
+                .and_then(|first| usize::try_from(first).ok())
+                .ok_or("object stream without /First")?;
+            let mut header = Parser::new(&data);
+            let mut offsets = Vec::new();
+            for _ in 0..count {
+                let obj_num = header.unsigned().map_err(|_| "bad object stream header")?;
+                let obj_offset = header.unsigned().map_err(|_| "bad object stream header")?;
+                let start = usize::try_from(obj_offset)
+                    .ok()
+                    .and_then(|o| first.checked_add(o))
+                    .ok_or("bad object stream header")?;
+                offsets.push((obj_num as u32, start));
+            }
+            self.object_streams.insert(stream, (data, offsets));
+        }
+        let (data, offsets) = &self.object_streams[&stream];
+        let &(obj_num, start) = offsets.get(index as usize).ok_or("object stream index out of range")?;
+        if obj_num != num {
+            return Err("object stream index mismatch".to_string());
+        }
+        // Pad so a trailing number is not mistaken for a truncated one
+        let mut slice = data.get(start..).ok_or("object stream offset out of range")?.to_vec();
+        slice.push(b' ');
+        Parser::new(&slice).object().map_err(|_| "unparsable compressed object".to_string())
+    }
+
+    fn resolve(&mut self, obj: &Obj) -> Result<Obj, String> {
+        match obj {
+            Obj::Ref(num) => self.object(*num),
+            other => Ok(other.clone()),
+        }
+    }
+
+    /// Read the cross-reference section at `offset` (classic table or stream), keeping
+    /// entries already known from newer sections. Returns its trailer dictionary. A section
+    /// reached a second time (a `/Prev` or `/XRefStm` loop) is an error.
+    fn load_xref(&mut self, offset: u64) -> Result<Dict, String> {
+        if !self.xref_sections.insert(offset) || self.xref_sections.len() > MAX_XREF_SECTIONS {
+            return Err(format!("cross-reference loop at {}", offset));
+        }
+        let head = self.read_at(offset, 4)?;
+        if head == b"xref" {
+            return self.load_xref_table(offset + 4);
+        }
+        let (obj, data) = self.object_at(offset, None)?;
+        let Obj::Dict(dict) = obj else {
+            return Err("xref stream is not a dictionary".to_string());
+        };
+        self.add_xref_stream(&dict, &data.ok_or("xref stream without data")?)?;
+        Ok(dict)
+    }
+
+    fn load_xref_table(&mut self, offset: u64) -> Result<Dict, String> {
+        let ((entries, trailer), _) = self.parse_at(offset, |p| {
+            let mut entries = Vec::new();
+            loop {
+                p.skip_whitespace();
+                if p.data[p.pos..].starts_with(b"trailer") {
+                    p.pos += 7;
+                    break;
+                }
+                let start = p.unsigned()?;
+                let count = p.unsigned()?;
+                for i in 0..count {
+                    let entry_offset = p.unsigned()?;
+                    p.unsigned()?;
+                    let kind = p.token()?;
+                    if kind == b"n" {
+                        let num = start.checked_add(i).and_then(|n| u32::try_from(n).ok());
+                        entries.push((num.ok_or(ParseError::Invalid)?, entry_offset));
+                    } else if kind != b"f" {
+                        return Err(ParseError::Invalid);
+                    }
+                }
+            }
+            match p.object()? {
+                Obj::Dict(trailer) => Ok((entries, trailer)),
+                _ => Err(ParseError::Invalid),
+            }
+        })?;
+        for (num, entry_offset) in entries {
+            self.xref.entry(num).or_insert(XrefEntry::Offset(entry_offset));
+        }
+        // Hybrid files list compressed objects in an extra stream
+        if let Some(stream_offset) = trailer.get(b"XRefStm").and_then(Obj::as_int) {
+            self.load_xref(stream_offset as u64)?;
+        }
+        Ok(trailer)
+    }
+
+    fn add_xref_stream(&mut self, dict: &Dict, data: &[u8]) -> Result<(), String> {
+        let widths: Vec<usize> = match dict.get(b"W") {
+            // A field wider than 8 bytes does not fit the u64 it is read into
+            Some(Obj::Array(items)) if items.len() == 3 => items
+                .iter()
+                .map(|w| w.as_int().filter(|w| (0..=8).contains(w)).map(|w| w as usize).ok_or("bad /W"))
+                .collect::<Result<_, _>>()?,
+            _ => return Err("xref stream without /W".to_string()),
+        };
+        let size = dict.get(b"Size").and_then(Obj::as_int).ok_or("xref stream without /Size")?;
+        let index: Vec<i64> = match dict.get(b"Index") {
+            Some(Obj::Array(items)) => items.iter().filter_map(Obj::as_int).collect(),
+            _ => vec![0, size],
+        };
+        let row = widths.iter().sum::<usize>();
+        if row == 0 {
+            return Err("bad /W".to_string());
+        }
+        let field = |bytes: &[u8]| bytes.iter().fold(0u64, |acc, &b| acc << 8 | b as u64);
+        let mut rows = data.chunks_exact(row);
+        for pair in index.chunks(2) {
+            let [start, count] = pair else {
+                return Err("bad /Index".to_string());
+            };
+            for i in 0..*count {
+                let Some(entry) = rows.next() else {
+                    return Err("xref stream too short".to_string());
+                };
+                let (kind, rest) = entry.split_at(widths[0]);
+                let (second, third) = rest.split_at(widths[1]);
+                let kind = if widths[0] == 0 { 1 } else { field(kind) };
+                let num = start
+                    .checked_add(i)
+                    .and_then(|n| u32::try_from(n).ok())
+                    .ok_or("bad /Index")?;
+                let entry = match kind {
+                    1 => XrefEntry::Offset(field(second)),
+                    2 => XrefEntry::Compressed(field(second) as u32, field(third) as u32),
+                    _ => continue,
+                };
+                self.xref.entry(num).or_insert(entry);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Text string from a PDF: UTF-16BE with a byte order mark, UTF-8, or (PDFDocEncoding,
+/// approximated as) Latin-1. None for blank strings.
+pub fn decode_text(bytes: &[u8]) -> Option<String> {
+    let text = if let Some(utf16) = bytes.strip_prefix(&[0xfe, 0xff]) {
+        let units: Vec<u16> = utf16.chunks_exact(2).map(|c| u16::from_be_bytes([c[0], c[1]])).collect();
+        String::from_utf16_lossy(&units)
+    } else if let Some(utf8) = bytes.strip_prefix(&[0xef, 0xbb, 0xbf]) {
+        String::from_utf8_lossy(utf8).into_owned()
+    } else {
+        match std::str::from_utf8(bytes) {
+            Ok(s) => s.to_string(),
+            Err(_) => bytes.iter().map(|&b| b as char).collect(),
+        }
+    };
+    let text = text.trim().to_string();
+    (!text.is_empty()).then_some(text)
+}
+
+/// Page count and title of a PDF, reading only the objects that hold them.
+pub fn read_metadata(path: &Path) -> Result<PdfMetadata, String> {
+    let file = File::open(path).map_err(|e| e.to_string())?;
+    let len = file.metadata().map_err(|e| e.to_string())?.len();
+    let mut reader = Reader {
+        file,
+        len,
+        xref: HashMap::new(),
+        object_streams: HashMap::new(),
+        xref_sections: HashSet::new(),
+        resolving: HashSet::new(),
+    };
+
+    let tail_start = len.saturating_sub(TAIL_BYTES);
+    let tail = reader.read_at(tail_start, TAIL_BYTES as usize)?;
+    let marker = tail
+        .windows(9)
+        .rposition(|w| w == b"startxref")
+        .ok_or("no startxref")?;
+    let mut p = Parser::new(&tail[marker + 9..]);
+    let mut offset = p.unsigned().map_err(|_| "bad startxref")?;
+
+    let mut trailer: Option<Dict> = None;
+    for _ in 0..MAX_XREF_SECTIONS {
+        let section = reader.load_xref(offset)?;
+        let prev = section.get(b"Prev").and_then(Obj::as_int);
+        trailer.get_or_insert(section);
+        match prev {
+            Some(prev) if prev >= 0 => offset = prev as u64,
+            _ => break,
+        }
+    }
+    let trailer = trailer.ok_or("no trailer")?;
+    if trailer.get(b"Encrypt").is_some() {
+        return Err("encrypted".to_string());
+    }
+
+    let root = trailer.get(b"Root").ok_or("no /Root")?.clone();
+    let catalog = reader.resolve(&root)?;
+    let pages = catalog.as_dict().and_then(|c| c.get(b"Pages")).ok_or("no /Pages")?.clone();
+    let pages = reader.resolve(&pages)?;
+    let count = pages.as_dict().and_then(|p| p.get(b"Count")).ok_or("no /Count")?.clone();
+    let page_count = reader
+        .resolve(&count)?
+        .as_int()
+        .filter(|&n| n > 0)
+        .and_then(|n| u32::try_from(n).ok())
+        .ok_or("bad /Count")?;
+
+    let title = match trailer.get(b"Info").cloned() {
+        Some(info) => match reader.resolve(&info)? {
+            Obj::Dict(info) => match info.get(b"Title").cloned() {
+                Some(title) => match reader.resolve(&title)? {
+                    Obj::Str(bytes) => decode_text(&bytes),
+                    _ => None,
+                },
+                None => None,
+            },
+            _ => None,
+        },
+        None => None,
+    };
+
+    Ok(PdfMetadata { page_count, title })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+    use std::collections::BTreeMap;
+    use std::fmt::Write as _;
+    use std::io::Write;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    const CATALOG: &[u8] = b"<< /Type /Catalog /Pages 2 0 R >>";
+    const PAGES: &[u8] = b"<< /Type /Pages /Kids [3 0 R 5 0 R] /Count 2 >>";
+    const PAGE: &[u8] = b"<< /Type /Page /Parent 2 0 R /MediaBox [0 0 612 792] >>";
+    const INFO: &[u8] = b"<< /Title (Moby-Dick) >>";
+
+    /// Writes a PDF by hand so each cross-reference layout can be produced exactly.
+    #[derive(Default)]
+    struct Pdf {
+        out: Vec<u8>,
+        offsets: BTreeMap<u32, u64>,
+    }
+
+    impl Pdf {
+        fn new() -> Self {
+            Self {
+                out: b"%PDF-1.5\n%\xe2\xe3\xcf\xd3\n".to_vec(),
+                ..Default::default()
+            }
+        }
+
+        fn offset(&self) -> u64 {
+            self.out.len() as u64
+        }
+
+        fn object(&mut self, num: u32, body: &[u8]) -> &mut Self {
+            self.offsets.insert(num, self.offset());
+            writeln!(self.out, "{} 0 obj", num).unwrap();
+            self.out.extend_from_slice(body);
+            self.out.extend_from_slice(b"\nendobj\n");
+            self
+        }
+
+        fn stream(&mut self, num: u32, dict: &str, data: &[u8]) -> &mut Self {
+            let mut body = format!("<< {} /Length {} >>\nstream\n", dict, data.len()).into_bytes();
+            body.extend_from_slice(data);
+            body.extend_from_slice(b"\nendstream");
+            self.object(num, &body)
+        }
+
+        /// The standard four-object document (catalog, page tree, two pages) plus Info.
+        fn book(&mut self) -> &mut Self {
+            self.object(1, CATALOG).object(2, PAGES).object(3, PAGE).object(5, PAGE).object(4, INFO)
+        }
+
+        /// A classic table for the given objects, with `trailer` entries. Returns its offset.
+        fn table(&mut self, nums: &[u32], trailer: &str) -> u64 {
+            let start = self.offset();
+            self.out.extend_from_slice(b"xref\n");
+            for &num in nums {
+                write!(self.out, "{} 1\n{:010} 00000 n\r\n", num, self.offsets[&num]).unwrap();
+            }
+            write!(self.out, "trailer\n<< {} >>\n", trailer).unwrap();
+            start
+        }
+
+        /// A cross-reference stream (object `num`) for `entries` of (object, row), PNG-Up
+        /// predicted and compressed. Returns its offset.
+        fn xref_stream(&mut self, num: u32, entries: &[(u32, [u8; 7])], dict: &str) -> u64 {
+            let start = self.offset();
+            let mut rows = Vec::new();
+            let mut prev = [0u8; 7];
+            let mut index = String::new();
+            for (obj, row) in entries {
+                write!(index, "{} 1 ", obj).unwrap();
+                rows.push(2);
+                rows.extend(row.iter().zip(prev).map(|(b, p)| b.wrapping_sub(p)));
+                prev = *row;
+            }
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(&rows).unwrap();
+            let data = encoder.finish().unwrap();
+            let dict = format!(
+                "/Type /XRef /W [1 4 2] /Index [{}] /Filter /FlateDecode \
+                 /DecodeParms << /Predictor 12 /Columns 7 >> {}",
+                index, dict
+            );
+            self.stream(num, &dict, &data);
+            start
+        }
+
+        fn row_at(&self, num: u32) -> [u8; 7] {
+            let offset = (self.offsets[&num] as u32).to_be_bytes();
+            [1, offset[0], offset[1], offset[2], offset[3], 0, 0]
+        }
+
+        fn finish(&mut self, xref: u64) -> Vec<u8> {
+            write!(self.out, "startxref\n{}\n%%EOF\n", xref).unwrap();
+            std::mem::take(&mut self.out)
+        }
+    }
+
+    fn compressed_row(stream: u32, index: u16) -> [u8; 7] {
+        let s = stream.to_be_bytes();
+        let i = index.to_be_bytes();
+        [2, s[0], s[1], s[2], s[3], i[0], i[1]]
+    }
+
+    /// An object stream (object `num`) holding `objects`.
+    fn object_stream(pdf: &mut Pdf, num: u32, objects: &[(u32, &[u8])]) {
+        let mut header = String::new();
+        let mut body = Vec::new();
+        for (obj, data) in objects {
+            write!(header, "{} {} ", obj, body.len()).unwrap();
+            body.extend_from_slice(data);
+            body.push(b' ');
+        }
+        let mut data = header.clone().into_bytes();
+        data.extend_from_slice(&body);
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&data).unwrap();
+        let dict = format!("/Type /ObjStm /N {} /First {} /Filter /FlateDecode", objects.len(), header.len());
+        pdf.stream(num, &dict, &encoder.finish().unwrap());
+    }
+
+    fn fast(bytes: &[u8]) -> Result<(u32, Option<String>), String> {
+        static FILES: AtomicU64 = AtomicU64::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "pdf-meta-{}-{}.pdf",
+            std::process::id(),
+            FILES.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::write(&path, bytes).unwrap();
+        let result = read_metadata(&path).map(|meta| (meta.page_count, meta.title));
+        let _ = std::fs::remove_file(&path);
+        result
+    }
+
+    /// Page count and title as the full parse (`pdf_info::load_info_full`) finds them.
+    fn full(bytes: &[u8]) -> (u32, Option<String>) {
+        let doc = lopdf::Document::load_mem(bytes).expect("lopdf loads the fixture");
+        let title = doc
+            .trailer
+            .get(b"Info")
+            .ok()
+            .and_then(|info| doc.dereference(info).ok())
+            .and_then(|(_, obj)| match obj.as_dict().ok()?.get(b"Title").ok()? {
+                lopdf::Object::String(bytes, _) => decode_text(bytes),
+                _ => None,
+            });
+        (doc.get_pages().len() as u32, title)
+    }
+
+    fn assert_matches_lopdf(bytes: &[u8]) {
+        let expected = full(bytes);
+        assert_eq!(expected, (2, Some("Moby-Dick".to_string())));
+        assert_eq!(fast(bytes), Ok(expected));
+    }
+
+    #[test]
+    fn classic_xref_table() {
+        let mut pdf = Pdf::new();
+        pdf.book();
+        let xref = pdf.table(&[1, 2, 3, 4, 5], "/Size 6 /Root 1 0 R /Info 4 0 R");
+        assert_matches_lopdf(&pdf.finish(xref));
+    }
+
+    #[test]
+    fn xref_stream_with_png_predictor_and_object_stream() {
+        let mut pdf = Pdf::new();
+        pdf.object(1, CATALOG).object(3, PAGE).object(5, PAGE);
+        object_stream(&mut pdf, 6, &[(2, PAGES), (4, INFO)]);
+        let entries = [
+            (1, pdf.row_at(1)),
+            (2, compressed_row(6, 0)),
+            (3, pdf.row_at(3)),
+            (4, compressed_row(6, 1)),
+            (5, pdf.row_at(5)),
+            (6, pdf.row_at(6)),
+        ];
+        let xref = pdf.xref_stream(7, &entries, "/Size 8 /Root 1 0 R /Info 4 0 R");
+        assert_matches_lopdf(&pdf.finish(xref));
+    }
+
+    #[test]
+    fn hybrid_file_with_xref_stream_for_compressed_objects() {
+        let mut pdf = Pdf::new();
+        pdf.object(1, CATALOG).object(3, PAGE).object(5, PAGE).object(4, INFO);
+        object_stream(&mut pdf, 6, &[(2, PAGES)]);
+        let stream = pdf.xref_stream(7, &[(2, compressed_row(6, 0))], "/Size 8");
+        let xref = pdf.table(
+            &[1, 3, 4, 5, 6],
+            &format!("/Size 8 /Root 1 0 R /Info 4 0 R /XRefStm {}", stream),
+        );
+        assert_matches_lopdf(&pdf.finish(xref));
+    }
+
+    #[test]
+    fn incremental_update_chain() {
+        let mut pdf = Pdf::new();
+        pdf.object(1, CATALOG)
+            .object(2, b"<< /Type /Pages /Kids [3 0 R] /Count 1 >>")
+            .object(3, PAGE)
+            .object(4, b"<< /Title (Draft) >>");
+        let first = pdf.table(&[1, 2, 3, 4], "/Size 5 /Root 1 0 R /Info 4 0 R");
+        // The update adds a page and retitles the book; the first section still has 1 and 3
+        pdf.object(5, PAGE).object(2, PAGES).object(4, INFO);
+        let second = pdf.table(&[2, 4, 5], &format!("/Size 6 /Root 1 0 R /Info 4 0 R /Prev {}", first));
+        assert_matches_lopdf(&pdf.finish(second));
+    }
+
+    #[test]
+    fn encrypted_files_are_left_to_the_full_parse() {
+        let mut pdf = Pdf::new();
+        pdf.book().object(6, b"<< /Filter /Standard /V 1 /R 2 /O <00> /U <00> /P -4 >>");
+        let xref = pdf.table(&[1, 2, 3, 4, 5, 6], "/Size 7 /Root 1 0 R /Info 4 0 R /Encrypt 6 0 R /ID [<01> <01>]");
+        assert_eq!(fast(&pdf.finish(xref)), Err("encrypted".to_string()));
+    }
+
+    #[test]
+    fn prev_loop_is_an_error() {
+        let mut pdf = Pdf::new();
+        pdf.book();
+        let offset = pdf.offset();
+        let xref = pdf.table(&[1, 2, 3, 4, 5], &format!("/Size 6 /Root 1 0 R /Prev {}", offset));
+        assert_eq!(xref, offset);
+        assert!(fast(&pdf.finish(xref)).is_err());
+    }
+
+    #[test]
+    fn xref_stream_pointing_at_its_own_table_is_an_error() {
+        let mut pdf = Pdf::new();
+        pdf.book();
+        let offset = pdf.offset();
+        let xref = pdf.table(&[1, 2, 3, 4, 5], &format!("/Size 6 /Root 1 0 R /XRefStm {}", offset));
+        assert!(fast(&pdf.finish(xref)).is_err());
+    }
+
+    #[test]
+    fn stream_length_referring_to_itself_is_an_error() {
+        let mut pdf = Pdf::new();
+        pdf.object(1, CATALOG).object(3, PAGE).object(5, PAGE);
+        // The object stream's /Length is the object stream itself
+        pdf.object(6, b"<< /Type /ObjStm /N 1 /First 4 /Length 6 0 R >>\nstream\n2 0 << >>\nendstream");
+        let entries = [
+            (1, pdf.row_at(1)),
+            (2, compressed_row(6, 0)),
+            (3, pdf.row_at(3)),
+            (5, pdf.row_at(5)),
+            (6, pdf.row_at(6)),
+        ];
+        let xref = pdf.xref_stream(7, &entries, "/Size 8 /Root 1 0 R");
+        assert!(fast(&pdf.finish(xref)).is_err());
+    }
+
+    #[test]
+    fn compressed_length_inside_its_own_object_stream_is_an_error() {
+        let mut pdf = Pdf::new();
+        pdf.object(1, CATALOG).object(3, PAGE).object(5, PAGE);
+        // /Length 8 0 R, and object 8 lives in this very object stream
+        pdf.object(6, b"<< /Type /ObjStm /N 2 /First 8 /Length 8 0 R >>\nstream\n2 0 8 3 << >> 20\nendstream");
+        let entries = [
+            (1, pdf.row_at(1)),
+            (2, compressed_row(6, 0)),
+            (3, pdf.row_at(3)),
+            (5, pdf.row_at(5)),
+            (6, pdf.row_at(6)),
+            (8, compressed_row(6, 1)),
+        ];
+        let xref = pdf.xref_stream(7, &entries, "/Size 9 /Root 1 0 R");
+        assert!(fast(&pdf.finish(xref)).is_err());
+    }
+
+    #[test]
+    fn deep_nesting_is_an_error() {
+        let mut body = b"<< /Type /Catalog /Pages 2 0 R /Deep ".to_vec();
+        body.extend(std::iter::repeat_n(b'[', 100_000));
+        body.extend(std::iter::repeat_n(b']', 100_000));
+        body.extend_from_slice(b" >>");
+        let mut pdf = Pdf::new();
+        pdf.object(1, &body).object(2, PAGES).object(3, PAGE).object(5, PAGE);
+        let xref = pdf.table(&[1, 2, 3, 5], "/Size 6 /Root 1 0 R");
+        assert!(fast(&pdf.finish(xref)).is_err());
+
+        let mut parser = Parser::new(b"[[[1]]] ");
+        assert_eq!(parser.object().ok(), Some(Obj::Array(vec![Obj::Array(vec![Obj::Array(vec![Obj::Int(1)])])])));
+    }
+
+    #[test]
+    fn predictor_parameters_are_bounded() {
+        let dict = |parms: &str| {
+            let mut p = Parser::new(parms.as_bytes());
+            p.object().unwrap().as_dict().unwrap().clone()
+        };
+        let huge = dict("<< /DecodeParms << /Predictor 12 /Columns 4611686018427387904 /Colors 4 >> >> ");
+        assert!(decode_stream(&huge, vec![2, 0]).is_err());
+        let wide = dict("<< /DecodeParms << /Predictor 12 /Columns 1000000 >> >> ");
+        assert!(decode_stream(&wide, vec![2, 0]).is_err());
+        let ok = dict("<< /DecodeParms << /Predictor 12 /Columns 2 >> >> ");
+        assert_eq!(decode_stream(&ok, vec![2, 1, 2, 2, 1, 1]), Ok(vec![1, 2, 2, 3]));
+    }
+
+    #[test]
+    fn inflation_is_bounded() {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+        let zeros = vec![0u8; 1024 * 1024];
+        for _ in 0..=MAX_DECODED_BYTES / zeros.len() as u64 {
+            encoder.write_all(&zeros).unwrap();
+        }
+        let bomb = encoder.finish().unwrap();
+        let mut p = Parser::new(b"<< /Filter /FlateDecode >> ");
+        let dict = p.object().unwrap().as_dict().unwrap().clone();
+        assert_eq!(decode_stream(&dict, bomb), Err("stream too large".to_string()));
+    }
+}
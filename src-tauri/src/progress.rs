@@ -1,12 +1,22 @@
+use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::Path;
-use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
 
-use crate::icloud;
+use crate::conflicts::{self, ConflictDecision};
+use crate::settings::{self, MergeStrategy};
+use crate::sync_backend::{self, SyncBackend};
+use crate::fsutil::{self, ReadError};
+use crate::{book_id, device, icloud};
 
 static PROGRESS_LOCK: Mutex<()> = Mutex::new(());
-static TMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Records whose timestamps are closer than this are treated as simultaneous (device clocks drift).
+const CLOCK_SKEW_SECS: i64 = 60;
+/// Device ID given to the single-file central record written by older app versions.
+const LEGACY_DEVICE: &str = "legacy";
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ReadingProgress {
@@ -19,6 +29,29 @@ pub struct ReadingProgress {
     pub last_read: String,
     #[serde(default)]
     pub version: u64,
+    /// Device that produced this position. Set by the backend on save.
+    #[serde(default)]
+    pub device_id: String,
+    /// The other device's position sync last judged for this book, so it is judged only once.
+    /// Local only: never published to the central store.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub settled_remote: Option<RemoteStamp>,
+}
+
+/// Identifies a central record: the device that wrote it and when.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RemoteStamp {
+    pub device_id: String,
+    pub last_read: String,
+}
+
+impl RemoteStamp {
+    fn of(record: &ReadingProgress) -> Self {
+        Self {
+            device_id: record.device_id.clone(),
+            last_read: record.last_read.clone(),
+        }
+    }
 }
 
 impl Default for ReadingProgress {
@@ -32,6 +65,8 @@ impl Default for ReadingProgress {
             scroll_position: 0.0,
             last_read: chrono::Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string(),
             version: 0,
+            device_id: String::new(),
+            settled_remote: None,
         }
     }
 }
@@ -40,33 +75,18 @@ fn local_progress_file(hash: &str) -> std::path::PathBuf {
     icloud::get_local_progress_dir().join(format!("{}.json", hash))
 }
 
-fn central_progress_file(hash: &str) -> std::path::PathBuf {
-    icloud::get_progress_dir().join(format!("{}.json", hash))
-}
-
 fn read_progress_file(path: &Path) -> Result<Option<ReadingProgress>, String> {
-    if !path.exists() {
-        return Ok(None);
-    }
-    let data =
-        std::fs::read_to_string(path).map_err(|e| format!("read_progress_failed|detail={}", e))?;
-    let progress: ReadingProgress =
-        serde_json::from_str(&data).map_err(|e| format!("parse_progress_failed|detail={}", e))?;
-    Ok(Some(progress))
+    fsutil::read_with_fallback(path, |data| serde_json::from_str::<ReadingProgress>(data)).map_err(|e| match e {
+        ReadError::Io(e) => format!("read_progress_failed|detail={}", e),
+        ReadError::Parse(e) => format!("parse_progress_failed|detail={}", e),
+    })
 }
 
 fn write_progress_file(path: &Path, progress: &ReadingProgress) -> Result<(), String> {
-    let seq = TMP_COUNTER.fetch_add(1, Ordering::Relaxed);
-    let tmp_path = path.with_extension(format!("json.{}.tmp", seq));
     let data = serde_json::to_string_pretty(progress)
         .map_err(|e| format!("serialize_progress_failed|detail={}", e))?;
-    std::fs::write(&tmp_path, &data)
-        .map_err(|e| format!("write_progress_failed|detail={}", e))?;
-    std::fs::rename(&tmp_path, path).map_err(|e| {
-        let _ = std::fs::remove_file(&tmp_path);
-        format!("rename_progress_failed|detail={}", e)
-    })?;
-    Ok(())
+    fsutil::write_atomic(path, data.as_bytes(), true)
+        .map_err(|e| format!("write_progress_failed|detail={}", e))
 }
 
 fn load_local(hash: &str) -> Result<Option<ReadingProgress>, String> {
@@ -75,103 +95,341 @@ fn load_local(hash: &str) -> Result<Option<ReadingProgress>, String> {
 
 fn save_local_inner(progress: &ReadingProgress) -> Result<ReadingProgress, String> {
     let path = local_progress_file(&progress.hash);
-    let current_version = read_progress_file(&path)?
-        .map(|p| p.version)
-        .unwrap_or(0);
+    let current = read_progress_file(&path)?;
     let mut to_save = progress.clone();
-    to_save.version = current_version + 1;
+    to_save.version = current.as_ref().map_or(0, |p| p.version) + 1;
+    to_save.device_id = device::current_id();
+    // The reader's position says nothing about which remote one was settled
+    to_save.settled_remote = current.and_then(|p| p.settled_remote);
     write_progress_file(&path, &to_save)?;
     Ok(to_save)
 }
 
-/// Save locally (version+1). Central is updated only via sync_inner to avoid iCloud conflicts.
+/// Latest unsaved progress per hash. The reader saves on every scroll pause, so writes are
+/// buffered here and coalesced into one file write per book per flush interval.
+static PENDING: Mutex<Option<HashMap<String, ReadingProgress>>> = Mutex::new(None);
+
+/// Save as this device's position. The write is buffered and reaches disk on the next flush
+/// (periodic, window close, app exit, or any load/sync of the same book). Central is updated
+/// only via sync_inner, and each device only ever writes its own record there, so devices
+/// never overwrite each other.
 pub fn save_local(progress: &ReadingProgress) -> Result<(), String> {
+    let mut progress = progress.clone();
+    progress.hash = book_id::current(&progress.hash);
+    PENDING
+        .lock()
+        .unwrap()
+        .get_or_insert_with(HashMap::new)
+        .insert(progress.hash.clone(), progress);
+    Ok(())
+}
+
+/// Write buffered progress to disk, for one hash or all. Callers hold PROGRESS_LOCK.
+fn flush_inner(hash: Option<&str>) {
+    let drained: Vec<ReadingProgress> = {
+        let mut pending = PENDING.lock().unwrap();
+        let Some(map) = pending.as_mut() else {
+            return;
+        };
+        match hash {
+            Some(h) => map.remove(h).into_iter().collect(),
+            None => map.drain().map(|(_, p)| p).collect(),
+        }
+    };
+    for progress in drained {
+        match save_local_inner(&progress) {
+            Ok(saved) => log::warn!(
+                "save: hash={}, page={}, version={}",
+                &saved.hash[..8.min(saved.hash.len())],
+                saved.current_page,
+                saved.version
+            ),
+            Err(e) => {
+                log::warn!("save: failed for {}, will retry: {}", progress.hash, e);
+                // Keep it for the next flush unless a newer save arrived meanwhile
+                PENDING
+                    .lock()
+                    .unwrap()
+                    .get_or_insert_with(HashMap::new)
+                    .entry(progress.hash.clone())
+                    .or_insert(progress);
+            }
+        }
+    }
+}
+
+/// Write buffered progress for one book to disk (e.g. its reader window closed). `hash` may
+/// be the ID the window was opened with; saves since it moved are buffered under the new one.
+pub fn flush(hash: &str) {
     let _guard = PROGRESS_LOCK.lock().unwrap();
-    let saved = save_local_inner(progress)?;
-    log::warn!(
-        "save: hash={}, page={}, version={}",
-        &saved.hash[..8.min(saved.hash.len())],
-        saved.current_page,
-        saved.version
-    );
+    let current = book_id::current(hash);
+    if current != hash {
+        flush_inner(Some(hash));
+    }
+    flush_inner(Some(&current));
+}
+
+/// Write all buffered progress to disk (periodic flush and app exit).
+pub fn flush_all() {
+    let _guard = PROGRESS_LOCK.lock().unwrap();
+    flush_inner(None);
+}
+
+/// Flush the write buffer every `interval` until `stop` is set, then once more.
+pub fn run_flusher(stop: Arc<AtomicBool>, interval: std::time::Duration) {
+    while !stop.load(Ordering::Relaxed) {
+        std::thread::sleep(interval);
+        flush_all();
+    }
+    flush_all();
+}
+
+/// Effective time of a record. Timestamps in the future (a device whose clock runs ahead) are
+/// clamped to now so that such a device cannot keep winning every merge.
+fn record_time(progress: &ReadingProgress) -> DateTime<Utc> {
+    let now = Utc::now();
+    DateTime::parse_from_rfc3339(&progress.last_read)
+        .map(|t| t.with_timezone(&Utc).min(now))
+        .unwrap_or(DateTime::<Utc>::MIN_UTC)
+}
+
+/// Whether `candidate` should replace `current`. Records closer together than the clock-skew
+/// tolerance are treated as simultaneous, and the one this device already shows is kept.
+fn is_newer(candidate: &ReadingProgress, current: &ReadingProgress) -> bool {
+    record_time(candidate) - record_time(current) > Duration::seconds(CLOCK_SKEW_SECS)
+}
+
+fn legacy_record_path(hash: &str) -> String {
+    format!("{}.json", hash)
+}
+
+fn device_record_path(hash: &str, device_id: &str) -> String {
+    format!("{}/{}.json", hash, device_id)
+}
+
+/// Book hash a top-level central entry belongs to: `<hash>.json` or the `<hash>/` directory.
+/// None for anything else (`Devices`, `Annotations`, temp files).
+pub fn hash_of_entry(name: &str) -> Option<&str> {
+    let hash = name.strip_suffix(".json").unwrap_or(name);
+    (!hash.is_empty() && hash.chars().all(|c| c.is_ascii_hexdigit())).then_some(hash)
+}
+
+/// Every central record for a hash: one per device, plus the legacy single-file record written
+/// by older app versions. Records older devices still write under the book's short ID are
+/// included.
+fn load_central_records(backend: &dyn SyncBackend, hash: &str) -> Result<Vec<ReadingProgress>, String> {
+    let mut records = Vec::new();
+    load_central_records_at(backend, hash, &mut records)?;
+    if let Some(short) = book_id::short_alias(hash) {
+        let mut short_records = Vec::new();
+        load_central_records_at(backend, short, &mut short_records)?;
+        note_legacy_peers(&records, &short_records);
+        records.extend(short_records);
+    }
+    for record in &mut records {
+        record.hash = hash.to_string();
+    }
+    Ok(records)
+}
+
+/// A device that writes under the short ID without a record at least as recent under the full
+/// one predates full-length IDs (current versions write both while mirroring).
+fn note_legacy_peers(full: &[ReadingProgress], short: &[ReadingProgress]) {
+    let own_id = device::current_id();
+    for record in short.iter().filter(|r| r.device_id != own_id) {
+        let time = record_time(record);
+        let mirrored = full
+            .iter()
+            .any(|f| f.device_id == record.device_id && record_time(f) >= time);
+        if !mirrored {
+            book_id::saw_legacy_peer(time);
+        }
+    }
+}
+
+fn load_central_records_at(backend: &dyn SyncBackend, id: &str, records: &mut Vec<ReadingProgress>) -> Result<(), String> {
+    let legacy: Option<ReadingProgress> = sync_backend::read_json(backend, &legacy_record_path(id))
+        .map_err(|e| format!("read_progress_failed|detail={}", e))?;
+    if let Some(mut legacy) = legacy {
+        if legacy.device_id.is_empty() {
+            legacy.device_id = LEGACY_DEVICE.to_string();
+        }
+        records.push(legacy);
+    }
+
+    for name in backend.list(id)? {
+        let Some(stem) = name.strip_suffix(".json") else {
+            continue;
+        };
+        match sync_backend::read_json::<ReadingProgress>(backend, &device_record_path(id, stem)) {
+            Ok(Some(mut record)) => {
+                if record.device_id.is_empty() {
+                    record.device_id = stem.to_string();
+                }
+                records.push(record);
+            }
+            Ok(None) => {}
+            Err(e) => log::warn!("sync: skipping unreadable record {}/{}: {}", id, name, e),
+        }
+    }
     Ok(())
 }
 
-fn load_central(hash: &str) -> Result<Option<ReadingProgress>, String> {
-    read_progress_file(&central_progress_file(hash))
+/// Newest record written by another device.
+fn newest_remote(backend: &dyn SyncBackend, hash: &str, own_id: &str) -> Result<Option<ReadingProgress>, String> {
+    let records = load_central_records(backend, hash)?;
+    Ok(records
+        .into_iter()
+        .filter(|r| r.device_id != own_id)
+        .max_by_key(record_time))
 }
 
-fn save_central(progress: &ReadingProgress) -> Result<(), String> {
-    write_progress_file(&central_progress_file(&progress.hash), progress)
+/// `last_read` of the record this device last published per hash, to skip redundant writes.
+fn published() -> &'static Mutex<HashMap<String, String>> {
+    static PUBLISHED: OnceLock<Mutex<HashMap<String, String>>> = OnceLock::new();
+    PUBLISHED.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Publish this device's own reading position. Also refreshes the legacy single-file record
+/// so that devices still running an older version keep picking it up, and mirrors both under
+/// the book's short ID while devices that predate full-length IDs are around
+/// (`book_id::mirror_short_ids`).
+fn push_own(backend: &dyn SyncBackend, local: &ReadingProgress) -> Result<(), String> {
+    if published().lock().unwrap().get(&local.hash) == Some(&local.last_read) {
+        return Ok(());
+    }
+    let mirror = book_id::short_alias(&local.hash).filter(|_| book_id::mirror_short_ids());
+    for id in std::iter::once(local.hash.as_str()).chain(mirror) {
+        let mut record = local.clone();
+        record.hash = id.to_string();
+        record.settled_remote = None;
+        sync_backend::write_json(backend, &device_record_path(id, &local.device_id), &record)?;
+
+        let legacy_path = legacy_record_path(id);
+        let legacy: Option<ReadingProgress> = sync_backend::read_json(backend, &legacy_path).ok().flatten();
+        if legacy.as_ref().map_or(true, |l| record_time(local) >= record_time(l)) {
+            record.version = legacy.map_or(0, |l| l.version).max(local.version) + 1;
+            sync_backend::write_json(backend, &legacy_path, &record)?;
+        }
+    }
+    published()
+        .lock()
+        .unwrap()
+        .insert(local.hash.clone(), local.last_read.clone());
+    Ok(())
 }
 
 fn sync_inner(hash: &str) -> Result<Option<ReadingProgress>, String> {
-    if !icloud::is_icloud_active() {
+    flush_inner(Some(hash));
+    // Other devices cannot know a provisional ID; the position syncs once the hash is confirmed
+    if book_id::is_provisional(hash) {
         return Ok(None);
     }
+    let Some(backend) = sync_backend::current() else {
+        return Ok(None);
+    };
+    device::announce(false);
+    let strategy = settings::load_library().merge_strategy;
+    reconcile(backend.as_ref(), hash, &device::current_id(), strategy)
+}
 
+/// Publish this device's position and settle it against the newest one from another device.
+fn reconcile(
+    backend: &dyn SyncBackend,
+    hash: &str,
+    own_id: &str,
+    strategy: MergeStrategy,
+) -> Result<Option<ReadingProgress>, String> {
     let local = load_local(hash)?;
-    let central = load_central(hash)?;
-
-    match (local, central) {
-        (Some(l), Some(c)) => {
-            if l.version > c.version {
-                log::info!(
-                    "sync: hash={}, pushing local v{} (page={}) over central v{} (page={})",
-                    &hash[..8.min(hash.len())], l.version, l.current_page, c.version, c.current_page
-                );
-                save_central(&l)?;
-                Ok(None)
-            } else if c.version > l.version {
-                log::info!(
-                    "sync: hash={}, pulling central v{} (page={}) over local v{} (page={})",
-                    &hash[..8.min(hash.len())], c.version, c.current_page, l.version, l.current_page
-                );
-                write_progress_file(&local_progress_file(hash), &c)?;
-                Ok(Some(c))
-            } else {
-                // Versions equal — use last_read as tiebreaker
-                if c.last_read > l.last_read {
-                    log::warn!(
-                        "sync: hash={}, equal v{} but central is newer (last_read), pulling page={}",
-                        &hash[..8.min(hash.len())], c.version, c.current_page
-                    );
-                    write_progress_file(&local_progress_file(hash), &c)?;
-                    Ok(Some(c))
-                } else if l.last_read > c.last_read {
-                    log::warn!(
-                        "sync: hash={}, equal v{} but local is newer (last_read), pushing page={}",
-                        &hash[..8.min(hash.len())], l.version, l.current_page
-                    );
-                    save_central(&l)?;
-                    Ok(None)
-                } else {
-                    Ok(None)
-                }
-            }
-        }
-        (Some(l), None) => {
-            log::info!(
-                "sync: hash={}, no central, pushing local v{} (page={})",
-                &hash[..8.min(hash.len())], l.version, l.current_page
-            );
-            save_central(&l)?;
+    if let Some(l) = local.as_ref().filter(|l| l.device_id == own_id) {
+        push_own(backend, l)?;
+    }
+
+    let Some(remote) = newest_remote(backend, hash, own_id)? else {
+        return Ok(None);
+    };
+    let Some(local) = local else {
+        return adopt(hash, remote, None).map(Some);
+    };
+    if local.current_page == remote.current_page {
+        // Same place; only zoom or scroll differ, so the most recent simply wins
+        return if is_newer(&remote, &local) {
+            adopt(hash, remote, Some(&local)).map(Some)
+        } else {
             Ok(None)
-        }
-        (None, Some(c)) => {
-            log::info!(
-                "sync: hash={}, no local, pulling central v{} (page={})",
-                &hash[..8.min(hash.len())], c.version, c.current_page
-            );
-            write_progress_file(&local_progress_file(hash), &c)?;
-            Ok(Some(c))
-        }
-        (None, None) => Ok(None),
+        };
+    }
+    // This remote position was already settled (or is waiting for the reader). The log is
+    // only consulted for positions settled before the stamp was kept in the record.
+    let settled = local.settled_remote.as_ref() == Some(&RemoteStamp::of(&remote));
+    if settled || conflicts::find(hash, &remote).is_some() {
+        return Ok(None);
+    }
+
+    let remote_newer = is_newer(&remote, &local);
+    let decision = match strategy {
+        MergeStrategy::MostRecent if remote_newer => ConflictDecision::Remote,
+        MergeStrategy::FurthestPage if remote.current_page > local.current_page => ConflictDecision::Remote,
+        MergeStrategy::Ask if remote_newer => ConflictDecision::Pending,
+        _ => ConflictDecision::Local,
+    };
+    // An older remote position that loses is not a disagreement, just history
+    if decision != ConflictDecision::Local || remote_newer {
+        conflicts::record(strategy, &local, &remote, decision);
+    }
+    if decision == ConflictDecision::Remote {
+        adopt(hash, remote, Some(&local)).map(Some)
+    } else {
+        let mut settled = local;
+        settled.settled_remote = Some(RemoteStamp::of(&remote));
+        write_progress_file(&local_progress_file(hash), &settled)?;
+        Ok(None)
     }
 }
 
-/// Core sync logic. Returns Some(progress) if central was newer (UI should update).
+fn adopt(hash: &str, mut remote: ReadingProgress, local: Option<&ReadingProgress>) -> Result<ReadingProgress, String> {
+    log::info!(
+        "sync: hash={}, pulling page={} from device {} (local page={:?})",
+        &hash[..8.min(hash.len())],
+        remote.current_page,
+        remote.device_id,
+        local.map(|l| l.current_page)
+    );
+    remote.settled_remote = Some(RemoteStamp::of(&remote));
+    write_progress_file(&local_progress_file(hash), &remote)?;
+    Ok(remote)
+}
+
+/// Settle a logged conflict by keeping one side. The chosen position is saved as this device's
+/// own reading now, so it also wins on every other device. Resolving with the side that was
+/// not chosen undoes an automatic decision.
+pub fn resolve_conflict(id: &str, keep: ConflictDecision) -> Result<ReadingProgress, String> {
+    let _guard = PROGRESS_LOCK.lock().unwrap();
+    let conflict = conflicts::get(id)?;
+    // A buffered save is older than the choice being made now
+    if let Some(map) = PENDING.lock().unwrap().as_mut() {
+        map.remove(&conflict.hash);
+    }
+    let mut chosen = match keep {
+        ConflictDecision::Local => conflict.local,
+        ConflictDecision::Remote => conflict.remote,
+        ConflictDecision::Pending => return Err("sync_conflict_invalid_choice".to_string()),
+    };
+    chosen.last_read = chrono::Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string();
+    let saved = save_local_inner(&chosen)?;
+    conflicts::set_decision(id, keep)?;
+    log::info!(
+        "conflict: {} resolved as {:?}, page={}",
+        id,
+        keep,
+        saved.current_page
+    );
+    Ok(saved)
+}
+
+/// Core sync logic. Returns Some(progress) if another device read more recently (UI should update).
 pub fn sync(hash: &str) -> Result<Option<ReadingProgress>, String> {
+    let hash = &book_id::current(hash);
     let _guard = PROGRESS_LOCK.lock().unwrap();
     sync_inner(hash)
 }
@@ -181,6 +439,7 @@ pub fn sync_all(hashes: &[String]) -> Result<Vec<ReadingProgress>, String> {
     let _guard = PROGRESS_LOCK.lock().unwrap();
     let mut updated = Vec::new();
     for hash in hashes {
+        let hash = &book_id::current(hash);
         match sync_inner(hash) {
             Ok(Some(progress)) => updated.push(progress),
             Ok(None) => {}
@@ -190,32 +449,227 @@ pub fn sync_all(hashes: &[String]) -> Result<Vec<ReadingProgress>, String> {
     Ok(updated)
 }
 
-/// Load progress with sync: try central sync first, then local, then central fallback.
+/// Hashes of every book that has a local progress file.
+pub fn local_hashes() -> Vec<String> {
+    std::fs::read_dir(icloud::get_local_progress_dir())
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter_map(|e| {
+                    let name = e.file_name().to_str()?.to_string();
+                    name.strip_suffix(".json").map(str::to_string)
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Load progress with sync: sync first (which also pulls a position when there is none
+/// locally), then the local file.
 pub fn load(hash: &str) -> Result<Option<ReadingProgress>, String> {
+    let hash = &book_id::current(hash);
     let _guard = PROGRESS_LOCK.lock().unwrap();
-    // Sync first so we get the latest from central if available
     if let Some(synced) = sync_inner(hash)? {
         return Ok(Some(synced));
     }
-    if let Some(local) = load_local(hash)? {
-        return Ok(Some(local));
-    }
-    if let Some(central) = load_central(hash)? {
-        write_progress_file(&local_progress_file(hash), &central)?;
-        return Ok(Some(central));
+    load_local(hash)
+}
+
+/// This device's position without syncing, including a save not flushed yet. For read-only
+/// queries that must not touch central.
+pub fn peek(hash: &str) -> Option<ReadingProgress> {
+    let pending = PENDING
+        .lock()
+        .unwrap()
+        .as_ref()
+        .and_then(|map| map.get(hash).cloned());
+    pending.or_else(|| load_local(hash).ok().flatten())
+}
+
+/// Last known position of a book on one device.
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceProgress {
+    pub device_id: String,
+    /// None for devices missing from the registry (e.g. records from older app versions).
+    pub device_name: Option<String>,
+    pub is_current_device: bool,
+    pub current_page: u32,
+    pub total_pages: u32,
+    pub last_read: String,
+}
+
+/// Per-device positions for a book, most recent first.
+pub fn device_records(hash: &str) -> Result<Vec<DeviceProgress>, String> {
+    let hash = &book_id::current(hash);
+    let _guard = PROGRESS_LOCK.lock().unwrap();
+    flush_inner(Some(hash));
+    let own_id = device::current_id();
+    let mut records = match sync_backend::current() {
+        Some(backend) => load_central_records(backend.as_ref(), hash)?,
+        None => Vec::new(),
+    };
+    if let Some(local) = load_local(hash)?.filter(|l| l.device_id == own_id) {
+        records.push(local);
     }
-    Ok(None)
+    records.sort_by_key(|r| std::cmp::Reverse(record_time(r)));
+
+    let mut seen = std::collections::HashSet::new();
+    Ok(records
+        .into_iter()
+        // The legacy file mirrors whichever device wrote last; skip it when that device has its own record
+        .filter(|r| seen.insert(r.device_id.clone()))
+        .map(|r| DeviceProgress {
+            device_name: device::name_of(&r.device_id),
+            is_current_device: r.device_id == own_id,
+            device_id: r.device_id,
+            current_page: r.current_page,
+            total_pages: r.total_pages,
+            last_read: r.last_read,
+        })
+        .collect())
 }
 
-/// Delete both local and central progress files for a hash.
+/// Delete the local progress file and every central record for a hash.
 pub fn delete(hash: &str) {
+    delete_local(hash);
+    if let Some(backend) = sync_backend::current() {
+        delete_central(backend.as_ref(), hash);
+    }
+}
+
+/// Delete this device's progress file (and any unsaved position) for a hash.
+pub fn delete_local(hash: &str) {
     let _guard = PROGRESS_LOCK.lock().unwrap();
+    remove_local(hash);
+}
+
+/// Callers hold PROGRESS_LOCK.
+fn remove_local(hash: &str) {
+    if let Some(map) = PENDING.lock().unwrap().as_mut() {
+        map.remove(hash);
+    }
     let local = local_progress_file(hash);
-    let central = central_progress_file(hash);
-    if local.exists() {
-        let _ = std::fs::remove_file(&local);
+    for path in [fsutil::backup_path(&local), local] {
+        if path.exists() {
+            let _ = std::fs::remove_file(&path);
+        }
+    }
+}
+
+/// Move this device's position from an old edition of a book to a new one, mapping the page.
+/// Returns false when there was nothing to move or the new edition already has a position
+/// at least as recent.
+pub fn migrate(
+    old: &str,
+    new: &str,
+    total_pages: Option<u32>,
+    map_page: &dyn Fn(u32) -> u32,
+) -> Result<bool, String> {
+    let _guard = PROGRESS_LOCK.lock().unwrap();
+    flush_inner(Some(old));
+    flush_inner(Some(new));
+    let Some(previous) = load_local(old)? else {
+        return Ok(false);
+    };
+    if load_local(new)?.is_some_and(|current| !is_newer(&previous, &current)) {
+        return Ok(false);
     }
-    if central.exists() {
-        let _ = std::fs::remove_file(&central);
+
+    let mut moved = previous.clone();
+    moved.hash = new.to_string();
+    moved.total_pages = total_pages.unwrap_or(previous.total_pages);
+    moved.current_page = map_page(previous.current_page).clamp(1, moved.total_pages.max(1));
+    if moved.current_page != previous.current_page {
+        // The offset within the old page means nothing on a different page
+        moved.scroll_position = 0.0;
+    }
+    save_local_inner(&moved)?;
+    remove_local(old);
+    Ok(true)
+}
+
+/// Delete every device's central record for a hash.
+pub fn delete_central(backend: &dyn SyncBackend, hash: &str) {
+    let _guard = PROGRESS_LOCK.lock().unwrap();
+    for id in std::iter::once(hash).chain(book_id::short_alias(hash)) {
+        for path in [legacy_record_path(id), format!("{}/", id)] {
+            if let Err(e) = backend.remove(&path) {
+                log::warn!("delete: failed to remove central {}: {}", path, e);
+            }
+        }
+    }
+    published().lock().unwrap().remove(hash);
+}
+
+/// Hashes that have a central record from any device.
+pub fn central_hashes(backend: &dyn SyncBackend) -> Result<Vec<String>, String> {
+    let mut hashes: Vec<String> = backend
+        .list("")?
+        .iter()
+        .filter_map(|name| hash_of_entry(name).map(str::to_string))
+        .collect();
+    hashes.sort();
+    hashes.dedup();
+    Ok(hashes)
+}
+
+/// Most recent time the book was read according to the local file and, if given, the
+/// central records. None when there is no readable record at all.
+pub fn last_read(hash: &str, backend: Option<&dyn SyncBackend>) -> Option<DateTime<Utc>> {
+    let _guard = PROGRESS_LOCK.lock().unwrap();
+    let mut records: Vec<ReadingProgress> = load_local(hash).ok().flatten().into_iter().collect();
+    if let Some(backend) = backend {
+        records.extend(load_central_records(backend, hash).unwrap_or_default());
+    }
+    records.iter().map(record_time).max()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync_backend::FolderBackend;
+
+    fn position(hash: &str, device_id: &str, page: u32, last_read: &str) -> ReadingProgress {
+        ReadingProgress {
+            hash: hash.to_string(),
+            current_page: page,
+            total_pages: 100,
+            last_read: last_read.to_string(),
+            device_id: device_id.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn a_settled_remote_position_is_not_judged_again_once_the_log_forgets_it() {
+        let hash = "5".repeat(64);
+        let own = device::current_id();
+        let root = std::env::temp_dir().join(format!("pdf-reader-central-{}", std::process::id()));
+        let backend = FolderBackend::new("test", root.clone());
+        std::fs::create_dir_all(icloud::get_local_progress_dir()).unwrap();
+
+        let remote = position(&hash, "other", 40, "2026-01-01T00:00:00.000Z");
+        sync_backend::write_json(&backend, &device_record_path(&hash, "other"), &remote).unwrap();
+        write_progress_file(&local_progress_file(&hash), &position(&hash, &own, 10, "2026-01-02T00:00:00.000Z"))
+            .unwrap();
+        let pulled = reconcile(&backend, &hash, &own, MergeStrategy::FurthestPage).unwrap();
+        assert_eq!(pulled.map(|p| p.current_page), Some(40));
+
+        // The reader goes back, and the log entry for page 40 has since been dropped
+        save_local_inner(&position(&hash, &own, 5, "2026-01-03T00:00:00.000Z")).unwrap();
+        conflicts::rename_hash(&hash, "forgotten").unwrap();
+        assert!(reconcile(&backend, &hash, &own, MergeStrategy::FurthestPage).unwrap().is_none());
+        assert_eq!(load_local(&hash).unwrap().unwrap().current_page, 5);
+        let published = std::fs::read_to_string(root.join(&hash).join(format!("{}.json", own))).unwrap();
+        assert!(published.contains("\"current_page\": 5"));
+        assert!(!published.contains("settled_remote"));
+
+        // A newer position from the other device is still judged
+        let moved_on = position(&hash, "other", 50, "2026-01-04T00:00:00.000Z");
+        sync_backend::write_json(&backend, &device_record_path(&hash, "other"), &moved_on).unwrap();
+        let pulled = reconcile(&backend, &hash, &own, MergeStrategy::FurthestPage).unwrap();
+        assert_eq!(pulled.map(|p| p.current_page), Some(50));
+
+        std::fs::remove_dir_all(&root).unwrap();
     }
 }
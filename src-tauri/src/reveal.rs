@@ -0,0 +1,67 @@
+use std::path::Path;
+use std::process::Command;
+
+/// Show a file selected in the system file manager. Returns once the file manager was asked;
+/// whether it shows the file is up to it.
+#[cfg(target_os = "macos")]
+pub fn reveal(path: &Path) -> Result<(), String> {
+    Command::new("open")
+        .arg("-R")
+        .arg(path)
+        .spawn()
+        .map_err(|e| format!("reveal_failed|detail={}", e))?;
+    Ok(())
+}
+
+#[cfg(target_os = "windows")]
+pub fn reveal(path: &Path) -> Result<(), String> {
+    use std::os::windows::process::CommandExt;
+    // Explorer parses its own command line: the path must be quoted after the comma, not the
+    // whole argument
+    Command::new("explorer")
+        .raw_arg(format!("/select,\"{}\"", path.display()))
+        .spawn()
+        .map_err(|e| format!("reveal_failed|detail={}", e))?;
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+pub fn reveal(path: &Path) -> Result<(), String> {
+    #[cfg(target_os = "linux")]
+    match show_items(path) {
+        Ok(()) => return Ok(()),
+        Err(e) => log::info!("reveal: file manager not available over D-Bus ({}), opening the folder", e),
+    }
+    // Without a file manager that can select it, open the folder it is in
+    let folder = if path.is_dir() { path } else { path.parent().unwrap_or(path) };
+    Command::new("xdg-open")
+        .arg(folder)
+        .spawn()
+        .map_err(|e| format!("reveal_failed|detail={}", e))?;
+    Ok(())
+}
+
+/// `file://` URI for an absolute path, each segment percent-encoded.
+#[cfg(target_os = "linux")]
+fn file_uri(path: &Path) -> String {
+    let segments: Vec<_> = path
+        .to_string_lossy()
+        .split('/')
+        .map(|segment| urlencoding::encode(segment).into_owned())
+        .collect();
+    format!("file://{}", segments.join("/"))
+}
+
+/// The freedesktop file manager interface (Nautilus, Dolphin, Nemo, Thunar, ...).
+#[cfg(target_os = "linux")]
+fn show_items(path: &Path) -> Result<(), zbus::Error> {
+    let connection = zbus::blocking::Connection::session()?;
+    connection.call_method(
+        Some("org.freedesktop.FileManager1"),
+        "/org/freedesktop/FileManager1",
+        Some("org.freedesktop.FileManager1"),
+        "ShowItems",
+        &(vec![file_uri(path)], ""),
+    )?;
+    Ok(())
+}
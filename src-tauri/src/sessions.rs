@@ -0,0 +1,476 @@
+use chrono::{DateTime, Duration, Local, NaiveDate, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+
+use crate::{book_id, icloud, progress};
+
+/// Gaps between two activity signals longer than this are treated as idle and end the session.
+const IDLE_TIMEOUT_SECS: i64 = 300;
+/// Sessions shorter than this with a single page are noise (opening a book to check something).
+const MIN_SESSION_SECS: u64 = 10;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReadingSession {
+    pub hash: String,
+    pub started_at: String,
+    pub ended_at: String,
+    /// Seconds of activity, with idle gaps excluded.
+    pub active_seconds: u64,
+    pub pages: Vec<u32>,
+}
+
+struct ActiveSession {
+    started_at: DateTime<Utc>,
+    last_activity: DateTime<Utc>,
+    active_seconds: u64,
+    pages: BTreeSet<u32>,
+}
+
+impl ActiveSession {
+    fn new(now: DateTime<Utc>, page: u32) -> Self {
+        Self {
+            started_at: now,
+            last_activity: now,
+            active_seconds: 0,
+            pages: BTreeSet::from([page]),
+        }
+    }
+
+    fn to_session(&self, hash: &str) -> ReadingSession {
+        ReadingSession {
+            hash: hash.to_string(),
+            started_at: format_time(self.started_at),
+            ended_at: format_time(self.last_activity),
+            active_seconds: self.active_seconds,
+            pages: self.pages.iter().copied().collect(),
+        }
+    }
+}
+
+fn active_sessions() -> &'static Mutex<HashMap<String, ActiveSession>> {
+    static ACTIVE: OnceLock<Mutex<HashMap<String, ActiveSession>>> = OnceLock::new();
+    ACTIVE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn format_time(t: DateTime<Utc>) -> String {
+    t.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string()
+}
+
+fn sessions_file(hash: &str) -> PathBuf {
+    icloud::get_sessions_dir().join(format!("{}.jsonl", hash))
+}
+
+fn append(session: &ReadingSession) -> Result<(), String> {
+    if session.active_seconds < MIN_SESSION_SECS && session.pages.len() < 2 {
+        return Ok(());
+    }
+    let line = serde_json::to_string(session)
+        .map_err(|e| format!("serialize_session_failed|detail={}", e))?;
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(sessions_file(&session.hash))
+        .map_err(|e| format!("write_session_failed|detail={}", e))?;
+    writeln!(file, "{}", line).map_err(|e| format!("write_session_failed|detail={}", e))?;
+    log::info!(
+        "session: hash={}, {}s active, {} pages",
+        &session.hash[..8.min(session.hash.len())],
+        session.active_seconds,
+        session.pages.len()
+    );
+    Ok(())
+}
+
+/// Record a sign of reading (page change, scroll, key press) for a book on a page.
+/// Starts a session if none is open, or closes the open one first if it went idle.
+pub fn record_activity(hash: &str, page: u32) {
+    let hash = &book_id::current(hash);
+    let now = Utc::now();
+    let mut active = active_sessions().lock().unwrap();
+    let idle = active
+        .get(hash)
+        .is_some_and(|s| (now - s.last_activity).num_seconds() > IDLE_TIMEOUT_SECS);
+    if idle {
+        if let Some(finished) = active.remove(hash) {
+            if let Err(e) = append(&finished.to_session(hash)) {
+                log::warn!("session: failed to record for {}: {}", hash, e);
+            }
+        }
+    }
+
+    let session = active
+        .entry(hash.to_string())
+        .or_insert_with(|| ActiveSession::new(now, page));
+    let gap = (now - session.last_activity).num_seconds().max(0) as u64;
+    session.active_seconds += gap;
+    session.last_activity = now;
+    session.pages.insert(page);
+}
+
+/// Close the open session for a book, e.g. when its reader window closes. `hash` may be the ID
+/// the window was opened with; activity since it moved is recorded under the new one.
+pub fn end(hash: &str) {
+    let current = book_id::current(hash);
+    if current != hash {
+        end_as(hash);
+    }
+    end_as(&current);
+}
+
+/// Close the open session kept under exactly `hash`.
+fn end_as(hash: &str) {
+    let finished = active_sessions().lock().unwrap().remove(hash);
+    if let Some(finished) = finished {
+        if let Err(e) = append(&finished.to_session(hash)) {
+            log::warn!("session: failed to record for {}: {}", hash, e);
+        }
+    }
+}
+
+/// Close every open session (app exit).
+pub fn end_all() {
+    let finished: Vec<(String, ActiveSession)> =
+        active_sessions().lock().unwrap().drain().collect();
+    for (hash, session) in finished {
+        if let Err(e) = append(&session.to_session(&hash)) {
+            log::warn!("session: failed to record for {}: {}", hash, e);
+        }
+    }
+}
+
+fn load_all() -> Vec<ReadingSession> {
+    let mut sessions = Vec::new();
+    let Ok(entries) = std::fs::read_dir(icloud::get_sessions_dir()) else {
+        return sessions;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("jsonl") {
+            continue;
+        }
+        let Ok(data) = std::fs::read_to_string(&path) else {
+            continue;
+        };
+        // A torn last line (crash mid-append) is skipped rather than failing the whole log
+        sessions.extend(data.lines().filter_map(|l| serde_json::from_str(l).ok()));
+    }
+    let open = active_sessions().lock().unwrap();
+    sessions.extend(open.iter().map(|(hash, s)| s.to_session(hash)));
+    sessions
+}
+
+/// Remove the session log for a hash.
+pub fn delete(hash: &str) {
+    active_sessions().lock().unwrap().remove(hash);
+    let path = sessions_file(hash);
+    if path.exists() {
+        let _ = std::fs::remove_file(&path);
+    }
+}
+
+/// Hashes with a session log.
+pub fn hashes() -> Vec<String> {
+    std::fs::read_dir(icloud::get_sessions_dir())
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter_map(|e| e.file_name().to_str()?.strip_suffix(".jsonl").map(str::to_string))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// End of the most recent session for a hash; now while a session is open.
+pub fn last_activity(hash: &str) -> Option<DateTime<Utc>> {
+    if active_sessions().lock().unwrap().contains_key(hash) {
+        return Some(Utc::now());
+    }
+    let data = std::fs::read_to_string(sessions_file(hash)).ok()?;
+    data.lines()
+        .filter_map(|l| serde_json::from_str::<ReadingSession>(l).ok())
+        .filter_map(|s| DateTime::parse_from_rfc3339(&s.ended_at).ok())
+        .map(|t| t.with_timezone(&Utc))
+        .max()
+}
+
+/// Move the session log of an old edition of a book to a new one, mapping visited pages.
+pub fn migrate(old: &str, new: &str, map_page: &dyn Fn(u32) -> u32) -> Result<(), String> {
+    // `old` already maps to `new`; close what is still open under `old` itself
+    end_as(old);
+    let old_path = sessions_file(old);
+    let data = match std::fs::read_to_string(&old_path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(format!("read_sessions_failed|detail={}", e)),
+    };
+
+    let mut lines = String::new();
+    for mut session in data.lines().filter_map(|l| serde_json::from_str::<ReadingSession>(l).ok()) {
+        session.hash = new.to_string();
+        session.pages = session.pages.iter().map(|&p| map_page(p)).collect::<BTreeSet<_>>().into_iter().collect();
+        let line = serde_json::to_string(&session)
+            .map_err(|e| format!("serialize_session_failed|detail={}", e))?;
+        lines.push_str(&line);
+        lines.push('\n');
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(sessions_file(new))
+        .map_err(|e| format!("write_session_failed|detail={}", e))?;
+    file.write_all(lines.as_bytes())
+        .map_err(|e| format!("write_session_failed|detail={}", e))?;
+    let _ = std::fs::remove_file(&old_path);
+    Ok(())
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DailyStats {
+    /// Local calendar date, YYYY-MM-DD.
+    pub date: String,
+    pub pages: u32,
+    pub minutes: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BookStats {
+    pub hash: String,
+    pub sessions: u32,
+    pub minutes: f64,
+    pub pages_visited: u32,
+    pub current_page: Option<u32>,
+    pub total_pages: Option<u32>,
+    /// Remaining pages times this book's (or the overall) average time per page.
+    pub estimated_minutes_left: Option<f64>,
+    pub last_read: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReadingStats {
+    pub daily: Vec<DailyStats>,
+    pub books: Vec<BookStats>,
+    pub total_minutes: f64,
+    pub current_streak: u32,
+    pub longest_streak: u32,
+}
+
+fn local_date<Tz: TimeZone>(ts: &str, tz: &Tz) -> Option<NaiveDate> {
+    DateTime::parse_from_rfc3339(ts)
+        .ok()
+        .map(|t| t.with_timezone(tz).date_naive())
+}
+
+fn streaks(days: &BTreeSet<NaiveDate>, today: NaiveDate) -> (u32, u32) {
+    let mut longest = 0;
+    let mut run = 0;
+    let mut prev: Option<NaiveDate> = None;
+    for day in days {
+        run = match prev {
+            Some(p) if *day - p == Duration::days(1) => run + 1,
+            _ => 1,
+        };
+        longest = longest.max(run);
+        prev = Some(*day);
+    }
+
+    // The current streak may end yesterday: today is not over yet
+    let mut current = 0;
+    let mut cursor = if days.contains(&today) {
+        today
+    } else {
+        today - Duration::days(1)
+    };
+    while days.contains(&cursor) {
+        current += 1;
+        cursor -= Duration::days(1);
+    }
+    (current, longest)
+}
+
+/// Pages read on one day, per book.
+type DayPages<'a> = HashSet<(&'a str, u32)>;
+
+/// Aggregate the session log. `days` limits the per-day series to the most recent days.
+pub fn stats(days: u32) -> ReadingStats {
+    summarize(&load_all(), days, Local::now().date_naive(), &Local)
+}
+
+/// `stats` over the given sessions, with days counted in `tz`.
+fn summarize<Tz: TimeZone>(sessions: &[ReadingSession], days: u32, today: NaiveDate, tz: &Tz) -> ReadingStats {
+    let since = today - Duration::days(i64::from(days.max(1)) - 1);
+
+    // Pages per day as a set: a page read in two sessions on the same day counts once
+    let mut daily: BTreeMap<NaiveDate, (DayPages, u64)> = BTreeMap::new();
+    let mut per_book: HashMap<&str, (u32, u64, BTreeSet<u32>, &str)> = HashMap::new();
+    let mut reading_days = BTreeSet::new();
+
+    for s in sessions {
+        let Some(date) = local_date(&s.started_at, tz) else {
+            continue;
+        };
+        reading_days.insert(date);
+        if date >= since {
+            let day = daily.entry(date).or_default();
+            day.0.extend(s.pages.iter().map(|&page| (s.hash.as_str(), page)));
+            day.1 += s.active_seconds;
+        }
+        let book = per_book
+            .entry(s.hash.as_str())
+            .or_insert((0, 0, BTreeSet::new(), s.ended_at.as_str()));
+        book.0 += 1;
+        book.1 += s.active_seconds;
+        book.2.extend(s.pages.iter().copied());
+        if s.ended_at.as_str() > book.3 {
+            book.3 = s.ended_at.as_str();
+        }
+    }
+
+    let total_seconds: u64 = sessions.iter().map(|s| s.active_seconds).sum();
+    let total_pages: usize = per_book.values().map(|b| b.2.len()).sum();
+    let overall_secs_per_page = (total_pages > 0).then(|| total_seconds as f64 / total_pages as f64);
+
+    let mut books: Vec<BookStats> = per_book
+        .into_iter()
+        .map(|(hash, (count, seconds, pages, last_read))| {
+            let progress = progress::peek(hash);
+            let secs_per_page = if pages.len() >= 5 {
+                Some(seconds as f64 / pages.len() as f64)
+            } else {
+                overall_secs_per_page
+            };
+            let estimated_minutes_left = progress.as_ref().and_then(|p| {
+                let left = p.total_pages.saturating_sub(p.current_page);
+                secs_per_page.map(|spp| (f64::from(left) * spp / 60.0).round())
+            });
+            BookStats {
+                hash: hash.to_string(),
+                sessions: count,
+                minutes: (seconds as f64 / 60.0 * 10.0).round() / 10.0,
+                pages_visited: pages.len() as u32,
+                current_page: progress.as_ref().map(|p| p.current_page),
+                total_pages: progress.as_ref().map(|p| p.total_pages),
+                estimated_minutes_left,
+                last_read: last_read.to_string(),
+            }
+        })
+        .collect();
+    books.sort_by(|a, b| b.last_read.cmp(&a.last_read));
+
+    let (current_streak, longest_streak) = streaks(&reading_days, today);
+
+    ReadingStats {
+        daily: daily
+            .into_iter()
+            .map(|(date, (pages, seconds))| DailyStats {
+                date: date.format("%Y-%m-%d").to_string(),
+                pages: pages.len() as u32,
+                minutes: (seconds as f64 / 60.0 * 10.0).round() / 10.0,
+            })
+            .collect(),
+        books,
+        total_minutes: (total_seconds as f64 / 60.0 * 10.0).round() / 10.0,
+        current_streak,
+        longest_streak,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::FixedOffset;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    fn days(list: &[&str]) -> BTreeSet<NaiveDate> {
+        list.iter().map(|d| date(d)).collect()
+    }
+
+    fn session(hash: &str, started_at: &str, seconds: u64, pages: &[u32]) -> ReadingSession {
+        ReadingSession {
+            hash: hash.to_string(),
+            started_at: started_at.to_string(),
+            ended_at: started_at.to_string(),
+            active_seconds: seconds,
+            pages: pages.to_vec(),
+        }
+    }
+
+    #[test]
+    fn streaks_break_on_a_missed_day() {
+        let read = days(&["2026-03-01", "2026-03-02", "2026-03-03", "2026-03-05", "2026-03-06"]);
+        assert_eq!(streaks(&read, date("2026-03-06")), (2, 3));
+        // Today is not over yet: a streak ending yesterday still counts
+        assert_eq!(streaks(&read, date("2026-03-07")), (2, 3));
+        assert_eq!(streaks(&read, date("2026-03-08")), (0, 3));
+        assert_eq!(streaks(&BTreeSet::new(), date("2026-03-08")), (0, 0));
+    }
+
+    #[test]
+    fn streaks_across_month_and_year_ends() {
+        let read = days(&["2025-12-30", "2025-12-31", "2026-01-01", "2026-02-28", "2026-03-01"]);
+        assert_eq!(streaks(&read, date("2026-03-01")), (2, 3));
+    }
+
+    #[test]
+    fn pages_count_once_per_day_and_book() {
+        let sessions = [
+            session("a", "2026-03-01T09:00:00.000Z", 120, &[1, 2, 3]),
+            session("a", "2026-03-01T18:00:00.000Z", 60, &[3, 4]),
+            // Same page number in another book is another page
+            session("b", "2026-03-01T20:00:00.000Z", 60, &[3]),
+            // Read again the next day: counts again
+            session("a", "2026-03-02T09:00:00.000Z", 60, &[4]),
+        ];
+        let stats = summarize(&sessions, 7, date("2026-03-02"), &Utc);
+        let daily: Vec<_> = stats.daily.iter().map(|d| (d.date.as_str(), d.pages, d.minutes)).collect();
+        assert_eq!(daily, [("2026-03-01", 5, 4.0), ("2026-03-02", 1, 1.0)]);
+        assert_eq!(stats.total_minutes, 5.0);
+        assert_eq!((stats.current_streak, stats.longest_streak), (2, 2));
+        let a = stats.books.iter().find(|b| b.hash == "a").unwrap();
+        assert_eq!((a.sessions, a.pages_visited, a.minutes), (3, 4, 4.0));
+        assert_eq!(stats.books[0].hash, "a");
+    }
+
+    #[test]
+    fn days_follow_local_midnight() {
+        let sessions = [
+            session("a", "2026-03-01T22:30:00.000Z", 60, &[1]),
+            session("a", "2026-03-02T01:30:00.000Z", 60, &[2]),
+        ];
+        // In UTC they are on two days
+        let utc = summarize(&sessions, 7, date("2026-03-02"), &Utc);
+        assert_eq!(utc.daily.len(), 2);
+        assert_eq!(utc.current_streak, 2);
+
+        // Three hours behind UTC both are before midnight on March 1st
+        let behind = FixedOffset::west_opt(3 * 3600).unwrap();
+        let stats = summarize(&sessions, 7, date("2026-03-01"), &behind);
+        let daily: Vec<_> = stats.daily.iter().map(|d| (d.date.as_str(), d.pages)).collect();
+        assert_eq!(daily, [("2026-03-01", 2)]);
+        assert_eq!((stats.current_streak, stats.longest_streak), (1, 1));
+
+        // Two hours ahead the first one is already on March 2nd
+        let ahead = FixedOffset::east_opt(2 * 3600).unwrap();
+        let stats = summarize(&sessions, 7, date("2026-03-02"), &ahead);
+        let daily: Vec<_> = stats.daily.iter().map(|d| (d.date.as_str(), d.pages)).collect();
+        assert_eq!(daily, [("2026-03-02", 2)]);
+    }
+
+    #[test]
+    fn daily_series_is_limited_to_recent_days() {
+        let sessions = [
+            session("a", "2026-02-20T12:00:00.000Z", 60, &[1]),
+            session("a", "2026-03-01T12:00:00.000Z", 60, &[2]),
+        ];
+        let stats = summarize(&sessions, 7, date("2026-03-01"), &Utc);
+        assert_eq!(stats.daily.len(), 1);
+        // Totals and streaks still cover everything
+        assert_eq!(stats.total_minutes, 2.0);
+        assert_eq!(stats.longest_streak, 1);
+    }
+}
@@ -0,0 +1,198 @@
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use crate::{fsutil, icloud, paths};
+
+/// How sync settles a disagreement between this device's position and another device's.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MergeStrategy {
+    /// The position read most recently wins.
+    #[default]
+    MostRecent,
+    /// The position furthest into the book wins.
+    FurthestPage,
+    /// Keep the local position and let the reader choose.
+    Ask,
+}
+
+/// Settings shared by every device using the same library (stored next to `Books/`).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LibrarySettings {
+    pub merge_strategy: MergeStrategy,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncBackendKind {
+    /// The iCloud Drive app folder (only when iCloud Drive is available).
+    #[default]
+    Icloud,
+    /// Any local folder kept in sync by another tool.
+    Folder,
+    Webdav,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SyncConfig {
+    pub backend: SyncBackendKind,
+    pub folder: Option<String>,
+    pub webdav_url: Option<String>,
+    pub webdav_username: Option<String>,
+    /// Stored in plain text in the device settings file, which only its owner can read
+    /// (see `save_device`); never synced and never sent to the frontend.
+    pub webdav_password: Option<String>,
+}
+
+/// Settings that belong to this machine only (never synced).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DeviceSettings {
+    pub sync: SyncConfig,
+    /// Periodically remove progress of books no longer in the library (see `gc`).
+    pub auto_gc: bool,
+    /// Library folders registered on this device besides the default one (see `library`).
+    pub libraries: Vec<LibraryEntry>,
+    /// ID of the library shown; None for the default one.
+    pub active_library: Option<String>,
+    /// Where the default library was last used (see `storage_migration`).
+    pub default_root: Option<String>,
+    /// A new default location the reader chose not to move to; not offered again.
+    pub declined_root: Option<String>,
+    /// Books an earlier version left in the local library after switching to iCloud Drive;
+    /// offered for import into the default library until imported or declined.
+    pub pending_import: Option<String>,
+    /// How long the file watchers wait for a burst of changes to settle.
+    pub watcher_debounce_ms: u64,
+    pub max_reader_windows: usize,
+    /// Verbose logging in the frontend (also enabled by `PDF_DEBUG=1`).
+    pub debug: bool,
+}
+
+impl Default for DeviceSettings {
+    fn default() -> Self {
+        Self {
+            sync: SyncConfig::default(),
+            auto_gc: false,
+            libraries: Vec::new(),
+            active_library: None,
+            default_root: None,
+            declined_root: None,
+            pending_import: None,
+            watcher_debounce_ms: 500,
+            max_reader_windows: 3,
+            debug: false,
+        }
+    }
+}
+
+/// A folder holding `Books/` and `Progress/`, registered as a library.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LibraryEntry {
+    pub id: String,
+    pub name: String,
+    pub root: String,
+}
+
+/// Both scopes, as exchanged with the frontend.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub library: LibrarySettings,
+    pub device: DeviceSettings,
+}
+
+const WATCHER_DEBOUNCE_MS: std::ops::RangeInclusive<u64> = 50..=10_000;
+const MAX_READER_WINDOWS: std::ops::RangeInclusive<usize> = 1..=20;
+
+fn library_settings_file() -> PathBuf {
+    icloud::get_base_dir().join("library.json")
+}
+
+/// Library settings, or defaults when the file is missing or unreadable.
+pub fn load_library() -> LibrarySettings {
+    std::fs::read_to_string(library_settings_file())
+        .ok()
+        .and_then(|data| serde_json::from_str(&data).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_library(settings: &LibrarySettings) -> Result<(), String> {
+    let data = serialize(settings)?;
+    fsutil::write_atomic(&library_settings_file(), data.as_bytes(), false)
+        .map_err(|e| format!("write_settings_failed|detail={}", e))
+}
+
+fn device_settings_file() -> PathBuf {
+    paths::config_dir().join("settings.json")
+}
+
+/// Device settings are read on hot paths (every storage path goes through the active library),
+/// and only this process writes them, so they are kept in memory after the first read.
+static DEVICE: Mutex<Option<DeviceSettings>> = Mutex::new(None);
+
+/// Device settings, or defaults when the file is missing or unreadable.
+pub fn load_device() -> DeviceSettings {
+    let mut cached = DEVICE.lock().unwrap();
+    cached
+        .get_or_insert_with(|| {
+            std::fs::read_to_string(device_settings_file())
+                .ok()
+                .and_then(|data| serde_json::from_str(&data).ok())
+                .unwrap_or_default()
+        })
+        .clone()
+}
+
+pub fn save_device(settings: &DeviceSettings) -> Result<(), String> {
+    let mut cached = DEVICE.lock().unwrap();
+    // Holds the WebDAV password: readable by the owner only
+    let data = serialize(settings)?;
+    fsutil::write_private(&device_settings_file(), data.as_bytes())
+        .map_err(|e| format!("write_settings_failed|detail={}", e))?;
+    *cached = Some(settings.clone());
+    Ok(())
+}
+
+pub fn load() -> Settings {
+    Settings {
+        library: load_library(),
+        device: load_device(),
+    }
+}
+
+/// Reject values the rest of the app cannot work with.
+pub fn validate(settings: &Settings) -> Result<(), String> {
+    let device = &settings.device;
+    if !WATCHER_DEBOUNCE_MS.contains(&device.watcher_debounce_ms) {
+        return Err(format!(
+            "invalid_setting|key=watcher_debounce_ms|min={}|max={}",
+            WATCHER_DEBOUNCE_MS.start(),
+            WATCHER_DEBOUNCE_MS.end()
+        ));
+    }
+    if !MAX_READER_WINDOWS.contains(&device.max_reader_windows) {
+        return Err(format!(
+            "invalid_setting|key=max_reader_windows|min={}|max={}",
+            MAX_READER_WINDOWS.start(),
+            MAX_READER_WINDOWS.end()
+        ));
+    }
+    Ok(())
+}
+
+/// Frontend debug logging: the device setting or `PDF_DEBUG=1`.
+pub fn debug_enabled() -> bool {
+    std::env::var("PDF_DEBUG").as_deref() == Ok("1") || load_device().debug
+}
+
+pub fn watcher_debounce() -> std::time::Duration {
+    std::time::Duration::from_millis(load_device().watcher_debounce_ms)
+}
+
+fn serialize<T: Serialize>(settings: &T) -> Result<String, String> {
+    serde_json::to_string_pretty(settings).map_err(|e| format!("serialize_settings_failed|detail={}", e))
+}
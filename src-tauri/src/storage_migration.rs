@@ -0,0 +1,336 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::{book_id, fsutil, hash_cache, icloud, paths, pdf_info, progress, settings, sync_backend};
+
+/// Only one migration runs at a time.
+static MIGRATION_LOCK: Mutex<()> = Mutex::new(());
+
+/// The default library's location no longer matches where it belongs, because iCloud Drive was
+/// turned on or off since it was last used. With `import`, the library stays where it is and
+/// books an earlier version left behind in `from` are offered to be merged into it.
+#[derive(Debug, Clone, Serialize)]
+pub struct StorageChange {
+    /// Where the library is now (and stays until it is migrated), or the leftover books.
+    pub from: String,
+    /// Where it belongs now.
+    pub to: String,
+    pub from_icloud: bool,
+    pub to_icloud: bool,
+    /// PDFs in `from`.
+    pub books: usize,
+    pub import: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MigrationMode {
+    /// Leave the old location as it is.
+    Copy,
+    /// Remove what was copied (or was already there) from the old location. Not offered for
+    /// iCloud Drive, where removing would delete the books on every device.
+    Move,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MigrationReport {
+    pub dry_run: bool,
+    pub mode: MigrationMode,
+    pub from: String,
+    pub to: String,
+    /// Books copied (or, with `dry_run`, to be copied).
+    pub books: Vec<String>,
+    /// Books already at the destination with the same contents.
+    pub already_there: Vec<String>,
+    /// Books whose name is taken at the destination by a different file; left in place.
+    pub conflicts: Vec<String>,
+    /// iCloud placeholders that are not downloaded and cannot be copied.
+    pub placeholders: Vec<String>,
+    /// Progress, annotation and device files copied.
+    pub progress_files: usize,
+    pub bytes: u64,
+    /// Files that could not be copied or removed, with the reason.
+    pub failed: Vec<String>,
+}
+
+fn in_icloud(root: &Path) -> bool {
+    icloud::get_icloud_base().is_some_and(|icloud| icloud == root)
+}
+
+fn books_in(root: &Path) -> usize {
+    std::fs::read_dir(root.join("Books")).map_or(0, |entries| {
+        entries
+            .flatten()
+            .filter(|e| e.path().extension().is_some_and(|ext| ext.eq_ignore_ascii_case("pdf")))
+            .count()
+    })
+}
+
+/// Compare where the default library is with where it belongs, at startup. The first run
+/// pins the location it finds; when an earlier version already switched to iCloud Drive and
+/// left books behind in the local library, those are offered for import.
+pub fn detect() -> Option<StorageChange> {
+    let mut device = settings::load_device();
+    let detected = icloud::detect_default_base_dir();
+    let mut changed = false;
+    let current = match device.default_root.clone().map(PathBuf::from) {
+        Some(root) if root.is_dir() => root,
+        Some(root) => {
+            log::warn!("storage: {} is gone, using {}", root.display(), detected.display());
+            detected.clone()
+        }
+        None => {
+            let leftover = paths::local_library_dir();
+            if leftover != detected && books_in(&leftover) > 0 {
+                log::info!("storage: books left in {}", leftover.display());
+                device.pending_import = Some(leftover.to_string_lossy().to_string());
+                changed = true;
+            }
+            detected.clone()
+        }
+    };
+    // Nothing left to import once the folder is emptied or removed by hand
+    if let Some(leftover) = device.pending_import.clone() {
+        if Path::new(&leftover) == current || books_in(Path::new(&leftover)) == 0 {
+            device.pending_import = None;
+            changed = true;
+        }
+    }
+
+    let declined = device.declined_root.as_deref() == Some(&*detected.to_string_lossy());
+    if current != detected && !declined {
+        log::info!("storage: library at {} now belongs at {}", current.display(), detected.display());
+    }
+    let current_str = current.to_string_lossy().to_string();
+    if device.default_root.as_deref() != Some(&current_str) {
+        device.default_root = Some(current_str);
+        changed = true;
+    }
+    if changed {
+        if let Err(e) = settings::save_device(&device) {
+            log::warn!("storage: failed to remember the library location: {}", e);
+        }
+    }
+    if let Some(leftover) = device.pending_import.map(PathBuf::from) {
+        return Some(StorageChange {
+            from: leftover.to_string_lossy().to_string(),
+            to: current.to_string_lossy().to_string(),
+            from_icloud: in_icloud(&leftover),
+            to_icloud: in_icloud(&current),
+            books: books_in(&leftover),
+            import: true,
+        });
+    }
+    (current != detected && !declined).then(|| StorageChange {
+        from: current.to_string_lossy().to_string(),
+        to: detected.to_string_lossy().to_string(),
+        from_icloud: in_icloud(&current),
+        to_icloud: in_icloud(&detected),
+        books: books_in(&current),
+        import: false,
+    })
+}
+
+/// Point settings and caches at a library folder that was moved as a whole, e.g. from its
+/// legacy location at startup (see `paths::init`).
+pub fn relocated(from: &Path, to: &Path) {
+    let from_str = from.to_string_lossy();
+    let to_str = to.to_string_lossy().to_string();
+    let mut device = settings::load_device();
+    for root in [&mut device.default_root, &mut device.declined_root, &mut device.pending_import]
+        .into_iter()
+        .flatten()
+    {
+        if *root == from_str {
+            *root = to_str.clone();
+        }
+    }
+    for lib in device.libraries.iter_mut().filter(|lib| lib.root == from_str) {
+        lib.root = to_str.clone();
+    }
+    if let Err(e) = settings::save_device(&device) {
+        log::warn!("storage: failed to update the library location: {}", e);
+    }
+    hash_cache::carry_over(&from.join("Books"), &to.join("Books"));
+}
+
+/// Keep the default library where it is and stop offering the new location, or leave the
+/// leftover books where they are.
+pub fn decline() -> Result<(), String> {
+    let mut device = settings::load_device();
+    if device.pending_import.take().is_none() {
+        device.declined_root = Some(icloud::detect_default_base_dir().to_string_lossy().to_string());
+    }
+    settings::save_device(&device)
+}
+
+/// Copy one file, creating its folder. Returns its size; with `dry_run` nothing is copied.
+fn copy_file(from: &Path, to: &Path, dry_run: bool) -> std::io::Result<u64> {
+    if dry_run {
+        return from.metadata().map(|m| m.len());
+    }
+    if let Some(parent) = to.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    fsutil::copy_atomic(from, to)
+}
+
+fn same_book(a: &Path, b: &Path) -> bool {
+    let size = |p: &Path| p.metadata().map(|m| m.len()).ok();
+    size(a) == size(b)
+        && matches!(
+            (pdf_info::compute_hash_cached(a), pdf_info::compute_hash_cached(b)),
+            (Ok(x), Ok(y)) if x == y
+        )
+}
+
+fn migrate_books(from: &Path, to: &Path, report: &mut MigrationReport, copied: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(from) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Some(name) = path.file_name().and_then(|n| n.to_str()).map(str::to_string) else {
+            continue;
+        };
+        if name.starts_with('.') && name.ends_with(".icloud") {
+            report.placeholders.push(name);
+            continue;
+        }
+        if !path.is_file() || name.starts_with('.') || name.ends_with(".tmp") {
+            continue;
+        }
+        let target = to.join(&name);
+        if target.exists() {
+            if same_book(&path, &target) {
+                report.already_there.push(name);
+                copied.push(path);
+            } else {
+                report.conflicts.push(name);
+            }
+            continue;
+        }
+        match copy_file(&path, &target, report.dry_run) {
+            Ok(bytes) => {
+                report.bytes += bytes;
+                report.books.push(name);
+                copied.push(path);
+            }
+            Err(e) => report.failed.push(format!("{}: {}", name, e)),
+        }
+    }
+}
+
+/// Copy progress records, annotations and the device registry. A file the destination already
+/// has is kept there when it is at least as new (another device may have written it).
+fn migrate_progress(from: &Path, to: &Path, report: &mut MigrationReport, copied: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(from) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let target = to.join(entry.file_name());
+        if path.is_dir() {
+            migrate_progress(&path, &target, report, copied);
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.ends_with(".tmp") {
+            continue;
+        }
+        let modified = |p: &Path| p.metadata().and_then(|m| m.modified()).ok();
+        if target.exists() && modified(&target) >= modified(&path) {
+            copied.push(path);
+            continue;
+        }
+        match copy_file(&path, &target, report.dry_run) {
+            Ok(bytes) => {
+                report.bytes += bytes;
+                report.progress_files += 1;
+                copied.push(path);
+            }
+            Err(e) => report.failed.push(format!("{}: {}", path.display(), e)),
+        }
+    }
+}
+
+/// Move the default library to where it now belongs, or merge leftover books into it (see
+/// `detect`), or with `dry_run` report what would happen. Nothing is removed from the old
+/// location when anything failed.
+pub fn migrate(mode: MigrationMode, dry_run: bool) -> Result<MigrationReport, String> {
+    let _guard = MIGRATION_LOCK.lock().unwrap();
+    let (from, to) = match settings::load_device().pending_import {
+        Some(leftover) => (PathBuf::from(leftover), icloud::get_default_base_dir()),
+        None => (icloud::get_default_base_dir(), icloud::detect_default_base_dir()),
+    };
+    if from == to {
+        return Err("storage_migration_not_needed".to_string());
+    }
+    if mode == MigrationMode::Move && in_icloud(&from) {
+        return Err("storage_move_from_icloud".to_string());
+    }
+    let mut report = MigrationReport {
+        dry_run,
+        mode,
+        from: from.to_string_lossy().to_string(),
+        to: to.to_string_lossy().to_string(),
+        books: Vec::new(),
+        already_there: Vec::new(),
+        conflicts: Vec::new(),
+        placeholders: Vec::new(),
+        progress_files: 0,
+        bytes: 0,
+        failed: Vec::new(),
+    };
+
+    if !dry_run {
+        progress::flush_all();
+        for dir in [to.join("Books"), to.join("Progress")] {
+            std::fs::create_dir_all(&dir)
+                .map_err(|e| format!("create_books_dir_failed|detail={}", e))?;
+        }
+    }
+    let mut copied = Vec::new();
+    migrate_books(&from.join("Books"), &to.join("Books"), &mut report, &mut copied);
+    migrate_progress(&from.join("Progress"), &to.join("Progress"), &mut report, &mut copied);
+    let library_settings = from.join("library.json");
+    if library_settings.is_file() && !to.join("library.json").exists() {
+        if let Err(e) = copy_file(&library_settings, &to.join("library.json"), dry_run) {
+            report.failed.push(format!("library.json: {}", e));
+        }
+    }
+    if dry_run {
+        return Ok(report);
+    }
+
+    let carried = hash_cache::carry_over(&from.join("Books"), &to.join("Books"));
+    if mode == MigrationMode::Move && report.failed.is_empty() {
+        for path in &copied {
+            if let Err(e) = std::fs::remove_file(path) {
+                report.failed.push(format!("{}: {}", path.display(), e));
+            }
+        }
+    }
+
+    let mut device = settings::load_device();
+    device.default_root = Some(report.to.clone());
+    device.declined_root = None;
+    device.pending_import = None;
+    settings::save_device(&device)?;
+    icloud::ensure_directories()?;
+    sync_backend::reload();
+    book_id::reload();
+    log::info!(
+        "storage: {:?} {} -> {}: {} books, {} progress files, {} bytes, {} hashes kept, {} failed",
+        mode,
+        report.from,
+        report.to,
+        report.books.len(),
+        report.progress_files,
+        report.bytes,
+        carried,
+        report.failed.len()
+    );
+    Ok(report)
+}
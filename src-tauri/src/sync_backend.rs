@@ -0,0 +1,539 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::{fsutil, icloud};
+use crate::settings::{self, SyncBackendKind, SyncConfig};
+
+/// The central store that devices sync progress, annotations and the device registry through.
+/// Paths are relative and `/`-separated, e.g. `Devices/<id>.json` or `<hash>/<device>.json`.
+pub trait SyncBackend: Send + Sync {
+    /// Short name for logs.
+    fn name(&self) -> &'static str;
+    /// Contents of a file, or None if it does not exist.
+    fn read(&self, path: &str) -> Result<Option<Vec<u8>>, String>;
+    /// Replace a file, creating parent directories as needed.
+    fn write(&self, path: &str, data: &[u8]) -> Result<(), String>;
+    /// Names of the entries directly inside a directory (empty if it does not exist).
+    fn list(&self, dir: &str) -> Result<Vec<String>, String>;
+    /// Remove a file, or a whole directory when the path ends in `/`. Missing entries are not an error.
+    fn remove(&self, path: &str) -> Result<(), String>;
+    /// Local directory mirroring the store, if changes can be watched on disk instead of polled.
+    fn local_root(&self) -> Option<&Path> {
+        None
+    }
+}
+
+/// A plain directory: the iCloud Drive folder, or any folder another tool keeps in sync
+/// (Dropbox, Syncthing, a NAS mount).
+pub struct FolderBackend {
+    name: &'static str,
+    root: PathBuf,
+}
+
+impl FolderBackend {
+    pub fn new(name: &'static str, root: PathBuf) -> Self {
+        Self { name, root }
+    }
+
+    fn resolve(&self, path: &str) -> PathBuf {
+        path.split('/')
+            .filter(|part| !part.is_empty())
+            .fold(self.root.clone(), |acc, part| acc.join(part))
+    }
+}
+
+impl SyncBackend for FolderBackend {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn read(&self, path: &str) -> Result<Option<Vec<u8>>, String> {
+        match std::fs::read(self.resolve(path)) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(format!("sync_read_failed|detail={}", e)),
+        }
+    }
+
+    fn write(&self, path: &str, data: &[u8]) -> Result<(), String> {
+        let target = self.resolve(path);
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| format!("sync_write_failed|detail={}", e))?;
+        }
+        fsutil::write_atomic(&target, data, false).map_err(|e| format!("sync_write_failed|detail={}", e))
+    }
+
+    fn list(&self, dir: &str) -> Result<Vec<String>, String> {
+        let entries = match std::fs::read_dir(self.resolve(dir)) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(format!("sync_list_failed|detail={}", e)),
+        };
+        Ok(entries
+            .flatten()
+            .filter_map(|e| e.file_name().to_str().map(str::to_string))
+            .filter(|name| !name.starts_with('.') && !name.ends_with(".tmp"))
+            .collect())
+    }
+
+    fn local_root(&self) -> Option<&Path> {
+        Some(&self.root)
+    }
+
+    fn remove(&self, path: &str) -> Result<(), String> {
+        let target = self.resolve(path);
+        let result = if target.is_dir() {
+            std::fs::remove_dir_all(&target)
+        } else {
+            std::fs::remove_file(&target)
+        };
+        match result {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(format!("sync_remove_failed|detail={}", e))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A WebDAV collection (Nextcloud, a NAS, `rclone serve webdav`, ...).
+pub struct WebDavBackend {
+    base_url: String,
+    username: String,
+    password: String,
+    client: reqwest::blocking::Client,
+    /// Collections known to exist, so MKCOL is only sent once per directory.
+    collections: Mutex<HashSet<String>>,
+}
+
+impl WebDavBackend {
+    pub fn new(url: &str, username: &str, password: &str) -> Result<Self, String> {
+        let url = url.trim();
+        if !(url.starts_with("http://") || url.starts_with("https://")) {
+            return Err("webdav_invalid_url".to_string());
+        }
+        let client = reqwest::blocking::Client::builder()
+            .connect_timeout(Duration::from_secs(5))
+            .timeout(Duration::from_secs(15))
+            // A redirected DELETE or PUT is replayed as a GET, which would report success
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .map_err(|e| format!("webdav_request_failed|detail={}", e))?;
+        Ok(Self {
+            base_url: format!("{}/", url.trim_end_matches('/')),
+            username: username.to_string(),
+            password: password.to_string(),
+            client,
+            collections: Mutex::new(HashSet::new()),
+        })
+    }
+
+    fn url(&self, path: &str) -> String {
+        let encoded: Vec<String> = path
+            .split('/')
+            .filter(|part| !part.is_empty())
+            .map(|part| urlencoding::encode(part).into_owned())
+            .collect();
+        let trailing = if path.ends_with('/') && !encoded.is_empty() { "/" } else { "" };
+        format!("{}{}{}", self.base_url, encoded.join("/"), trailing)
+    }
+
+    fn request(&self, method: &str, path: &str) -> reqwest::blocking::RequestBuilder {
+        let method = reqwest::Method::from_bytes(method.as_bytes()).unwrap_or(reqwest::Method::GET);
+        let builder = self.client.request(method, self.url(path));
+        if self.username.is_empty() {
+            builder
+        } else {
+            builder.basic_auth(&self.username, Some(&self.password))
+        }
+    }
+
+    fn send(&self, builder: reqwest::blocking::RequestBuilder) -> Result<reqwest::blocking::Response, String> {
+        let response = builder
+            .send()
+            .map_err(|e| format!("webdav_request_failed|detail={}", e))?;
+        match response.status().as_u16() {
+            401 | 403 => Err("webdav_unauthorized".to_string()),
+            _ => Ok(response),
+        }
+    }
+
+    fn ensure_collection(&self, dir: &str) -> Result<(), String> {
+        let mut prefix = String::new();
+        for part in dir.split('/').filter(|p| !p.is_empty()) {
+            prefix = if prefix.is_empty() {
+                part.to_string()
+            } else {
+                format!("{}/{}", prefix, part)
+            };
+            if self.collections.lock().unwrap().contains(&prefix) {
+                continue;
+            }
+            let status = self.send(self.request("MKCOL", &format!("{}/", prefix)))?.status();
+            // 405 Method Not Allowed: the collection already exists
+            if !(status.is_success() || status.as_u16() == 405) {
+                return Err(format!("webdav_request_failed|detail=MKCOL {} {}", prefix, status));
+            }
+            self.collections.lock().unwrap().insert(prefix.clone());
+        }
+        Ok(())
+    }
+}
+
+/// Entry names from a PROPFIND multistatus body. The XML namespace prefix varies by server
+/// (`D:`, `d:`, none), so hrefs are located by tag suffix rather than with a full XML parser.
+fn propfind_names(body: &str, dir_url_path: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut rest = body;
+    while let Some(open) = rest.find("href>") {
+        let after = &rest[open + 5..];
+        let Some(close) = after.find("</") else {
+            break;
+        };
+        let href = after[..close].trim();
+        // Skip past the closing tag so it is not mistaken for the next opening one
+        rest = after[close..].split_once('>').map_or("", |(_, tail)| tail);
+
+        let path = href
+            .find("://")
+            .and_then(|i| href[i + 3..].find('/').map(|j| &href[i + 3 + j..]))
+            .unwrap_or(href);
+        let path = urlencoding::decode(path).map(|p| p.into_owned()).unwrap_or_else(|_| path.to_string());
+        if path.trim_end_matches('/') == dir_url_path.trim_end_matches('/') {
+            continue;
+        }
+        if let Some(name) = path.trim_end_matches('/').rsplit('/').next() {
+            if !name.is_empty() && !name.starts_with('.') {
+                names.push(name.to_string());
+            }
+        }
+    }
+    names
+}
+
+impl SyncBackend for WebDavBackend {
+    fn name(&self) -> &'static str {
+        "webdav"
+    }
+
+    fn read(&self, path: &str) -> Result<Option<Vec<u8>>, String> {
+        let response = self.send(self.request("GET", path))?;
+        let status = response.status();
+        if status.as_u16() == 404 {
+            return Ok(None);
+        }
+        if !status.is_success() {
+            return Err(format!("webdav_request_failed|detail=GET {} {}", path, status));
+        }
+        let bytes = response
+            .bytes()
+            .map_err(|e| format!("webdav_request_failed|detail={}", e))?;
+        Ok(Some(bytes.to_vec()))
+    }
+
+    fn write(&self, path: &str, data: &[u8]) -> Result<(), String> {
+        if let Some((dir, _)) = path.rsplit_once('/') {
+            self.ensure_collection(dir)?;
+        }
+        let status = self.send(self.request("PUT", path).body(data.to_vec()))?.status();
+        if !status.is_success() {
+            return Err(format!("webdav_request_failed|detail=PUT {} {}", path, status));
+        }
+        Ok(())
+    }
+
+    fn list(&self, dir: &str) -> Result<Vec<String>, String> {
+        let dir_path = format!("{}/", dir.trim_end_matches('/'));
+        let response = self.send(self.request("PROPFIND", &dir_path).header("Depth", "1"))?;
+        let status = response.status();
+        if status.as_u16() == 404 {
+            return Ok(Vec::new());
+        }
+        if !status.is_success() {
+            return Err(format!("webdav_request_failed|detail=PROPFIND {} {}", dir, status));
+        }
+        let body = response
+            .text()
+            .map_err(|e| format!("webdav_request_failed|detail={}", e))?;
+        let full = self.url(&dir_path);
+        let url_path = full
+            .find("://")
+            .and_then(|i| full[i + 3..].find('/').map(|j| full[i + 3 + j..].to_string()))
+            .unwrap_or_default();
+        let url_path = urlencoding::decode(&url_path).map(|p| p.into_owned()).unwrap_or(url_path);
+        Ok(propfind_names(&body, &url_path))
+    }
+
+    fn remove(&self, path: &str) -> Result<(), String> {
+        let trimmed = path.trim_end_matches('/');
+        let known = self.collections.lock().unwrap().contains(trimmed);
+        let mut target = if known { format!("{}/", trimmed) } else { path.to_string() };
+        let mut status = self.send(self.request("DELETE", &target))?.status();
+        if status.is_redirection() && !target.ends_with('/') {
+            // Servers redirect a collection named without its slash to the slashed URL
+            target = format!("{}/", trimmed);
+            status = self.send(self.request("DELETE", &target))?.status();
+        }
+        if !(status.is_success() || status.as_u16() == 404) {
+            return Err(format!("webdav_request_failed|detail=DELETE {} {}", target, status));
+        }
+        self.collections
+            .lock()
+            .unwrap()
+            .retain(|c| c != trimmed && !c.starts_with(&format!("{}/", trimmed)));
+        Ok(())
+    }
+}
+
+fn build(config: &SyncConfig) -> Result<Option<Arc<dyn SyncBackend>>, String> {
+    match config.backend {
+        SyncBackendKind::Icloud => Ok(icloud::is_icloud_active()
+            .then(|| Arc::new(FolderBackend::new("icloud", icloud::get_progress_dir())) as Arc<dyn SyncBackend>)),
+        SyncBackendKind::Folder => {
+            let folder = config.folder.as_deref().map(str::trim).unwrap_or_default();
+            if folder.is_empty() {
+                return Err("sync_folder_missing".to_string());
+            }
+            let folder = PathBuf::from(folder);
+            if !folder.is_dir() {
+                return Err("sync_folder_not_exist".to_string());
+            }
+            Ok(Some(Arc::new(FolderBackend::new("folder", folder))))
+        }
+        SyncBackendKind::Webdav => {
+            let backend = WebDavBackend::new(
+                config.webdav_url.as_deref().unwrap_or_default(),
+                config.webdav_username.as_deref().unwrap_or_default(),
+                config.webdav_password.as_deref().unwrap_or_default(),
+            )?;
+            Ok(Some(Arc::new(backend)))
+        }
+    }
+}
+
+fn cache() -> &'static Mutex<Option<Option<Arc<dyn SyncBackend>>>> {
+    static BACKEND: Mutex<Option<Option<Arc<dyn SyncBackend>>>> = Mutex::new(None);
+    &BACKEND
+}
+
+/// The configured backend, or None when sync is unavailable (e.g. iCloud Drive is off).
+pub fn current() -> Option<Arc<dyn SyncBackend>> {
+    let mut cached = cache().lock().unwrap();
+    if let Some(backend) = cached.as_ref() {
+        return backend.clone();
+    }
+    let backend = match build(&settings::load_device().sync) {
+        Ok(backend) => backend,
+        Err(e) => {
+            log::warn!("sync backend unavailable: {}", e);
+            None
+        }
+    };
+    if let Some(b) = &backend {
+        log::info!("sync backend: {}", b.name());
+    }
+    *cached = Some(backend.clone());
+    backend
+}
+
+/// Rebuild the backend on next use, e.g. after the storage folder it may live in moved.
+pub fn reload() {
+    *cache().lock().unwrap() = None;
+}
+
+/// Validate and store a new sync configuration, and switch to it.
+pub fn configure(config: SyncConfig) -> Result<(), String> {
+    let backend = build(&config)?;
+    if let Some(b) = &backend {
+        // Fail early on bad credentials or an unreachable server
+        b.list("")?;
+    }
+    let mut device = settings::load_device();
+    device.sync = config;
+    settings::save_device(&device)?;
+    *cache().lock().unwrap() = Some(backend);
+    Ok(())
+}
+
+/// Parse a JSON document from the backend; None if missing.
+pub fn read_json<T: serde::de::DeserializeOwned>(
+    backend: &dyn SyncBackend,
+    path: &str,
+) -> Result<Option<T>, String> {
+    match backend.read(path)? {
+        Some(data) => serde_json::from_slice(&data)
+            .map(Some)
+            .map_err(|e| format!("sync_parse_failed|detail={}", e)),
+        None => Ok(None),
+    }
+}
+
+pub fn write_json<T: serde::Serialize>(backend: &dyn SyncBackend, path: &str, value: &T) -> Result<(), String> {
+    let data = serde_json::to_vec_pretty(value)
+        .map_err(|e| format!("sync_serialize_failed|detail={}", e))?;
+    backend.write(path, &data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+
+    #[test]
+    fn propfind_names_handles_prefixes_absolute_hrefs_and_encoding() {
+        let body = r#"<?xml version="1.0"?>
+<d:multistatus xmlns:d="DAV:">
+  <d:response><d:href>/dav/Progress/</d:href></d:response>
+  <d:response><d:href>/dav/Progress/abc.json</d:href></d:response>
+  <d:response><d:href>https://example.com/dav/Progress/My%20Book.json</d:href></d:response>
+  <d:response><d:href>/dav/Progress/sub/</d:href></d:response>
+  <d:response><d:href>/dav/Progress/.hidden</d:href></d:response>
+</d:multistatus>"#;
+        assert_eq!(propfind_names(body, "/dav/Progress/"), vec!["abc.json", "My Book.json", "sub"]);
+
+        let unprefixed = r#"<multistatus xmlns="DAV:"><response><href>/Progress</href></response>
+<response><href>/Progress/x.json</href></response></multistatus>"#;
+        assert_eq!(propfind_names(unprefixed, "/Progress/"), vec!["x.json"]);
+        assert!(propfind_names("", "/").is_empty());
+    }
+
+    const MOVED: &str = "301 Moved Permanently";
+
+    /// A minimal WebDAV server: one request per connection, files kept in memory.
+    fn serve(expected_auth: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let mut files: BTreeMap<String, Vec<u8>> = BTreeMap::new();
+            let mut collections: HashSet<String> = HashSet::from(["/dav/".to_string()]);
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { continue };
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let mut parts = line.split_whitespace();
+                let method = parts.next().unwrap_or_default().to_string();
+                let path = urlencoding::decode(parts.next().unwrap_or_default()).unwrap().into_owned();
+                let mut length = 0;
+                let mut authorized = false;
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+                    let header = header.trim_end();
+                    if header.is_empty() {
+                        break;
+                    }
+                    let (name, value) = header.split_once(':').unwrap();
+                    match name.to_ascii_lowercase().as_str() {
+                        "content-length" => length = value.trim().parse().unwrap(),
+                        "authorization" => authorized = value.trim() == expected_auth,
+                        _ => {}
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+
+                let redirect = format!("Location: {}/\r\n", path);
+                let parent = |p: &str| format!("{}/", p.trim_end_matches('/').rsplit_once('/').unwrap().0);
+                let (status, reply) = if !authorized {
+                    ("401 Unauthorized", Vec::new())
+                } else {
+                    match method.as_str() {
+                        "MKCOL" if collections.contains(&path) => ("405 Method Not Allowed", Vec::new()),
+                        "MKCOL" if collections.contains(&parent(&path)) => {
+                            collections.insert(path);
+                            ("201 Created", Vec::new())
+                        }
+                        "PUT" if collections.contains(&parent(&path)) => {
+                            files.insert(path, body);
+                            ("201 Created", Vec::new())
+                        }
+                        "GET" => match files.get(&path) {
+                            Some(data) => ("200 OK", data.clone()),
+                            None => ("404 Not Found", Vec::new()),
+                        },
+                        "PROPFIND" if collections.contains(&path) => {
+                            let mut xml = format!("<D:multistatus xmlns:D=\"DAV:\"><D:response><D:href>{}</D:href></D:response>", path);
+                            for name in files.keys().filter(|f| parent(f) == path) {
+                                xml.push_str(&format!("<D:response><D:href>{}</D:href></D:response>", name.replace(' ', "%20")));
+                            }
+                            xml.push_str("</D:multistatus>");
+                            ("207 Multi-Status", xml.into_bytes())
+                        }
+                        "DELETE" if collections.contains(&format!("{}/", path)) => (MOVED, Vec::new()),
+                        "DELETE" if collections.contains(&path) => {
+                            collections.retain(|c| !c.starts_with(&path));
+                            files.retain(|f, _| !f.starts_with(&path));
+                            ("204 No Content", Vec::new())
+                        }
+                        "DELETE" => match files.remove(&path) {
+                            Some(_) => ("204 No Content", Vec::new()),
+                            None => ("404 Not Found", Vec::new()),
+                        },
+                        "PROPFIND" => ("404 Not Found", Vec::new()),
+                        _ => ("409 Conflict", Vec::new()),
+                    }
+                };
+                let location = if status == MOVED { redirect } else { String::new() };
+                let head = format!(
+                    "HTTP/1.1 {}\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n",
+                    status,
+                    location,
+                    reply.len()
+                );
+                let _ = stream.write_all(head.as_bytes());
+                let _ = stream.write_all(&reply);
+            }
+        });
+        format!("http://{}/dav", addr)
+    }
+
+    #[test]
+    fn webdav_round_trip() {
+        // "user:secret"
+        let url = serve("Basic dXNlcjpzZWNyZXQ=");
+        let backend = WebDavBackend::new(&url, "user", "secret").unwrap();
+
+        assert_eq!(backend.read("Progress/missing.json").unwrap(), None);
+        assert!(backend.list("Progress").unwrap().is_empty());
+
+        backend.write("Progress/abc.json", b"{\"page\":3}").unwrap();
+        backend.write("Progress/My Book.json", b"{}").unwrap();
+        assert_eq!(backend.read("Progress/abc.json").unwrap().as_deref(), Some(&b"{\"page\":3}"[..]));
+        let mut names = backend.list("Progress").unwrap();
+        names.sort();
+        assert_eq!(names, vec!["My Book.json", "abc.json"]);
+
+        backend.remove("Progress/abc.json").unwrap();
+        backend.remove("Progress/abc.json").unwrap();
+        assert_eq!(backend.read("Progress/abc.json").unwrap(), None);
+        assert_eq!(backend.list("Progress").unwrap(), vec!["My Book.json"]);
+
+        backend.write("Progress/sub/x.json", b"{}").unwrap();
+        backend.write("Progress/sub/y.json", b"{}").unwrap();
+        backend.remove("Progress/sub/").unwrap();
+        assert_eq!(backend.read("Progress/sub/x.json").unwrap(), None);
+        assert_eq!(backend.list("Progress/sub").unwrap(), Vec::<String>::new());
+
+        // A collection named without its slash, by a client that never created it
+        backend.write("Progress/other/z.json", b"{}").unwrap();
+        let fresh = WebDavBackend::new(&url, "user", "secret").unwrap();
+        fresh.remove("Progress/other").unwrap();
+        assert_eq!(fresh.read("Progress/other/z.json").unwrap(), None);
+        // Removing a collection forgets it, so the next write creates it again
+        backend.write("Progress/sub/x.json", b"{}").unwrap();
+        assert_eq!(backend.read("Progress/sub/x.json").unwrap().as_deref(), Some(&b"{}"[..]));
+
+        let wrong = WebDavBackend::new(&url, "user", "wrong").unwrap();
+        assert_eq!(wrong.read("Progress/My Book.json"), Err("webdav_unauthorized".to_string()));
+    }
+
+    #[test]
+    fn webdav_rejects_non_http_urls() {
+        assert!(WebDavBackend::new("ftp://example.com", "", "").is_err());
+    }
+}
@@ -1,52 +1,333 @@
 use notify::Watcher;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::Path;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{mpsc, Arc};
-use std::time::Duration;
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
 use tauri::Emitter;
 
-use crate::icloud;
+use crate::commands::{self, ProgressSource};
+use crate::sync_backend::{self, SyncBackend};
+use crate::{book_id, icloud, icloud_placeholder, progress, settings, window};
 
-pub fn start_watching(app_handle: tauri::AppHandle, stop: Arc<AtomicBool>) -> Result<(), String> {
-    let books_dir = icloud::get_books_dir();
-    if !books_dir.exists() {
-        return Err("books_dir_not_exist".to_string());
+/// Backends without a local folder to watch (WebDAV) are polled this often, for the books open
+/// in reader windows only; the library syncs the rest when it loads.
+const REMOTE_POLL_SECS: u64 = 60;
+
+/// Backoff between attempts to watch the Books folder, doubling from the first to the last.
+const RETRY_MIN: Duration = Duration::from_secs(1);
+const RETRY_MAX: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WatcherState {
+    Starting,
+    Watching,
+    /// The Books folder is missing (e.g. deleted, or on a drive that is not connected).
+    Waiting,
+    Failed,
+    Stopped,
+}
+
+/// How the Books folder watcher is doing, for the frontend (`watcher:health`).
+#[derive(Debug, Clone, Serialize)]
+pub struct WatcherHealth {
+    pub state: WatcherState,
+    pub dir: String,
+    /// When the current state was entered.
+    pub since: String,
+    /// Times the watcher was set up again after the first time.
+    pub restarts: u32,
+    pub last_error: Option<String>,
+    pub retry_in_secs: Option<u64>,
+}
+
+static HEALTH: Mutex<Option<WatcherHealth>> = Mutex::new(None);
+static RESTART: AtomicBool = AtomicBool::new(false);
+
+pub fn health() -> WatcherHealth {
+    HEALTH.lock().unwrap().clone().unwrap_or_else(|| WatcherHealth {
+        state: WatcherState::Starting,
+        dir: icloud::get_books_dir().to_string_lossy().to_string(),
+        since: chrono::Utc::now().to_rfc3339(),
+        restarts: 0,
+        last_error: None,
+        retry_in_secs: None,
+    })
+}
+
+fn set_health(app_handle: &tauri::AppHandle, update: impl FnOnce(&mut WatcherHealth)) {
+    let mut current = health();
+    let before = current.state;
+    update(&mut current);
+    if current.state != before {
+        current.since = chrono::Utc::now().to_rfc3339();
     }
+    *HEALTH.lock().unwrap() = Some(current.clone());
+    let _ = app_handle.emit("watcher:health", current);
+}
+
+/// Ask the supervisor to set the watcher up again now, skipping any pending retry delay.
+pub fn restart() {
+    RESTART.store(true, Ordering::Relaxed);
+}
 
+/// Identity of a folder, to notice it was deleted and recreated under the same path (the
+/// watch would then still point at the old one).
+fn dir_identity(dir: &Path) -> Option<(u64, u64)> {
+    let meta = std::fs::metadata(dir).ok()?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        Some((meta.dev(), meta.ino()))
+    }
+    #[cfg(not(unix))]
+    {
+        meta.is_dir().then_some((0, 0))
+    }
+}
+
+/// Watch the Books folder of the active library until `stop` is set. The watch is set up again
+/// when the folder is deleted or recreated, the library changes, the watcher fails (with
+/// backoff) or `restart` is called. The library is told to rescan after every re-arm, since
+/// changes may have been missed in between.
+pub fn supervise(app_handle: tauri::AppHandle, stop: Arc<AtomicBool>) {
+    let mut delay = RETRY_MIN;
+    let mut armed_before = false;
+    while !stop.load(Ordering::Relaxed) {
+        RESTART.store(false, Ordering::Relaxed);
+        let books_dir = icloud::get_books_dir();
+        let dir = books_dir.to_string_lossy().to_string();
+        let result = if books_dir.is_dir() {
+            watch_books(&app_handle, &stop, &books_dir, |app_handle| {
+                set_health(app_handle, |h| {
+                    h.state = WatcherState::Watching;
+                    h.dir = dir.clone();
+                    h.retry_in_secs = None;
+                    if armed_before {
+                        h.restarts += 1;
+                    }
+                });
+                if armed_before {
+                    let _ = app_handle.emit("books:changed", ());
+                }
+                armed_before = true;
+            })
+        } else {
+            Err("books_dir_not_exist".to_string())
+        };
+        if stop.load(Ordering::Relaxed) {
+            break;
+        }
+
+        match result {
+            // The folder changed under the watch; set it up again right away
+            Ok(()) => delay = RETRY_MIN,
+            Err(e) => {
+                let state = if books_dir.is_dir() { WatcherState::Failed } else { WatcherState::Waiting };
+                log::warn!("File watcher: {} ({:?}), retrying in {}s", e, state, delay.as_secs());
+                set_health(&app_handle, |h| {
+                    h.state = state;
+                    h.dir = books_dir.to_string_lossy().to_string();
+                    h.last_error = Some(e);
+                    h.retry_in_secs = Some(delay.as_secs());
+                });
+                let until = Instant::now() + delay;
+                while Instant::now() < until && !stop.load(Ordering::Relaxed) && !RESTART.load(Ordering::Relaxed) {
+                    std::thread::sleep(Duration::from_millis(250));
+                }
+                delay = if RESTART.load(Ordering::Relaxed) { RETRY_MIN } else { (delay * 2).min(RETRY_MAX) };
+            }
+        }
+    }
+    set_health(&app_handle, |h| {
+        h.state = WatcherState::Stopped;
+        h.retry_in_secs = None;
+    });
+    log::info!("File watcher stopped");
+}
+
+enum Signal {
+    Changed,
+    Failed(String),
+}
+
+/// Watch one Books folder. Returns Ok when it should be watched again (folder deleted,
+/// recreated or no longer the active one, or a restart was requested) and Err when the
+/// watcher itself failed. `on_armed` runs once the watch is in place.
+fn watch_books(
+    app_handle: &tauri::AppHandle,
+    stop: &AtomicBool,
+    books_dir: &Path,
+    on_armed: impl FnOnce(&tauri::AppHandle),
+) -> Result<(), String> {
     let (tx, rx) = mpsc::channel();
 
     let mut watcher = notify::recommended_watcher(move |res: Result<notify::Event, notify::Error>| {
-        if let Ok(event) = res {
-            let dominated_by_pdf = event.paths.iter().any(|p| {
-                p.extension()
-                    .map_or(false, |ext| ext.eq_ignore_ascii_case("pdf"))
-            });
-            if dominated_by_pdf {
-                let _ = tx.send(());
+        match res {
+            Ok(event) => {
+                // Placeholders come and go as iCloud Drive evicts and downloads books
+                let dominated_by_pdf = event.paths.iter().any(|p| {
+                    p.extension()
+                        .map_or(false, |ext| ext.eq_ignore_ascii_case("pdf"))
+                        || icloud_placeholder::is_placeholder(p)
+                });
+                if dominated_by_pdf {
+                    let _ = tx.send(Signal::Changed);
+                }
+            }
+            Err(e) => {
+                let _ = tx.send(Signal::Failed(e.to_string()));
             }
         }
     })
     .map_err(|e| format!("create_watcher_failed|detail={}", e))?;
 
     watcher
-        .watch(&books_dir, notify::RecursiveMode::NonRecursive)
+        .watch(books_dir, notify::RecursiveMode::NonRecursive)
         .map_err(|e| format!("watch_dir_failed|detail={}", e))?;
+    let identity = dir_identity(books_dir);
 
     log::info!("Watching directory: {}", books_dir.display());
+    on_armed(app_handle);
 
     // Debounce loop
-    while !stop.load(Ordering::Relaxed) {
+    loop {
+        if stop.load(Ordering::Relaxed) || RESTART.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        if icloud::get_books_dir() != books_dir || dir_identity(books_dir) != identity {
+            log::info!("Books directory {} moved or was recreated", books_dir.display());
+            return Ok(());
+        }
         match rx.recv_timeout(Duration::from_millis(500)) {
-            Ok(()) => {
-                // Debounce: wait 500ms for more events
-                while rx.recv_timeout(Duration::from_millis(500)).is_ok() {}
+            Ok(Signal::Changed) => {
+                // Debounce: wait until events stop arriving
+                let debounce = settings::watcher_debounce();
+                while let Ok(signal) = rx.recv_timeout(debounce) {
+                    if let Signal::Failed(e) = signal {
+                        return Err(format!("watch_dir_failed|detail={}", e));
+                    }
+                }
                 let _ = app_handle.emit("books:changed", ());
                 log::info!("Books directory changed, emitted event");
             }
+            Ok(Signal::Failed(e)) => return Err(format!("watch_dir_failed|detail={}", e)),
             Err(mpsc::RecvTimeoutError::Timeout) => continue,
-            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            Err(mpsc::RecvTimeoutError::Disconnected) => return Err("watcher_disconnected".to_string()),
         }
     }
+}
 
-    log::info!("File watcher stopped");
+/// Book hash a changed central file belongs to: `<hash>.json` or `<hash>/<device>.json`.
+/// Other entries (`Devices/`, `Annotations/`, temp files) are ignored, and so are records older
+/// devices write under a short ID this device has no book for.
+fn changed_hash(root: &Path, path: &Path) -> Option<String> {
+    let first = path.strip_prefix(root).ok()?.components().next()?;
+    let name = first.as_os_str().to_str()?;
+    if name.ends_with(".tmp") || path.to_string_lossy().ends_with(".tmp") {
+        return None;
+    }
+    book_id::resolve(progress::hash_of_entry(name)?)
+}
+
+fn sync_and_emit(app_handle: &tauri::AppHandle, hashes: impl IntoIterator<Item = String>) {
+    for hash in hashes {
+        match progress::sync(&hash) {
+            Ok(Some(pulled)) => {
+                log::info!(
+                    "progress watcher: hash={}, remote page={}",
+                    &hash[..8.min(hash.len())],
+                    pulled.current_page
+                );
+                commands::emit_progress_changed(app_handle, &pulled, ProgressSource::Remote);
+            }
+            Ok(None) => {}
+            Err(e) => log::warn!("progress watcher: sync failed for {}: {}", hash, e),
+        }
+    }
+    commands::emit_new_conflicts(app_handle);
+}
+
+/// Push progress written by other devices to open windows as soon as it lands in the central
+/// store, instead of waiting for the next frontend poll. Folder backends (iCloud Drive, a
+/// synced folder) are watched on disk; others are polled. Re-arms when the backend changes.
+pub fn watch_progress(app_handle: tauri::AppHandle, stop: Arc<AtomicBool>) {
+    while !stop.load(Ordering::Relaxed) {
+        let backend = sync_backend::current();
+        match backend.as_ref().and_then(|b| b.local_root().map(Path::to_path_buf)) {
+            Some(root) => {
+                if let Err(e) = watch_folder(&app_handle, &stop, backend.as_ref().unwrap(), &root) {
+                    log::warn!("progress watcher: {}", e);
+                    std::thread::sleep(Duration::from_secs(5));
+                }
+            }
+            None => poll_remote(&app_handle, &stop, backend.as_ref()),
+        }
+    }
+    log::info!("Progress watcher stopped");
+}
+
+fn backend_changed(current: Option<&Arc<dyn SyncBackend>>) -> bool {
+    match (current, sync_backend::current()) {
+        (Some(a), Some(b)) => !Arc::ptr_eq(a, &b),
+        (None, None) => false,
+        _ => true,
+    }
+}
+
+fn watch_folder(
+    app_handle: &tauri::AppHandle,
+    stop: &AtomicBool,
+    backend: &Arc<dyn SyncBackend>,
+    root: &Path,
+) -> Result<(), String> {
+    let (tx, rx) = mpsc::channel();
+    let watch_root = root.to_path_buf();
+    let mut watcher = notify::recommended_watcher(move |res: Result<notify::Event, notify::Error>| {
+        if let Ok(event) = res {
+            for path in &event.paths {
+                if let Some(hash) = changed_hash(&watch_root, path) {
+                    let _ = tx.send(hash);
+                }
+            }
+        }
+    })
+    .map_err(|e| format!("create_watcher_failed|detail={}", e))?;
+    watcher
+        .watch(root, notify::RecursiveMode::Recursive)
+        .map_err(|e| format!("watch_dir_failed|detail={}", e))?;
+    log::info!("Watching progress directory: {}", root.display());
+
+    while !stop.load(Ordering::Relaxed) && !backend_changed(Some(backend)) {
+        match rx.recv_timeout(Duration::from_millis(500)) {
+            Ok(first) => {
+                let mut hashes = HashSet::from([first]);
+                // Debounce: a device writes its record and the legacy file back to back
+                let debounce = settings::watcher_debounce();
+                while let Ok(hash) = rx.recv_timeout(debounce) {
+                    hashes.insert(hash);
+                }
+                sync_and_emit(app_handle, hashes);
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => continue,
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+    }
     Ok(())
 }
+
+fn poll_remote(app_handle: &tauri::AppHandle, stop: &AtomicBool, backend: Option<&Arc<dyn SyncBackend>>) {
+    let mut last_poll = Instant::now();
+    while !stop.load(Ordering::Relaxed) && !backend_changed(backend) {
+        std::thread::sleep(Duration::from_secs(1));
+        if backend.is_some() && last_poll.elapsed() >= Duration::from_secs(REMOTE_POLL_SECS) {
+            last_poll = Instant::now();
+            let open = window::open_reader_hashes(app_handle);
+            if !open.is_empty() {
+                sync_and_emit(app_handle, open);
+            }
+        }
+    }
+}
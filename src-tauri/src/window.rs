@@ -1,5 +1,7 @@
 use tauri::{AppHandle, Manager, WebviewUrl, WebviewWindowBuilder};
 
+use crate::{book_id, settings};
+
 pub fn open_reader(app_handle: &AppHandle, file_path: &str, hash: &str) -> Result<(), String> {
     let label = format!("reader-{}", hash);
 
@@ -12,7 +14,7 @@ pub fn open_reader(app_handle: &AppHandle, file_path: &str, hash: &str) -> Resul
     }
 
     // Limit max reader windows
-    const MAX_READER_WINDOWS: usize = 3;
+    let max_reader_windows = settings::load_device().max_reader_windows;
 
     let reader_count = app_handle
         .webview_windows()
@@ -20,10 +22,10 @@ pub fn open_reader(app_handle: &AppHandle, file_path: &str, hash: &str) -> Resul
         .filter(|l| l.starts_with("reader-"))
         .count();
 
-    if reader_count >= MAX_READER_WINDOWS {
+    if reader_count >= max_reader_windows {
         return Err(format!(
             "max_reader_windows|max={}",
-            MAX_READER_WINDOWS
+            max_reader_windows
         ));
     }
 
@@ -67,3 +69,16 @@ pub fn open_reader(app_handle: &AppHandle, file_path: &str, hash: &str) -> Resul
 
     Ok(())
 }
+
+/// Hashes of the books open in reader windows, under their current IDs.
+pub fn open_reader_hashes(app_handle: &AppHandle) -> Vec<String> {
+    let mut hashes: Vec<String> = app_handle
+        .webview_windows()
+        .keys()
+        .filter_map(|label| label.strip_prefix("reader-"))
+        .map(book_id::current)
+        .collect();
+    hashes.sort();
+    hashes.dedup();
+    hashes
+}
@@ -1,5 +1,26 @@
 import { invoke } from "@tauri-apps/api/core";
-import type { PdfInfo, OutlineItem, ReadingProgress } from "./types";
+import type {
+  Annotation,
+  Device,
+  DeviceProgress,
+  ConflictDecision,
+  ExportFormat,
+  GcReport,
+  Library,
+  MergeStrategy,
+  MigrationMode,
+  MigrationReport,
+  PdfInfo,
+  OutlineItem,
+  PdfAnnotation,
+  ReadingProgress,
+  ReadingStats,
+  Settings,
+  StorageChange,
+  SyncConfig,
+  SyncConflict,
+  WatcherHealth,
+} from "./types";
 
 export async function scanBooks(): Promise<PdfInfo[]> {
   return invoke("scan_books");
@@ -13,6 +34,35 @@ export async function getPdfOutline(filePath: string): Promise<OutlineItem[]> {
   return invoke("get_pdf_outline", { filePath });
 }
 
+export async function getPdfAnnotations(
+  filePath: string,
+): Promise<PdfAnnotation[]> {
+  return invoke("get_pdf_annotations", { filePath });
+}
+
+export async function exportAnnotatedPdf(
+  filePath: string,
+  hash: string,
+  destPath: string,
+): Promise<void> {
+  return invoke("export_annotated_pdf", { filePath, hash, destPath });
+}
+
+export async function exportAnnotations(
+  filePath: string,
+  format: ExportFormat,
+  destPath: string,
+): Promise<void> {
+  return invoke("export_annotations", { filePath, format, destPath });
+}
+
+export async function exportAllAnnotations(
+  format: ExportFormat,
+  destDir: string,
+): Promise<string[]> {
+  return invoke("export_all_annotations", { format, destDir });
+}
+
 export async function loadProgress(
   hash: string,
 ): Promise<ReadingProgress | null> {
@@ -25,6 +75,46 @@ export async function saveProgress(
   return invoke("save_progress", { progressData });
 }
 
+export async function loadAnnotations(hash: string): Promise<Annotation[]> {
+  return invoke("load_annotations", { hash });
+}
+
+export async function addAnnotation(
+  annotation: Omit<Annotation, "id" | "created_at" | "updated_at" | "deleted">,
+): Promise<Annotation> {
+  return invoke("add_annotation", { annotation });
+}
+
+export async function updateAnnotation(
+  annotation: Annotation,
+): Promise<Annotation> {
+  return invoke("update_annotation", { annotation });
+}
+
+export async function deleteAnnotation(
+  hash: string,
+  id: string,
+): Promise<void> {
+  return invoke("delete_annotation", { hash, id });
+}
+
+export async function syncAnnotations(
+  hash: string,
+): Promise<Annotation[] | null> {
+  return invoke("sync_annotations", { hash });
+}
+
+export async function recordReadingActivity(
+  hash: string,
+  page: number,
+): Promise<void> {
+  return invoke("record_reading_activity", { hash, page });
+}
+
+export async function getReadingStats(days?: number): Promise<ReadingStats> {
+  return invoke("get_reading_stats", { days });
+}
+
 export async function openReaderWindow(
   filePath: string,
   hash: string,
@@ -40,6 +130,10 @@ export async function revealInFinder(path: string): Promise<void> {
   return invoke("reveal_in_finder", { path });
 }
 
+export async function downloadBook(filePath: string): Promise<void> {
+  return invoke("download_book", { filePath });
+}
+
 export async function deletePdf(
   filePath: string,
   hash: string,
@@ -66,6 +160,118 @@ export async function syncAllProgress(
   return invoke("sync_all_progress", { hashes });
 }
 
+export async function getProgressRecords(
+  hash: string,
+): Promise<DeviceProgress[]> {
+  return invoke("get_progress_records", { hash });
+}
+
+export async function getDevices(): Promise<Device[]> {
+  return invoke("get_devices");
+}
+
+export async function renameDevice(name: string): Promise<Device> {
+  return invoke("rename_device", { name });
+}
+
+export async function getSyncConfig(): Promise<SyncConfig> {
+  return invoke("get_sync_config");
+}
+
+export async function setSyncConfig(config: SyncConfig): Promise<void> {
+  return invoke("set_sync_config", { config });
+}
+
+export async function getMergeStrategy(): Promise<MergeStrategy> {
+  return invoke("get_merge_strategy");
+}
+
+export async function setMergeStrategy(strategy: MergeStrategy): Promise<void> {
+  return invoke("set_merge_strategy", { strategy });
+}
+
+export async function getSettings(): Promise<Settings> {
+  return invoke("get_settings");
+}
+
+/** Validates and saves both scopes; an empty WebDAV password keeps the stored one. */
+export async function updateSettings(settings: Settings): Promise<Settings> {
+  return invoke("update_settings", { settings });
+}
+
+export async function listLibraries(): Promise<Library[]> {
+  return invoke("list_libraries");
+}
+
+export async function addLibrary(name: string, root: string): Promise<Library> {
+  return invoke("add_library", { name, root });
+}
+
+export async function renameLibrary(id: string, name: string): Promise<void> {
+  return invoke("rename_library", { id, name });
+}
+
+/** Forgets the library; its folder is left untouched. */
+export async function removeLibrary(id: string): Promise<void> {
+  return invoke("remove_library", { id });
+}
+
+/** Closes reader windows, then shows the other library. */
+export async function switchLibrary(id: string): Promise<void> {
+  return invoke("switch_library", { id });
+}
+
+export async function getStorageChange(): Promise<StorageChange | null> {
+  return invoke("get_storage_change");
+}
+
+export async function migrateStorage(
+  mode: MigrationMode,
+  dryRun: boolean,
+): Promise<MigrationReport> {
+  return invoke("migrate_storage", { mode, dryRun });
+}
+
+export async function keepStorageRoot(): Promise<void> {
+  return invoke("keep_storage_root");
+}
+
+export async function getWatcherHealth(): Promise<WatcherHealth> {
+  return invoke("get_watcher_health");
+}
+
+/** Sets up the Books folder watcher again without waiting for its next retry. */
+export async function restartWatcher(): Promise<void> {
+  return invoke("restart_watcher");
+}
+
+export async function gcLibrary(dryRun: boolean): Promise<GcReport> {
+  return invoke("gc_library", { dryRun });
+}
+
+export async function getAutoGc(): Promise<boolean> {
+  return invoke("get_auto_gc");
+}
+
+export async function setAutoGc(enabled: boolean): Promise<void> {
+  return invoke("set_auto_gc", { enabled });
+}
+
+export async function getSyncConflicts(hash?: string): Promise<SyncConflict[]> {
+  return invoke("get_sync_conflicts", { hash: hash ?? null });
+}
+
+export async function resolveSyncConflict(
+  id: string,
+  keep: ConflictDecision,
+): Promise<ReadingProgress> {
+  return invoke("resolve_sync_conflict", { id, keep });
+}
+
+export async function undoSyncConflict(id: string): Promise<ReadingProgress> {
+  return invoke("undo_sync_conflict", { id });
+}
+
 export async function isDebugEnabled(): Promise<boolean> {
   return invoke<boolean>("is_debug_enabled");
 }
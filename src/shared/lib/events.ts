@@ -1,14 +1,53 @@
 import { listen, type UnlistenFn } from "@tauri-apps/api/event";
-import type { ReadingProgress } from "./types";
+import type {
+  AnnotationsChanged,
+  ProgressChanged,
+  Settings,
+  SyncConflict,
+  WatcherHealth,
+} from "./types";
 
 export function onBooksChanged(callback: () => void): Promise<UnlistenFn> {
   return listen("books:changed", callback);
 }
 
 export function onProgressChanged(
-  callback: (progress: ReadingProgress) => void,
+  callback: (progress: ProgressChanged) => void,
 ): Promise<UnlistenFn> {
-  return listen<ReadingProgress>("progress:changed", (event) => {
+  return listen<ProgressChanged>("progress:changed", (event) => {
+    callback(event.payload);
+  });
+}
+
+export function onAnnotationsChanged(
+  callback: (change: AnnotationsChanged) => void,
+): Promise<UnlistenFn> {
+  return listen<AnnotationsChanged>("annotations:changed", (event) => {
+    callback(event.payload);
+  });
+}
+
+/** A sync conflict that waits for the reader to choose (merge strategy "ask"). */
+export function onProgressConflict(
+  callback: (conflict: SyncConflict) => void,
+): Promise<UnlistenFn> {
+  return listen<SyncConflict>("progress:conflict", (event) => {
+    callback(event.payload);
+  });
+}
+
+export function onSettingsChanged(
+  callback: (settings: Settings) => void,
+): Promise<UnlistenFn> {
+  return listen<Settings>("settings:changed", (event) => {
+    callback(event.payload);
+  });
+}
+
+export function onWatcherHealth(
+  callback: (health: WatcherHealth) => void,
+): Promise<UnlistenFn> {
+  return listen<WatcherHealth>("watcher:health", (event) => {
     callback(event.payload);
   });
 }
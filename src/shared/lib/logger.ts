@@ -1,8 +1,10 @@
 import { isDebugEnabled } from "./commands";
+import { onSettingsChanged } from "./events";
 
-let enabled =
+const forced =
   new URLSearchParams(window.location.search).get("debug") === "1" ||
   localStorage.getItem("pdf-debug") === "1";
+let enabled = forced;
 
 export async function initLogger() {
   if (!enabled) {
@@ -15,6 +17,14 @@ export async function initLogger() {
   if (enabled) {
     console.log("[PDF] Debug logging enabled");
   }
+  // Follow the debug setting when it is changed in any window
+  onSettingsChanged(() => {
+    isDebugEnabled()
+      .then((debug) => {
+        enabled = forced || debug;
+      })
+      .catch((e) => console.warn("[PDF] Failed to check debug flag:", e));
+  }).catch((e) => console.warn("[PDF] Failed to listen for settings:", e));
 }
 
 export const logger = {
@@ -1,10 +1,15 @@
+export type DownloadState = "local" | "cloud";
+
 export interface PdfInfo {
   path: string;
   filename: string;
   title: string;
+  /** 0 for books in iCloud Drive this device has never downloaded */
   page_count: number;
+  /** Empty for books in iCloud Drive this device has never downloaded */
   hash: string;
   file_size: number;
+  download_state: DownloadState;
 }
 
 export interface OutlineItem {
@@ -22,7 +27,218 @@ export interface ReadingProgress {
   scroll_position: number;
   last_read: string;
   version: number;
+  /** Device that produced this position; set by the backend on save. */
+  device_id?: string;
+  /** The other device's position sync last settled for this book; local only. */
+  settled_remote?: { device_id: string; last_read: string };
+}
+
+export interface DeviceProgress {
+  device_id: string;
+  /** null for devices missing from the registry (older app versions). */
+  device_name: string | null;
+  is_current_device: boolean;
+  current_page: number;
+  total_pages: number;
+  last_read: string;
+}
+
+export type MergeStrategy = "most_recent" | "furthest_page" | "ask";
+
+export type ConflictDecision = "local" | "remote" | "pending";
+
+export interface SyncConflict {
+  id: string;
+  hash: string;
+  detected_at: string;
+  strategy: MergeStrategy;
+  local: ReadingProgress;
+  remote: ReadingProgress;
+  decision: ConflictDecision;
+  resolved_at: string | null;
+}
+
+export type SyncBackendKind = "icloud" | "folder" | "webdav";
+
+export interface GcReport {
+  dry_run: boolean;
+  books: number;
+  library_complete: boolean;
+  central_checked: boolean;
+  local_progress: string[];
+  central_progress: string[];
+  local_annotations: string[];
+  central_annotations: string[];
+  sessions: string[];
+  hash_cache_entries: number;
+  pdf_cache_entries: number;
+}
+
+export interface SyncConfig {
+  backend: SyncBackendKind;
+  folder: string | null;
+  webdav_url: string | null;
+  webdav_username: string | null;
+  /** Never returned by the backend; leave empty to keep the stored password. */
+  webdav_password: string | null;
+}
+
+export interface LibrarySettings {
+  merge_strategy: MergeStrategy;
+}
+
+/**
+ * The default library's place changed because iCloud Drive was turned on or off. With
+ * `import`, books an earlier version left in `from` are offered to be merged into `to`.
+ */
+export interface StorageChange {
+  from: string;
+  to: string;
+  from_icloud: boolean;
+  to_icloud: boolean;
+  books: number;
+  import: boolean;
+}
+
+export type MigrationMode = "copy" | "move";
+
+export interface MigrationReport {
+  dry_run: boolean;
+  mode: MigrationMode;
+  from: string;
+  to: string;
+  books: string[];
+  already_there: string[];
+  conflicts: string[];
+  placeholders: string[];
+  progress_files: number;
+  bytes: number;
+  failed: string[];
+}
+
+export type WatcherState = "starting" | "watching" | "waiting" | "failed" | "stopped";
+
+/** State of the Books folder watcher ("watcher:health"). */
+export interface WatcherHealth {
+  state: WatcherState;
+  dir: string;
+  since: string;
+  restarts: number;
+  last_error: string | null;
+  retry_in_secs: number | null;
+}
+
+export interface LibraryEntry {
+  id: string;
+  name: string;
+  root: string;
+}
+
+/** A library as listed for switching. The default one has id "default" and no name. */
+export interface Library extends LibraryEntry {
+  active: boolean;
 }
 
+export interface DeviceSettings {
+  sync: SyncConfig;
+  auto_gc: boolean;
+  /** Managed with the library commands; ignored by updateSettings. */
+  libraries: LibraryEntry[];
+  active_library: string | null;
+  watcher_debounce_ms: number;
+  max_reader_windows: number;
+  debug: boolean;
+}
+
+/** Settings shared through the library (`library`) and kept on this Mac (`device`). */
+export interface Settings {
+  library: LibrarySettings;
+  device: DeviceSettings;
+}
+
+export interface Device {
+  id: string;
+  name: string;
+  last_seen: string;
+}
+
+/** Payload of `progress:changed`: where the new position came from. */
+export interface ProgressChanged extends ReadingProgress {
+  source: "local" | "remote";
+}
+
+export type AnnotationKind = "highlight" | "underline" | "note" | "free_text";
+
+/** Eight numbers in PDF `QuadPoints` order: x1 y1 x2 y2 x3 y3 x4 y4 (PDF user space). */
+export type Quad = [number, number, number, number, number, number, number, number];
+
+export interface Annotation {
+  id: string;
+  hash: string;
+  kind: AnnotationKind;
+  page: number;
+  quads: Quad[];
+  text: string;
+  note: string;
+  color: string;
+  created_at: string;
+  updated_at: string;
+  deleted: boolean;
+}
+
+export interface AnnotationsChanged {
+  hash: string;
+  annotations: Annotation[];
+}
+
+export type PdfAnnotationKind =
+  | "highlight"
+  | "underline"
+  | "strike_out"
+  | "text"
+  | "free_text"
+  | "ink";
+
+/** An annotation stored inside the PDF file (Preview, Acrobat, iPad, ...). */
+export interface PdfAnnotation {
+  kind: PdfAnnotationKind;
+  page: number;
+  rect: [number, number, number, number];
+  quad_points: Quad[];
+  ink_list: number[][];
+  color: string | null;
+  author: string | null;
+  contents: string | null;
+  modified: string | null;
+  text: string | null;
+}
+
+export interface DailyStats {
+  date: string;
+  pages: number;
+  minutes: number;
+}
+
+export interface BookStats {
+  hash: string;
+  sessions: number;
+  minutes: number;
+  pages_visited: number;
+  current_page: number | null;
+  total_pages: number | null;
+  estimated_minutes_left: number | null;
+  last_read: string;
+}
+
+export interface ReadingStats {
+  daily: DailyStats[];
+  books: BookStats[];
+  total_minutes: number;
+  current_streak: number;
+  longest_streak: number;
+}
+
+export type ExportFormat = "markdown" | "json" | "csv";
+
 export type SortKey = "title" | "recent" | "size";
 export type SortOrder = "asc" | "desc";
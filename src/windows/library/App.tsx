@@ -2,6 +2,7 @@ import { useBooks } from "./hooks/use-books";
 import { Toolbar } from "./components/Toolbar";
 import { BookGrid } from "./components/BookGrid";
 import { EmptyState } from "./components/EmptyState";
+import { StorageMigrationDialog } from "./components/StorageMigrationDialog";
 import { ScrollArea } from "@shared/components/ui/scroll-area";
 import { Toaster } from "sonner";
 import { useTranslation } from "react-i18next";
@@ -39,6 +40,7 @@ export default function App() {
           <BookGrid books={books} />
         )}
       </ScrollArea>
+      <StorageMigrationDialog />
       <Toaster position="bottom-center" richColors />
     </div>
   );
@@ -31,12 +31,19 @@ import { Label } from "@shared/components/ui/label";
 import { useThumbnail } from "../hooks/use-thumbnail";
 import {
   openReaderWindow,
+  downloadBook,
   revealInFinder,
   deletePdf,
   renamePdf,
+  getProgressRecords,
 } from "@shared/lib/commands";
-import type { PdfInfo, ReadingProgress } from "@shared/lib/types";
-import { FileText } from "lucide-react";
+import type {
+  DeviceProgress,
+  PdfInfo,
+  ReadingProgress,
+} from "@shared/lib/types";
+import { formatTimeAgo, isMac } from "@shared/lib/utils";
+import { CloudDownload, FileText } from "lucide-react";
 import { logger } from "@shared/lib/logger";
 import { toast } from "sonner";
 import { useTranslation } from "react-i18next";
@@ -47,7 +54,7 @@ interface BookCardProps {
 }
 
 export function BookCard({ book }: BookCardProps) {
-  const { t } = useTranslation();
+  const { t, i18n } = useTranslation();
   const cardRef = useRef<HTMLDivElement>(null);
   const [visible, setVisible] = useState(false);
   const [deleteOpen, setDeleteOpen] = useState(false);
@@ -55,6 +62,7 @@ export function BookCard({ book }: BookCardProps) {
   const [renameOpen, setRenameOpen] = useState(false);
   const [newName, setNewName] = useState("");
   const [renameError, setRenameError] = useState("");
+  const [otherDevice, setOtherDevice] = useState<DeviceProgress | null>(null);
   const renderCount = useRef(0);
   renderCount.current++;
   logger.debug(`BookCard render #${renderCount.current}: ${book.title}`);
@@ -76,12 +84,27 @@ export function BookCard({ book }: BookCardProps) {
     return () => observer.disconnect();
   }, []);
 
+  // Only a placeholder is here until iCloud Drive downloads the book
+  const inCloud = book.download_state === "cloud";
+
   const { url: thumbnailUrl, loading } = useThumbnail(
     book.path,
     book.hash,
-    visible,
+    visible && !inCloud,
   );
 
+  const lastRead = book.progress?.last_read;
+  useEffect(() => {
+    if (!visible || !lastRead) return;
+    getProgressRecords(book.hash)
+      .then((records) => {
+        // Only worth showing when another device is where the reading last happened
+        const newest = records[0];
+        setOtherDevice(newest && !newest.is_current_device ? newest : null);
+      })
+      .catch((err) => logger.warn(`Failed to load device progress for ${book.title}:`, err));
+  }, [visible, book.hash, book.title, lastRead]);
+
   const progressPercent = book.progress
     ? Math.round(
         (book.progress.current_page / Math.max(1, book.progress.total_pages)) *
@@ -91,6 +114,11 @@ export function BookCard({ book }: BookCardProps) {
 
   const handleOpen = async () => {
     try {
+      if (inCloud) {
+        await downloadBook(book.path);
+        toast(t("library:downloadStarted", { title: book.title }));
+        return;
+      }
       await openReaderWindow(book.path, book.hash);
     } catch (e) {
       toast.error(translateError(String(e)));
@@ -138,10 +166,18 @@ export function BookCard({ book }: BookCardProps) {
             ref={cardRef}
             className="group flex flex-col cursor-pointer rounded-lg p-2 transition-colors hover:bg-accent"
             onDoubleClick={handleOpen}
-            title={t("library:bookTooltip", { title: book.title, count: book.page_count })}
+            title={
+              inCloud
+                ? t("library:cloudTooltip", { title: book.title })
+                : t("library:bookTooltip", { title: book.title, count: book.page_count })
+            }
           >
             <div className="relative aspect-[3/4] w-full overflow-hidden rounded-md bg-muted mb-2 border border-border">
-              {loading ? (
+              {inCloud && !thumbnailUrl ? (
+                <div className="absolute inset-0 flex items-center justify-center text-muted-foreground">
+                  <CloudDownload className="size-12" />
+                </div>
+              ) : loading ? (
                 <div className="absolute inset-0 flex items-center justify-center">
                   <div className="size-6 animate-spin rounded-full border-2 border-muted-foreground border-t-transparent" />
                 </div>
@@ -149,7 +185,7 @@ export function BookCard({ book }: BookCardProps) {
                 <img
                   src={thumbnailUrl}
                   alt={book.title}
-                  className="h-full w-full object-cover"
+                  className={`h-full w-full object-cover ${inCloud ? "opacity-50" : ""}`}
                   draggable={false}
                 />
               ) : (
@@ -162,7 +198,7 @@ export function BookCard({ book }: BookCardProps) {
               {book.title}
             </p>
             <p className="text-[10px] text-muted-foreground mt-0.5">
-              {t("pageCount", { count: book.page_count })}
+              {inCloud ? t("library:inCloud") : t("pageCount", { count: book.page_count })}
             </p>
             {book.progress && book.progress.current_page > 1 && (
               <div className="mt-1.5 flex items-center gap-1.5">
@@ -172,22 +208,41 @@ export function BookCard({ book }: BookCardProps) {
                 </span>
               </div>
             )}
+            {otherDevice && (
+              <p className="text-[10px] text-muted-foreground mt-0.5 truncate">
+                {t("library:pageOnDevice", {
+                  page: otherDevice.current_page,
+                  device: otherDevice.device_name ?? t("library:otherDevice"),
+                  time: formatTimeAgo(otherDevice.last_read, i18n.language),
+                })}
+              </p>
+            )}
           </div>
         </ContextMenuTrigger>
         <ContextMenuContent>
-          <ContextMenuItem onSelect={handleRenameOpen}>{t("rename")}</ContextMenuItem>
+          {inCloud ? (
+            <ContextMenuItem onSelect={handleOpen}>{t("library:downloadBook")}</ContextMenuItem>
+          ) : (
+            <>
+              <ContextMenuItem onSelect={handleRenameOpen}>{t("rename")}</ContextMenuItem>
+              <ContextMenuItem
+                onSelect={() => {
+                  setDeleteError("");
+                  setDeleteOpen(true);
+                }}
+                className="text-destructive focus:text-destructive"
+              >
+                {t("delete")}
+              </ContextMenuItem>
+            </>
+          )}
+          <ContextMenuSeparator />
           <ContextMenuItem
-            onSelect={() => {
-              setDeleteError("");
-              setDeleteOpen(true);
-            }}
-            className="text-destructive focus:text-destructive"
+            onSelect={() =>
+              revealInFinder(book.path).catch((e) => toast.error(translateError(String(e))))
+            }
           >
-            {t("delete")}
-          </ContextMenuItem>
-          <ContextMenuSeparator />
-          <ContextMenuItem onSelect={() => revealInFinder(book.path)}>
-            {t("library:revealInFinder")}
+            {t(isMac ? "library:revealInFinder" : "library:revealInFolder")}
           </ContextMenuItem>
         </ContextMenuContent>
       </ContextMenu>
@@ -9,7 +9,7 @@ export function BookGrid({ books }: BookGridProps) {
   return (
     <div className="grid grid-cols-[repeat(auto-fill,minmax(160px,1fr))] gap-3 p-4 pt-0">
       {books.map((book) => (
-        <BookCard key={book.hash} book={book} />
+        <BookCard key={book.hash || book.path} book={book} />
       ))}
     </div>
   );
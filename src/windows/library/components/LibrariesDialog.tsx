@@ -0,0 +1,170 @@
+import { useEffect, useState } from "react";
+import {
+  Dialog,
+  DialogContent,
+  DialogDescription,
+  DialogHeader,
+  DialogTitle,
+} from "@shared/components/ui/dialog";
+import { Button } from "@shared/components/ui/button";
+import { Input } from "@shared/components/ui/input";
+import { Label } from "@shared/components/ui/label";
+import {
+  addLibrary,
+  listLibraries,
+  removeLibrary,
+  switchLibrary,
+} from "@shared/lib/commands";
+import type { Library } from "@shared/lib/types";
+import { translateError } from "@shared/lib/error-codes";
+import { open as openDialog } from "@tauri-apps/plugin-dialog";
+import { useTranslation } from "react-i18next";
+import { Check, Loader2, Trash2 } from "lucide-react";
+
+interface LibrariesDialogProps {
+  open: boolean;
+  onOpenChange: (open: boolean) => void;
+}
+
+export function libraryName(library: Library, t: (key: string) => string) {
+  return library.name || t("library:librariesDefault");
+}
+
+export function LibrariesDialog({ open, onOpenChange }: LibrariesDialogProps) {
+  const { t } = useTranslation();
+  const [libraries, setLibraries] = useState<Library[]>([]);
+  const [name, setName] = useState("");
+  const [root, setRoot] = useState("");
+  const [error, setError] = useState("");
+  const [busy, setBusy] = useState(false);
+
+  const reload = () =>
+    listLibraries()
+      .then(setLibraries)
+      .catch((err) => setError(translateError(String(err))));
+
+  useEffect(() => {
+    if (!open) return;
+    setError("");
+    reload();
+  }, [open]);
+
+  const run = async (action: () => Promise<unknown>) => {
+    setBusy(true);
+    setError("");
+    try {
+      await action();
+      return true;
+    } catch (err) {
+      setError(translateError(String(err)));
+      return false;
+    } finally {
+      setBusy(false);
+    }
+  };
+
+  const handleChooseFolder = async () => {
+    const selected = await openDialog({ directory: true });
+    if (typeof selected === "string") {
+      setRoot(selected);
+      if (!name) setName(selected.split("/").filter(Boolean).pop() ?? "");
+    }
+  };
+
+  const handleAdd = async (e: React.FormEvent) => {
+    e.preventDefault();
+    if (await run(() => addLibrary(name, root))) {
+      setName("");
+      setRoot("");
+      reload();
+    }
+  };
+
+  const handleSwitch = async (library: Library) => {
+    if (await run(() => switchLibrary(library.id))) {
+      onOpenChange(false);
+    }
+  };
+
+  const handleRemove = async (library: Library) => {
+    if (await run(() => removeLibrary(library.id))) {
+      reload();
+    }
+  };
+
+  return (
+    <Dialog open={open} onOpenChange={onOpenChange}>
+      <DialogContent>
+        <DialogHeader>
+          <DialogTitle>{t("library:librariesTitle")}</DialogTitle>
+          <DialogDescription>{t("library:librariesDescription")}</DialogDescription>
+        </DialogHeader>
+        <div className="grid gap-2 py-2">
+          {libraries.map((library) => (
+            <div key={library.id} className="flex items-center gap-2">
+              <div className="min-w-0 flex-1">
+                <p className="truncate text-sm font-medium">{libraryName(library, t)}</p>
+                <p className="truncate text-xs text-muted-foreground" title={library.root}>
+                  {library.root}
+                </p>
+              </div>
+              {library.active ? (
+                <span className="flex items-center gap-1 text-xs text-muted-foreground">
+                  <Check className="size-3" />
+                  {t("library:librariesActive")}
+                </span>
+              ) : (
+                <>
+                  <Button
+                    size="sm"
+                    variant="outline"
+                    disabled={busy}
+                    onClick={() => handleSwitch(library)}
+                  >
+                    {t("library:librariesSwitch")}
+                  </Button>
+                  {library.id !== "default" && (
+                    <Button
+                      size="icon"
+                      variant="ghost"
+                      className="size-8"
+                      disabled={busy}
+                      onClick={() => handleRemove(library)}
+                      title={t("library:librariesRemove")}
+                    >
+                      <Trash2 className="size-4" />
+                    </Button>
+                  )}
+                </>
+              )}
+            </div>
+          ))}
+        </div>
+        <form onSubmit={handleAdd} className="grid gap-2 border-t pt-4">
+          <Label htmlFor="library-name">{t("library:librariesAddName")}</Label>
+          <Input
+            id="library-name"
+            value={name}
+            onChange={(e) => setName(e.target.value)}
+          />
+          <Label htmlFor="library-root">{t("library:librariesAddFolder")}</Label>
+          <div className="flex gap-2">
+            <Input
+              id="library-root"
+              value={root}
+              onChange={(e) => setRoot(e.target.value)}
+            />
+            <Button type="button" variant="outline" onClick={handleChooseFolder}>
+              {t("library:syncChoose")}
+            </Button>
+          </div>
+          {error && <p className="text-sm text-destructive">{error}</p>}
+          <Button type="submit" disabled={busy || !name.trim() || !root.trim()}>
+            {busy && <Loader2 className="size-4 animate-spin" />}
+            {t("library:librariesAdd")}
+          </Button>
+        </form>
+      </DialogContent>
+    </Dialog>
+  );
+}
@@ -0,0 +1,140 @@
+import { useEffect, useState } from "react";
+import {
+  Dialog,
+  DialogContent,
+  DialogDescription,
+  DialogFooter,
+  DialogHeader,
+  DialogTitle,
+} from "@shared/components/ui/dialog";
+import { Button } from "@shared/components/ui/button";
+import {
+  getStorageChange,
+  keepStorageRoot,
+  migrateStorage,
+} from "@shared/lib/commands";
+import type { MigrationMode, MigrationReport, StorageChange } from "@shared/lib/types";
+import { translateError } from "@shared/lib/error-codes";
+import { useTranslation } from "react-i18next";
+import { Loader2 } from "lucide-react";
+import { toast } from "sonner";
+
+/**
+ * Offered at startup when iCloud Drive was turned on or off since the library was last used:
+ * copy or move the library to its new place, or keep it where it is. Also offers books an
+ * earlier version left in the local library for import.
+ */
+export function StorageMigrationDialog() {
+  const { t } = useTranslation();
+  const [change, setChange] = useState<StorageChange | null>(null);
+  const [plan, setPlan] = useState<MigrationReport | null>(null);
+  const [error, setError] = useState("");
+  const [busy, setBusy] = useState(false);
+
+  useEffect(() => {
+    getStorageChange()
+      .then((found) => {
+        setChange(found);
+        // The report is the same for both modes; only what happens afterwards differs
+        if (found) return migrateStorage("copy", true).then(setPlan);
+      })
+      .catch((err) => setError(translateError(String(err))));
+  }, []);
+
+  const close = () => setChange(null);
+
+  const handleMigrate = async (mode: MigrationMode) => {
+    setBusy(true);
+    setError("");
+    try {
+      const report = await migrateStorage(mode, false);
+      close();
+      if (report.failed.length > 0) {
+        toast.error(t("library:storageFailed", { count: report.failed.length }));
+      } else if (change?.import) {
+        toast.success(t("library:storageImported", { count: report.books.length }));
+      } else {
+        toast.success(t("library:storageDone", { count: report.books.length }));
+      }
+    } catch (err) {
+      setError(translateError(String(err)));
+    } finally {
+      setBusy(false);
+    }
+  };
+
+  const handleKeep = async () => {
+    try {
+      await keepStorageRoot();
+      close();
+    } catch (err) {
+      setError(translateError(String(err)));
+    }
+  };
+
+  if (!change) return null;
+
+  return (
+    <Dialog open onOpenChange={(open) => !open && close()}>
+      <DialogContent>
+        <DialogHeader>
+          <DialogTitle>
+            {change.import ? t("library:storageImportTitle") : t("library:storageTitle")}
+          </DialogTitle>
+          <DialogDescription>
+            {change.import
+              ? t("library:storageImport", { from: change.from, count: change.books })
+              : change.to_icloud
+                ? t("library:storageToIcloud", { from: change.from })
+                : t("library:storageFromIcloud", { to: change.to })}
+          </DialogDescription>
+        </DialogHeader>
+        <div className="grid gap-2 py-2 text-sm">
+          {plan ? (
+            <>
+              <p>
+                {t("library:storagePlan", {
+                  books: plan.books.length,
+                  size: (plan.bytes / 1024 / 1024).toFixed(1),
+                  progress: plan.progress_files,
+                })}
+              </p>
+              {plan.already_there.length > 0 && (
+                <p className="text-muted-foreground">
+                  {t("library:storageAlreadyThere", { count: plan.already_there.length })}
+                </p>
+              )}
+              {plan.conflicts.length > 0 && (
+                <p className="text-muted-foreground" title={plan.conflicts.join("\n")}>
+                  {t("library:storageConflicts", { count: plan.conflicts.length })}
+                </p>
+              )}
+              {plan.placeholders.length > 0 && (
+                <p className="text-muted-foreground">
+                  {t("library:storagePlaceholders", { count: plan.placeholders.length })}
+                </p>
+              )}
+            </>
+          ) : (
+            !error && <Loader2 className="size-4 animate-spin" />
+          )}
+          {error && <p className="text-destructive">{error}</p>}
+        </div>
+        <DialogFooter>
+          <Button variant="outline" onClick={handleKeep} disabled={busy}>
+            {change.import ? t("library:storageImportSkip") : t("library:storageKeep")}
+          </Button>
+          {!change.from_icloud && (
+            <Button variant="outline" onClick={() => handleMigrate("move")} disabled={busy || !plan}>
+              {t("library:storageMove")}
+            </Button>
+          )}
+          <Button onClick={() => handleMigrate("copy")} disabled={busy || !plan}>
+            {busy && <Loader2 className="size-4 animate-spin" />}
+            {t("library:storageCopy")}
+          </Button>
+        </DialogFooter>
+      </DialogContent>
+    </Dialog>
+  );
+}
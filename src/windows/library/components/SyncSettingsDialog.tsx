@@ -0,0 +1,162 @@
+import { useEffect, useState } from "react";
+import {
+  Dialog,
+  DialogContent,
+  DialogDescription,
+  DialogFooter,
+  DialogHeader,
+  DialogTitle,
+} from "@shared/components/ui/dialog";
+import { Button } from "@shared/components/ui/button";
+import { Input } from "@shared/components/ui/input";
+import { Label } from "@shared/components/ui/label";
+import { getSyncConfig, setSyncConfig } from "@shared/lib/commands";
+import type { SyncBackendKind, SyncConfig } from "@shared/lib/types";
+import { translateError } from "@shared/lib/error-codes";
+import { open as openDialog } from "@tauri-apps/plugin-dialog";
+import { useTranslation } from "react-i18next";
+import { Loader2 } from "lucide-react";
+
+interface SyncSettingsDialogProps {
+  open: boolean;
+  onOpenChange: (open: boolean) => void;
+}
+
+const backendLabelKeys: Record<SyncBackendKind, string> = {
+  icloud: "library:syncIcloud",
+  folder: "library:syncFolder",
+  webdav: "library:syncWebdav",
+};
+
+const emptyConfig: SyncConfig = {
+  backend: "icloud",
+  folder: null,
+  webdav_url: null,
+  webdav_username: null,
+  webdav_password: null,
+};
+
+export function SyncSettingsDialog({ open, onOpenChange }: SyncSettingsDialogProps) {
+  const { t } = useTranslation();
+  const [config, setConfig] = useState<SyncConfig>(emptyConfig);
+  const [error, setError] = useState("");
+  const [saving, setSaving] = useState(false);
+
+  useEffect(() => {
+    if (!open) return;
+    setError("");
+    getSyncConfig()
+      .then(setConfig)
+      .catch((err) => setError(translateError(String(err))));
+  }, [open]);
+
+  const update = (patch: Partial<SyncConfig>) => {
+    setConfig((c) => ({ ...c, ...patch }));
+    setError("");
+  };
+
+  const handleChooseFolder = async () => {
+    const selected = await openDialog({ directory: true });
+    if (typeof selected === "string") update({ folder: selected });
+  };
+
+  const handleSubmit = async (e: React.FormEvent) => {
+    e.preventDefault();
+    setSaving(true);
+    try {
+      await setSyncConfig(config);
+      onOpenChange(false);
+    } catch (err) {
+      setError(translateError(String(err)));
+    } finally {
+      setSaving(false);
+    }
+  };
+
+  return (
+    <Dialog open={open} onOpenChange={onOpenChange}>
+      <DialogContent>
+        <form onSubmit={handleSubmit}>
+          <DialogHeader>
+            <DialogTitle>{t("library:syncTitle")}</DialogTitle>
+            <DialogDescription>{t("library:syncDescription")}</DialogDescription>
+          </DialogHeader>
+          <div className="grid gap-4 py-4">
+            <div className="flex gap-2">
+              {(Object.keys(backendLabelKeys) as SyncBackendKind[]).map((kind) => (
+                <Button
+                  key={kind}
+                  type="button"
+                  size="sm"
+                  variant={config.backend === kind ? "default" : "outline"}
+                  onClick={() => update({ backend: kind })}
+                >
+                  {t(backendLabelKeys[kind])}
+                </Button>
+              ))}
+            </div>
+
+            {config.backend === "folder" && (
+              <div className="grid gap-2">
+                <Label htmlFor="sync-folder">{t("library:syncFolderLabel")}</Label>
+                <div className="flex gap-2">
+                  <Input
+                    id="sync-folder"
+                    value={config.folder ?? ""}
+                    onChange={(e) => update({ folder: e.target.value })}
+                  />
+                  <Button type="button" variant="outline" onClick={handleChooseFolder}>
+                    {t("library:syncChoose")}
+                  </Button>
+                </div>
+              </div>
+            )}
+
+            {config.backend === "webdav" && (
+              <>
+                <div className="grid gap-2">
+                  <Label htmlFor="webdav-url">{t("library:webdavUrl")}</Label>
+                  <Input
+                    id="webdav-url"
+                    placeholder="https://example.com/remote.php/dav/files/me/PDFReader"
+                    value={config.webdav_url ?? ""}
+                    onChange={(e) => update({ webdav_url: e.target.value })}
+                  />
+                </div>
+                <div className="grid gap-2">
+                  <Label htmlFor="webdav-username">{t("library:webdavUsername")}</Label>
+                  <Input
+                    id="webdav-username"
+                    value={config.webdav_username ?? ""}
+                    onChange={(e) => update({ webdav_username: e.target.value })}
+                  />
+                </div>
+                <div className="grid gap-2">
+                  <Label htmlFor="webdav-password">{t("library:webdavPassword")}</Label>
+                  <Input
+                    id="webdav-password"
+                    type="password"
+                    placeholder={t("library:webdavPasswordKeep")}
+                    value={config.webdav_password ?? ""}
+                    onChange={(e) => update({ webdav_password: e.target.value })}
+                  />
+                </div>
+              </>
+            )}
+
+            {error && <p className="text-sm text-destructive">{error}</p>}
+          </div>
+          <DialogFooter>
+            <Button type="button" variant="outline" onClick={() => onOpenChange(false)}>
+              {t("cancel")}
+            </Button>
+            <Button type="submit" disabled={saving}>
+              {saving && <Loader2 className="size-4 animate-spin" />}
+              {t("confirm")}
+            </Button>
+          </DialogFooter>
+        </form>
+      </DialogContent>
+    </Dialog>
+  );
+}
@@ -1,14 +1,39 @@
-import { useState } from "react";
-import { Search, Import, ArrowUpDown, Sun, Moon, Loader2 } from "lucide-react";
+import { useState, useEffect } from "react";
+import {
+  Search,
+  Import,
+  ArrowUpDown,
+  Sun,
+  Moon,
+  Loader2,
+  RefreshCw,
+  Cloud,
+  Eraser,
+  Library as LibraryIcon,
+  TriangleAlert,
+} from "lucide-react";
 import { Button } from "@shared/components/ui/button";
 import { Input } from "@shared/components/ui/input";
-import { importPdf, getBooksDirectory, revealInFinder } from "@shared/lib/commands";
+import {
+  importPdf,
+  getBooksDirectory,
+  revealInFinder,
+  getMergeStrategy,
+  setMergeStrategy,
+  gcLibrary,
+  listLibraries,
+  getWatcherHealth,
+  restartWatcher,
+} from "@shared/lib/commands";
+import { onSettingsChanged, onWatcherHealth } from "@shared/lib/events";
 import { useTheme } from "@shared/hooks/use-theme";
 import { open } from "@tauri-apps/plugin-dialog";
-import type { SortKey } from "@shared/lib/types";
+import type { Library, MergeStrategy, SortKey, WatcherHealth } from "@shared/lib/types";
 import { useTranslation } from "react-i18next";
 import { translateError } from "@shared/lib/error-codes";
 import { toast } from "sonner";
+import { SyncSettingsDialog } from "./SyncSettingsDialog";
+import { LibrariesDialog, libraryName } from "./LibrariesDialog";
 
 interface ToolbarProps {
   search: string;
@@ -26,6 +51,14 @@ const sortLabelKeys: Record<SortKey, string> = {
 
 const sortCycle: SortKey[] = ["title", "recent", "size"];
 
+const mergeLabelKeys: Record<MergeStrategy, string> = {
+  most_recent: "library:mergeMostRecent",
+  furthest_page: "library:mergeFurthestPage",
+  ask: "library:mergeAsk",
+};
+
+const mergeCycle: MergeStrategy[] = ["most_recent", "furthest_page", "ask"];
+
 export function Toolbar({
   search,
   onSearchChange,
@@ -36,6 +69,45 @@ export function Toolbar({
   const { t } = useTranslation();
   const { resolvedTheme, setTheme } = useTheme();
   const [importing, setImporting] = useState(false);
+  const [syncOpen, setSyncOpen] = useState(false);
+  const [cleaning, setCleaning] = useState(false);
+  const [librariesOpen, setLibrariesOpen] = useState(false);
+  const [activeLibrary, setActiveLibrary] = useState<Library | null>(null);
+  const [watcherHealth, setWatcherHealth] = useState<WatcherHealth | null>(null);
+  const [mergeStrategy, setMergeStrategyState] =
+    useState<MergeStrategy>("most_recent");
+
+  useEffect(() => {
+    const loadSettings = () => {
+      getMergeStrategy()
+        .then(setMergeStrategyState)
+        .catch(() => {
+          // Keep the default label; the backend falls back to it too
+        });
+      listLibraries()
+        .then((libraries) => setActiveLibrary(libraries.find((l) => l.active) ?? null))
+        .catch(() => setActiveLibrary(null));
+    };
+    loadSettings();
+    // Merge strategy and active library change with the library
+    const unlistenPromise = onSettingsChanged(loadSettings);
+    return () => {
+      unlistenPromise.then((unlisten) => unlisten());
+    };
+  }, []);
+
+  useEffect(() => {
+    getWatcherHealth()
+      .then(setWatcherHealth)
+      .catch(() => setWatcherHealth(null));
+    const unlistenPromise = onWatcherHealth(setWatcherHealth);
+    return () => {
+      unlistenPromise.then((unlisten) => unlisten());
+    };
+  }, []);
+
+  const watcherDown =
+    watcherHealth?.state === "waiting" || watcherHealth?.state === "failed";
 
   const handleImport = async () => {
     if (importing) return;
@@ -66,9 +138,57 @@ export function Toolbar({
     onSortChange(sortCycle[(idx + 1) % sortCycle.length]);
   };
 
+  const handleCycleMerge = async () => {
+    const idx = mergeCycle.indexOf(mergeStrategy);
+    const next = mergeCycle[(idx + 1) % mergeCycle.length];
+    try {
+      await setMergeStrategy(next);
+      setMergeStrategyState(next);
+    } catch (err) {
+      toast.error(translateError(String(err)));
+    }
+  };
+
+  const handleCleanup = async () => {
+    if (cleaning) return;
+    setCleaning(true);
+    try {
+      const report = await gcLibrary(true);
+      const progress = new Set([
+        ...report.local_progress,
+        ...report.central_progress,
+        ...report.local_annotations,
+        ...report.central_annotations,
+        ...report.sessions,
+      ]).size;
+      const cache = report.hash_cache_entries + report.pdf_cache_entries;
+      if (progress + cache === 0) {
+        toast(t("library:cleanupNothing"));
+        return;
+      }
+      toast(t("library:cleanupFound", { progress, cache }), {
+        action: {
+          label: t("library:cleanupRemove"),
+          onClick: () => {
+            gcLibrary(false)
+              .then(() => toast.success(t("library:cleanupDone")))
+              .catch((err) => toast.error(translateError(String(err))));
+          },
+        },
+      });
+    } catch (err) {
+      toast.error(translateError(String(err)));
+    } finally {
+      setCleaning(false);
+    }
+  };
+
   const handleOpenFolder = async () => {
-    const dir = await getBooksDirectory();
-    revealInFinder(dir);
+    try {
+      await revealInFinder(await getBooksDirectory());
+    } catch (err) {
+      toast.error(translateError(String(err)));
+    }
   };
 
   const toggleTheme = () => {
@@ -88,6 +208,17 @@ export function Toolbar({
           className="pl-8 h-8"
         />
       </div>
+      <Button
+        variant="ghost"
+        size="sm"
+        onClick={() => setLibrariesOpen(true)}
+        title={t("library:librariesTitle")}
+      >
+        <LibraryIcon className="size-4" />
+        {activeLibrary && (
+          <span className="text-xs max-w-32 truncate">{libraryName(activeLibrary, t)}</span>
+        )}
+      </Button>
       <Button
         variant="ghost"
         size="sm"
@@ -97,7 +228,51 @@ export function Toolbar({
         <ArrowUpDown className="size-4" />
         <span className="text-xs">{sortLabel}</span>
       </Button>
+      <Button
+        variant="ghost"
+        size="sm"
+        onClick={handleCycleMerge}
+        title={t("library:mergeLabel", { strategy: t(mergeLabelKeys[mergeStrategy]) })}
+      >
+        <RefreshCw className="size-4" />
+        <span className="text-xs">{t(mergeLabelKeys[mergeStrategy])}</span>
+      </Button>
+      <Button
+        variant="ghost"
+        size="icon"
+        onClick={() => setSyncOpen(true)}
+        className="size-8"
+        title={t("library:syncTitle")}
+      >
+        <Cloud className="size-4" />
+      </Button>
+      <Button
+        variant="ghost"
+        size="icon"
+        onClick={handleCleanup}
+        disabled={cleaning}
+        className="size-8"
+        title={t("library:cleanupTitle")}
+      >
+        {cleaning ? <Loader2 className="size-4 animate-spin" /> : <Eraser className="size-4" />}
+      </Button>
       <div className="flex-1" />
+      {watcherDown && watcherHealth && (
+        <Button
+          variant="ghost"
+          size="icon"
+          onClick={() => restartWatcher()}
+          className="size-8 text-amber-500"
+          title={t(
+            watcherHealth.state === "waiting"
+              ? "library:watcherWaiting"
+              : "library:watcherFailed",
+            { dir: watcherHealth.dir },
+          )}
+        >
+          <TriangleAlert className="size-4" />
+        </Button>
+      )}
       <Button variant="ghost" size="icon" onClick={toggleTheme} className="size-8">
         {resolvedTheme === "dark" ? (
           <Moon className="size-4" />
@@ -112,6 +287,8 @@ export function Toolbar({
         {importing ? <Loader2 className="size-4 animate-spin" /> : <Import className="size-4" />}
         {importing ? t("importing") : t("import")}
       </Button>
+      <SyncSettingsDialog open={syncOpen} onOpenChange={setSyncOpen} />
+      <LibrariesDialog open={librariesOpen} onOpenChange={setLibrariesOpen} />
     </div>
   );
 }
@@ -8,6 +8,9 @@ interface BookWithProgress extends PdfInfo {
   progress?: ReadingProgress;
 }
 
+// Books not downloaded from iCloud Drive yet may have no ID; they change state when they arrive
+const bookKey = (book: PdfInfo) => `${book.hash || book.path}:${book.download_state}`;
+
 export function useBooks() {
   const [books, setBooks] = useState<BookWithProgress[]>([]);
   const [loading, setLoading] = useState(true);
@@ -32,9 +35,10 @@ export function useBooks() {
       const progressStart = performance.now();
       const BATCH_SIZE = 5;
       const allProgress = new Map<string, ReadingProgress>();
-      for (let i = 0; i < scanned.length; i += BATCH_SIZE) {
+      const identified = scanned.filter((book) => book.hash);
+      for (let i = 0; i < identified.length; i += BATCH_SIZE) {
         if (id !== refreshIdRef.current) return;
-        const batch = scanned.slice(i, i + BATCH_SIZE);
+        const batch = identified.slice(i, i + BATCH_SIZE);
         const results = await Promise.all(
           batch.map(async (book) => {
             const progress = await loadProgress(book.hash).catch(() => null);
@@ -49,7 +53,7 @@ export function useBooks() {
       logger.perf(`loadProgress: ${(performance.now() - progressStart).toFixed(0)}ms, ${allProgress.size}/${scanned.length} have progress`);
       setBooks((prev) =>
         prev.map((book) => {
-          const progress = allProgress.get(book.hash);
+          const progress = book.hash ? allProgress.get(book.hash) : undefined;
           return progress ? { ...book, progress } : book;
         }),
       );
@@ -72,7 +76,7 @@ export function useBooks() {
   }, [refresh]);
 
   useEffect(() => {
-    const unlistenPromise = onProgressChanged((progress) => {
+    const unlistenPromise = onProgressChanged(({ source: _, ...progress }) => {
       setBooks((prev) =>
         prev.map((book) =>
           book.hash === progress.hash ? { ...book, progress } : book,
@@ -96,25 +100,25 @@ export function useBooks() {
         let hashesForSync: string[] = [];
 
         setBooks((prev) => {
-          const currentHashes = new Set(prev.map((b) => b.hash));
+          const currentKeys = new Set(prev.map(bookKey));
           const hasChange =
-            currentHashes.size !== scanned.length ||
-            scanned.some((b) => !currentHashes.has(b.hash));
+            currentKeys.size !== scanned.length ||
+            scanned.some((b) => !currentKeys.has(bookKey(b)));
 
           if (!hasChange) {
-            hashesForSync = prev.map((b) => b.hash);
+            hashesForSync = prev.map((b) => b.hash).filter(Boolean);
             return prev;
           }
 
-          logger.debug(`Books changed: ${currentHashes.size} -> ${scanned.length}`);
+          logger.debug(`Books changed: ${currentKeys.size} -> ${scanned.length}`);
           const progressMap = new Map(
-            prev.map((b) => [b.hash, b.progress]),
+            prev.filter((b) => b.hash).map((b) => [b.hash, b.progress]),
           );
           const next = scanned.map((book) => ({
             ...book,
-            progress: progressMap.get(book.hash),
+            progress: book.hash ? progressMap.get(book.hash) : undefined,
           }));
-          hashesForSync = next.map((b) => b.hash);
+          hashesForSync = next.map((b) => b.hash).filter(Boolean);
           return next;
         });
 
@@ -7,6 +7,7 @@ import { ReaderToolbar } from "./components/ReaderToolbar";
 import { Sidebar } from "./components/Sidebar";
 import type { ReadingProgress } from "@shared/lib/types";
 import { useTranslation } from "react-i18next";
+import { Toaster } from "sonner";
 
 export default function App() {
   const { t } = useTranslation("reader");
@@ -130,6 +131,7 @@ export default function App() {
               currentPage={currentPage}
               onPageSelect={handleSidebarPageSelect}
               filePath={filePath}
+              hash={hash}
             />
           </div>
         )}
@@ -147,6 +149,7 @@ export default function App() {
           />
         </div>
       </div>
+      <Toaster position="bottom-center" />
     </div>
   );
 }
@@ -0,0 +1,122 @@
+import { useState, useEffect } from "react";
+import type { Annotation, PdfAnnotation } from "@shared/lib/types";
+import { getPdfAnnotations, loadAnnotations } from "@shared/lib/commands";
+import { onAnnotationsChanged } from "@shared/lib/events";
+import { cn } from "@shared/lib/utils";
+import { useTranslation } from "react-i18next";
+
+interface AnnotationListProps {
+  filePath: string;
+  hash: string;
+  currentPage: number;
+  onPageSelect: (page: number) => void;
+}
+
+interface ListEntry {
+  key: string;
+  page: number;
+  color: string | null;
+  text: string;
+  note: string;
+  embedded: boolean;
+}
+
+function fromApp(a: Annotation): ListEntry {
+  return {
+    key: a.id,
+    page: a.page,
+    color: a.color,
+    text: a.text,
+    note: a.note,
+    embedded: false,
+  };
+}
+
+function fromPdf(a: PdfAnnotation, i: number): ListEntry {
+  return {
+    key: `pdf-${i}`,
+    page: a.page,
+    color: a.color,
+    text: a.text ?? "",
+    note: a.contents ?? "",
+    embedded: true,
+  };
+}
+
+export function AnnotationList({
+  filePath,
+  hash,
+  currentPage,
+  onPageSelect,
+}: AnnotationListProps) {
+  const { t } = useTranslation("reader");
+  const [appEntries, setAppEntries] = useState<ListEntry[]>([]);
+  const [pdfEntries, setPdfEntries] = useState<ListEntry[]>([]);
+
+  useEffect(() => {
+    getPdfAnnotations(filePath)
+      .then((list) => setPdfEntries(list.map(fromPdf)))
+      .catch((err) => console.error("Failed to load PDF annotations:", err));
+  }, [filePath]);
+
+  useEffect(() => {
+    loadAnnotations(hash)
+      .then((list) => setAppEntries(list.map(fromApp)))
+      .catch((err) => console.error("Failed to load annotations:", err));
+
+    const unlisten = onAnnotationsChanged((change) => {
+      if (change.hash === hash) {
+        setAppEntries(change.annotations.map(fromApp));
+      }
+    });
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, [hash]);
+
+  const entries = [...appEntries, ...pdfEntries].sort(
+    (a, b) => a.page - b.page,
+  );
+
+  if (entries.length === 0) {
+    return (
+      <div className="p-4 text-xs text-muted-foreground text-center">
+        {t("noAnnotations")}
+      </div>
+    );
+  }
+
+  return (
+    <div className="p-1">
+      {entries.map((entry) => (
+        <button
+          key={entry.key}
+          className={cn(
+            "flex gap-2 w-full text-left px-2 py-1.5 rounded text-xs hover:bg-accent transition-colors",
+            entry.page === currentPage && "bg-accent",
+          )}
+          onClick={() => onPageSelect(entry.page)}
+        >
+          <span
+            className="w-1 shrink-0 rounded-full"
+            style={{ backgroundColor: entry.color ?? "var(--muted-foreground)" }}
+          />
+          <span className="flex-1 min-w-0">
+            {entry.text && (
+              <span className="block line-clamp-3">{entry.text}</span>
+            )}
+            {entry.note && (
+              <span className="block text-muted-foreground line-clamp-2">
+                {entry.note}
+              </span>
+            )}
+            <span className="block text-[10px] text-muted-foreground">
+              {t("annotationPage", { page: entry.page })}
+              {entry.embedded && ` · ${t("annotationEmbedded")}`}
+            </span>
+          </span>
+        </button>
+      ))}
+    </div>
+  );
+}
@@ -4,10 +4,11 @@ import type { OutlineItem } from "@shared/lib/types";
 import { getPdfOutline } from "@shared/lib/commands";
 import { ThumbnailList } from "./ThumbnailList";
 import { OutlineTree } from "./OutlineTree";
+import { AnnotationList } from "./AnnotationList";
 import { cn } from "@shared/lib/utils";
 import { useTranslation } from "react-i18next";
 
-type SidebarTab = "thumbnails" | "outline";
+type SidebarTab = "thumbnails" | "outline" | "annotations";
 
 interface SidebarProps {
   pdf: PDFDocumentProxy;
@@ -15,6 +16,7 @@ interface SidebarProps {
   currentPage: number;
   onPageSelect: (page: number) => void;
   filePath: string;
+  hash: string;
 }
 
 export function Sidebar({
@@ -23,6 +25,7 @@ export function Sidebar({
   currentPage,
   onPageSelect,
   filePath,
+  hash,
 }: SidebarProps) {
   const { t } = useTranslation("reader");
   const [tab, setTab] = useState<SidebarTab>("thumbnails");
@@ -59,6 +62,17 @@ export function Sidebar({
         >
           {t("outline")}
         </button>
+        <button
+          className={cn(
+            "flex-1 py-1.5 text-xs text-center transition-colors",
+            tab === "annotations"
+              ? "text-foreground border-b-2 border-primary"
+              : "text-muted-foreground hover:text-foreground",
+          )}
+          onClick={() => setTab("annotations")}
+        >
+          {t("annotations")}
+        </button>
       </div>
 
       <div className="flex-1 overflow-hidden">
@@ -69,7 +83,7 @@ export function Sidebar({
             currentPage={currentPage}
             onPageSelect={onPageSelect}
           />
-        ) : (
+        ) : tab === "outline" ? (
           <div className="h-full overflow-auto">
             <OutlineTree
               items={outline}
@@ -77,6 +91,15 @@ export function Sidebar({
               currentPage={currentPage}
             />
           </div>
+        ) : (
+          <div className="h-full overflow-auto">
+            <AnnotationList
+              filePath={filePath}
+              hash={hash}
+              currentPage={currentPage}
+              onPageSelect={onPageSelect}
+            />
+          </div>
         )}
       </div>
     </div>
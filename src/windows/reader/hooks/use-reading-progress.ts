@@ -1,14 +1,28 @@
 import { useEffect, useRef, useCallback } from "react";
 import {
   loadProgress,
+  recordReadingActivity,
+  resolveSyncConflict,
   saveProgress as saveProgressCmd,
   syncProgress,
 } from "@shared/lib/commands";
+import { onProgressChanged, onProgressConflict } from "@shared/lib/events";
 import type { ReadingProgress } from "@shared/lib/types";
 import { getCurrentWindow } from "@tauri-apps/api/window";
+import { toast } from "sonner";
+import { useTranslation } from "react-i18next";
+import { formatTimeAgo } from "@shared/lib/utils";
+
+// Reading activity heartbeat: at most one per interval while the user interacts
+const ACTIVITY_INTERVAL_MS = 30000;
 
 function dedupKey(p: ReadingProgress): string {
-  const { last_read: _, scroll_position: _sp, ...rest } = { ...p, version: 0 };
+  const {
+    last_read: _,
+    scroll_position: _sp,
+    device_id: _d,
+    ...rest
+  } = { ...p, version: 0 };
   return JSON.stringify(rest);
 }
 
@@ -31,6 +45,7 @@ export function useReadingProgress({
   scrollPosition,
   onRestore,
 }: UseReadingProgressOptions) {
+  const { t, i18n } = useTranslation("reader");
   const saveTimeoutRef = useRef<ReturnType<typeof setTimeout> | null>(null);
   const lastSavedRef = useRef<string>("");
   const lastRestoreRef = useRef(0);
@@ -112,12 +127,50 @@ export function useReadingProgress({
     return () => clearInterval(interval);
   }, [hash]);
 
-  // Sync with central (iCloud) every 2s
+  // Report interaction so the backend can track active reading time between saves
+  const currentPageRef = useRef(currentPage);
+  currentPageRef.current = currentPage;
+
+  useEffect(() => {
+    if (!hash) return;
+    let lastSent = 0;
+    const onActivity = () => {
+      const now = Date.now();
+      if (now - lastSent < ACTIVITY_INTERVAL_MS) return;
+      lastSent = now;
+      recordReadingActivity(hash, currentPageRef.current).catch(() => {
+        // Session tracking is best-effort
+      });
+    };
+    const events = ["wheel", "keydown", "pointerdown", "pointermove"] as const;
+    for (const e of events) window.addEventListener(e, onActivity, { passive: true });
+    return () => {
+      for (const e of events) window.removeEventListener(e, onActivity);
+    };
+  }, [hash]);
+
+  // Sync with central every 10s; changes from other devices are normally pushed by the
+  // backend as `progress:changed` events, this is the fallback
   const onRestoreRef = useRef(onRestore);
   useEffect(() => {
     onRestoreRef.current = onRestore;
   }, [onRestore]);
 
+  useEffect(() => {
+    if (!hash) return;
+    const unlisten = onProgressChanged((change) => {
+      if (change.hash !== hash || change.source !== "remote") return;
+      const { source: _, ...pulled } = change;
+      console.warn(`[SYNC] remote: hash=${hash.slice(0, 8)}, page=${pulled.current_page}`);
+      lastRestoreRef.current = Date.now();
+      onRestoreRef.current(pulled);
+      lastSavedRef.current = dedupKey(pulled);
+    });
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, [hash]);
+
   useEffect(() => {
     if (!hash) return;
     const interval = setInterval(async () => {
@@ -132,10 +185,45 @@ export function useReadingProgress({
       } catch {
         // Sync failure is non-critical, retry next interval
       }
-    }, 2000);
+    }, 10000);
     return () => clearInterval(interval);
   }, [hash]);
 
+  // Merge strategy "ask": let the reader choose between this device and the other one
+  useEffect(() => {
+    if (!hash) return;
+    const unlisten = onProgressConflict((conflict) => {
+      if (conflict.hash !== hash) return;
+      const keep = async (side: "local" | "remote") => {
+        try {
+          const kept = await resolveSyncConflict(conflict.id, side);
+          if (side === "remote") {
+            lastRestoreRef.current = Date.now();
+            onRestoreRef.current(kept);
+          }
+          lastSavedRef.current = dedupKey(kept);
+        } catch (err) {
+          console.error("Failed to resolve sync conflict:", err);
+        }
+      };
+      toast(
+        t("conflictTitle", {
+          page: conflict.remote.current_page,
+          time: formatTimeAgo(conflict.remote.last_read, i18n.language),
+        }),
+        {
+          id: conflict.id,
+          duration: Infinity,
+          action: { label: t("conflictJump"), onClick: () => keep("remote") },
+          cancel: { label: t("conflictStay"), onClick: () => keep("local") },
+        },
+      );
+    });
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, [hash, t, i18n.language]);
+
   // Save and sync on window close
   useEffect(() => {
     if (!hash) return;